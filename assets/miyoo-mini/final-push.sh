@@ -0,0 +1,26 @@
+#!/bin/sh
+# Run by Onion before suspending or shutting down, so changed saves reach the
+# server before the device goes to sleep.
+
+set -euo pipefail
+
+SYNCER_FILE="$(find /mnt/SDCARD/App -type f -name 'syncer-daemon')"
+SYNCER_ROOT="$(dirname "$SYNCER_FILE")"
+
+if [ "$SYNCER_ROOT" == "" ]; then
+    echo "ERROR: Could not determine run root." >&2;
+    exit -1;
+fi
+
+exec 1>>"$SYNCER_ROOT/daemon-wrapper.out"
+exec 2>>"$SYNCER_ROOT/daemon-wrapper.err"
+
+cd "$SYNCER_ROOT"
+
+if [ ! -f "./syncer-daemon" ]; then
+    echo "ERROR: Daemon not found."
+    exit -1
+fi
+export NO_COLOR=1
+# Waits for the push to finish; never hold up the suspend over a failed one.
+./syncer-daemon final-push || echo "WARNING: Final push failed." >&2
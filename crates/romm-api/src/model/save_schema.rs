@@ -13,6 +13,8 @@ pub struct SaveSchema {
     pub file_size_bytes: i64,
     pub full_path: String,
     pub id: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub md5_hash: Option<String>,
     pub rom_id: i64,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub screenshot: Option<String>,
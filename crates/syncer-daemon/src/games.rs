@@ -0,0 +1,267 @@
+//! Helpers for noticing games being launched & exited by the platform's
+//! frontend, such as Onion OS writing each game's launch command to
+//! `/tmp/cmd_to_run.sh`.
+
+use std::ffi::OsStr;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use procfs::{process, ProcError};
+use tokio::fs;
+
+/// How long we give a launched game to show up in the process list before
+/// counting it as exited.
+pub const GAME_LAUNCH_GRACE: Duration = Duration::from_secs(10);
+
+/// A change in which game is being played.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GameEvent {
+    Launched { rom: PathBuf, at: Instant },
+    Exited { rom: PathBuf },
+}
+
+/// Follows the game being played across successive reads of the launch
+/// command.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct GameTracker {
+    /// The ROM in the launch command the last time we looked.
+    seen: Option<PathBuf>,
+    /// The ROM we're treating as being played, and when it was launched.
+    playing: Option<(PathBuf, Instant)>,
+}
+
+impl GameTracker {
+    /// Takes in the ROM the launch command held at `now`, returning the games
+    /// launched & exited since the last update.
+    ///
+    /// Once a game has had [`GAME_LAUNCH_GRACE`] to start, it counts as exited
+    /// as soon as `is_running` says it isn't, even if the frontend left the
+    /// launch command behind.
+    pub async fn update<F, Fut>(
+        &mut self,
+        launched: Option<PathBuf>,
+        now: Instant,
+        is_running: F,
+    ) -> Vec<GameEvent>
+    where
+        F: FnOnce(PathBuf) -> Fut,
+        Fut: Future<Output = bool>,
+    {
+        let mut events = Vec::new();
+        if launched != self.seen {
+            self.seen.clone_from(&launched);
+            if let Some(rom) = launched {
+                if let Some((previous, _)) = self.playing.take() {
+                    events.push(GameEvent::Exited { rom: previous });
+                }
+                events.push(GameEvent::Launched {
+                    rom: rom.clone(),
+                    at: now,
+                });
+                self.playing = Some((rom, now));
+            }
+        }
+        if let Some((rom, launched_at)) = &self.playing {
+            let exited = if self.seen.as_ref() != Some(rom) {
+                true
+            } else if now.saturating_duration_since(*launched_at) < GAME_LAUNCH_GRACE {
+                false
+            } else {
+                !is_running(rom.clone()).await
+            };
+            if exited {
+                events.push(GameEvent::Exited { rom: rom.clone() });
+                self.playing = None;
+            }
+        }
+        events
+    }
+}
+
+/// Reads the ROM being played from the launch command at `path`, if a game
+/// has been launched.
+pub async fn launched_rom(path: &Path) -> io::Result<Option<PathBuf>> {
+    match fs::read_to_string(path).await {
+        Ok(command) => Ok(rom_from_command(&command)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Works out the ROM a launch command starts.
+///
+/// Frontends pass the ROM as the last argument, quoted since ROM names are
+/// full of spaces, eg
+/// `"/mnt/SDCARD/Emu/GBA/launch.sh" "/mnt/SDCARD/Roms/GBA/Pokemon Emerald.gba"`.
+fn rom_from_command(command: &str) -> Option<PathBuf> {
+    let last_arg = command
+        .split('"')
+        .skip(1)
+        .step_by(2)
+        .filter(|arg| !arg.is_empty())
+        .last()?;
+    Some(PathBuf::from(last_arg))
+}
+
+/// Checks whether any process still has `rom` on its command line, ie the
+/// emulator or the script that launched it.
+///
+/// Only good for telling when a game has exited; the launch script shows up
+/// here before the emulator has even started.
+pub async fn rom_is_running(rom: &Path) -> Result<bool, ProcError> {
+    let Some(name) = rom.file_name().map(OsStr::to_owned) else {
+        return Ok(false);
+    };
+    let task = move || {
+        let procs = process::all_processes()?;
+        let skip_not_found = procs.filter(|p| !matches!(p, Err(ProcError::NotFound(_))));
+        for proc in skip_not_found {
+            // Processes can exit while we're looking at them.
+            let cmdline = match proc?.cmdline() {
+                Ok(cmdline) => cmdline,
+                Err(ProcError::NotFound(_)) => {
+                    continue;
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+            };
+            // Compare by name, since frontends like to build paths with `..`.
+            if cmdline
+                .iter()
+                .any(|arg| Path::new(arg).file_name() == Some(&name))
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    };
+    tokio::task::spawn_blocking(task).await.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn rt() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    async fn running(_: PathBuf) -> bool {
+        true
+    }
+
+    async fn not_running(_: PathBuf) -> bool {
+        false
+    }
+
+    #[test]
+    fn test_launch_and_exit() {
+        rt().block_on(async {
+            let tetris = PathBuf::from("/mnt/SDCARD/Roms/GB/Tetris.gb");
+            let start = Instant::now();
+            let mut tracker = GameTracker::default();
+            assert_eq!(tracker.update(None, start, not_running).await, []);
+            assert_eq!(
+                tracker
+                    .update(Some(tetris.clone()), start, not_running)
+                    .await,
+                [GameEvent::Launched {
+                    rom: tetris.clone(),
+                    at: start
+                }]
+            );
+            // The game gets a while to show up in the process list.
+            let checks = AtomicUsize::new(0);
+            let counted = |_| async {
+                checks.fetch_add(1, Ordering::SeqCst);
+                false
+            };
+            let soon = start + Duration::from_secs(1);
+            assert_eq!(
+                tracker.update(Some(tetris.clone()), soon, counted).await,
+                []
+            );
+            assert_eq!(checks.load(Ordering::SeqCst), 0);
+
+            let later = start + Duration::from_secs(5);
+            assert_eq!(
+                tracker.update(None, later, running).await,
+                [GameEvent::Exited {
+                    rom: tetris.clone()
+                }]
+            );
+            assert_eq!(tracker.update(None, later, running).await, []);
+        });
+    }
+
+    #[test]
+    fn test_switch_games() {
+        rt().block_on(async {
+            let tetris = PathBuf::from("/mnt/SDCARD/Roms/GB/Tetris.gb");
+            let emerald = PathBuf::from("/mnt/SDCARD/Roms/GBA/Pokemon Emerald.gba");
+            let start = Instant::now();
+            let mut tracker = GameTracker::default();
+            tracker.update(Some(tetris.clone()), start, running).await;
+            let later = start + Duration::from_secs(60);
+            assert_eq!(
+                tracker.update(Some(emerald.clone()), later, running).await,
+                [
+                    GameEvent::Exited { rom: tetris },
+                    GameEvent::Launched {
+                        rom: emerald,
+                        at: later
+                    }
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_exit_with_command_left_behind() {
+        rt().block_on(async {
+            let tetris = PathBuf::from("/mnt/SDCARD/Roms/GB/Tetris.gb");
+            let start = Instant::now();
+            let mut tracker = GameTracker::default();
+            tracker.update(Some(tetris.clone()), start, running).await;
+            let later = start + GAME_LAUNCH_GRACE;
+            assert_eq!(
+                tracker.update(Some(tetris.clone()), later, running).await,
+                []
+            );
+            assert_eq!(
+                tracker
+                    .update(Some(tetris.clone()), later, not_running)
+                    .await,
+                [GameEvent::Exited {
+                    rom: tetris.clone()
+                }]
+            );
+            // The stale command isn't a new launch.
+            assert_eq!(tracker.update(Some(tetris), later, running).await, []);
+        });
+    }
+
+    #[test]
+    fn test_rom_from_command() {
+        let retroarch = r#"LD_PRELOAD=/mnt/SDCARD/miyoo/lib/libpadsp.so "/mnt/SDCARD/Emu/GBA/../../RetroArch/retroarch" -v -L "/mnt/SDCARD/Emu/GBA/../../RetroArch/.retroarch/cores/gpsp_libretro.so" "/mnt/SDCARD/Roms/GBA/Pokemon Emerald.gba""#;
+        assert_eq!(
+            rom_from_command(retroarch),
+            Some(PathBuf::from("/mnt/SDCARD/Roms/GBA/Pokemon Emerald.gba"))
+        );
+        let launcher = "\"/mnt/SDCARD/Emu/GB/launch.sh\" \"/mnt/SDCARD/Roms/GB/Tetris.gb\"\n";
+        assert_eq!(
+            rom_from_command(launcher),
+            Some(PathBuf::from("/mnt/SDCARD/Roms/GB/Tetris.gb"))
+        );
+        assert_eq!(rom_from_command("./app.sh"), None);
+        assert_eq!(rom_from_command(""), None);
+    }
+}
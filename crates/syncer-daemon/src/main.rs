@@ -1,33 +1,51 @@
-use std::{env, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    env, fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use chrono::Local;
 use futures::{future::Either, pin_mut, FutureExt};
 use notify::{RecursiveMode, Watcher};
 use socketproto::spawn_command_listen_thread;
 use tokio::{
-    sync::{mpsc, watch},
+    sync::{mpsc, oneshot, watch},
     task::JoinHandle,
 };
 use tracing::{debug, error, info, level_filters::LevelFilter, warn};
 use tracing_subscriber::{util::SubscriberInitExt, EnvFilter, FmtSubscriber};
 
 use syncer_model::{
-    commands::{DaemonCommand, DaemonCommandBody},
-    config::Config,
+    commands::{
+        DaemonCommand, DaemonCommandBody, DaemonResponse, DaemonResponseBody, DaemonStatus,
+        SyncStatus, Transfer,
+    },
+    config::{Config, PowerState, RemoteKind, Severity, DROPIN_DIR_NAME},
     platforms::Platform,
 };
 
-mod database;
+use syncer_engine::{
+    database::SaveMetaDatabase,
+    deviceclient::{ProfileSaves, ScopedSaves},
+    directory::DirectoryRemote,
+    openfiles::OpenFiles,
+    rommclient::RommClient,
+    stores::{LocalStore, RemoteStore, StateStore},
+    syncing::{run_sync, SyncCancelled, SyncReport},
+    utils::CancelToken,
+    webdav::WebDavRemote,
+};
+
+mod games;
+mod network;
+mod power;
+mod resume;
 mod socketproto;
-use database::SaveMetaDatabase;
-mod md5hash;
-mod rommclient;
-use rommclient::RommClient;
-mod deviceclient;
-mod model;
-use model::SaveMeta;
-mod syncing;
-use syncing::run_sync;
-use utils::{ConfigurableSleep, ConfigurableSleepSetter, EventTrigger};
+use games::{GameEvent, GameTracker, GAME_LAUNCH_GRACE};
+use resume::ResumeDetector;
+use utils::{ConfigurableSleep, EventTrigger};
 mod utils;
 
 fn main() {
@@ -36,6 +54,12 @@ fn main() {
         println!("{}", env!("CARGO_PKG_VERSION"));
         return;
     }
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [_, "config", "check"] => std::process::exit(check_config()),
+        [_, "config", "explain"] => std::process::exit(explain_config()),
+        [_, "final-push"] => std::process::exit(final_push()),
+        _ => {}
+    }
     init_logger();
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -47,6 +71,83 @@ fn main() {
     debug!("Shutting down.");
 }
 
+/// Runs every config check, printing what was found; returns the exit code
+/// for the process.
+fn check_config() -> i32 {
+    let diagnostics = block_on(Config::check_current_platform());
+    for diagnostic in &diagnostics {
+        println!("{diagnostic}");
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    if diagnostics.is_empty() {
+        println!("Config OK");
+    } else {
+        println!("{errors} error(s), {warnings} warning(s)");
+    }
+    if errors > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Prints the effective value of every setting & where it was set; returns
+/// the exit code for the process.
+fn explain_config() -> i32 {
+    match block_on(Config::explain_current_platform()) {
+        Ok(explanations) => {
+            for explanation in explanations {
+                println!("{explanation}");
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("Error loading config: {e:#}");
+            1
+        }
+    }
+}
+
+/// Asks the running daemon for a final push & waits for it to finish; returns
+/// the exit code for the process.
+///
+/// Meant to be run by the platform's suspend & shutdown scripts.
+fn final_push() -> i32 {
+    let cmd = DaemonCommand::new(DaemonCommandBody::FinalPush);
+    match block_on(socketproto::query_daemon(&cmd, FINAL_PUSH_TIMEOUT)) {
+        Ok(DaemonResponse {
+            body: DaemonResponseBody::Status(status),
+            ..
+        }) => {
+            println!("{:?}", status.sync);
+            match status.error {
+                Some(error) => {
+                    eprintln!("Final push failed: {error}");
+                    1
+                }
+                None => 0,
+            }
+        }
+        Err(e) => {
+            eprintln!("Error asking the daemon for a final push: {e}");
+            1
+        }
+    }
+}
+
+/// Runs a one-off command's future on a single-threaded runtime.
+fn block_on<F: std::future::Future>(fut: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(fut)
+}
+
 fn init_logger() {
     let trace_env = EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
@@ -76,14 +177,11 @@ async fn async_main() {
         .await
         .unwrap();
     info!("Starting with config: {cfg:?}");
-    let cl = RommClient::new(
-        cfg.romm.url.clone().unwrap(),
-        cfg.romm.api_key.clone().unwrap(),
-    );
 
-    let state = Arc::new(DaemonState::new());
+    let state = Arc::new(DaemonState::new(cfg).await);
     let _command_waiter = spawn_command_listen_thread(Arc::clone(&state)).unwrap();
     wait_for_death().await.unwrap();
+    state.shutdown().await;
     if let Err(e) = tokio::fs::remove_file(Platform::get().socket_path()).await {
         warn!("Error cleaning up daemon socket: {e:?}");
     }
@@ -91,70 +189,389 @@ async fn async_main() {
 }
 
 pub struct DaemonState {
-    /// The setter for configuring the time the `_sync_loop_thread` should sleep between syncs.
-    sync_loop_sleep: ConfigurableSleepSetter,
-    /// The thread responsible for triggering a sync every `poll_interval` time.
+    /// The config currently in effect.
+    ///
+    /// Only ever replaced as a whole, and only by a config that loaded &
+    /// validated, so every sync sees one consistent config.
+    config: watch::Sender<Arc<Config>>,
+    /// The trigger for reloading the config on the `_config_reload_thread`.
+    config_reload: EventTrigger,
+    /// The background task that reloads the config whenever triggered, either
+    /// by the `_config_watch_thread` or from a call to
+    /// [`DaemonCommandBody::ReloadConfig`].
+    _config_reload_thread: JoinHandle<()>,
+    /// The background task that triggers a config reload whenever one of the
+    /// config files is modified.
+    _config_watch_thread: JoinHandle<()>,
+
+    /// The thread responsible for triggering syncs on the `system.schedule`,
+    /// or every `poll_interval` time.
     _sync_loop_thread: JoinHandle<()>,
 
-    /// The trigger for starting a sync on the `_sync_actor_thread`.
+    /// The trigger for starting a sync on the `_sync_actor_thread`, unless
+    /// the `system.schedule` rules it out.
     sync_trigger: EventTrigger,
+    /// Queues syncs on the `_sync_actor_thread` ahead of any regular ones.
+    priority_sync: mpsc::Sender<PrioritySync>,
+    /// Stops the sync currently running on the `_sync_actor_thread`, if any.
+    sync_cancel: CancelToken,
+    /// The daemon's current status, as published by the `_sync_actor_thread`.
+    ///
+    /// Also the source of truth for whether syncing is paused.
+    status: watch::Sender<DaemonStatus>,
     /// The background task that performs full syncs whenever triggered, either
     /// by the [`_sync_loop_thread`] or from a call to
     /// [`DaemonCommand::DoSync`].
     _sync_actor_thread: JoinHandle<()>,
 
-    /// The list of paths to listen to for changes
-    fs_watch_paths: watch::Sender<Vec<PathBuf>>,
-
     /// The background task that triggers a sync whenever a relevant path gets modified (if enabled)
     _fs_watch_thread: JoinHandle<()>,
+
+    /// The background task that triggers a sync when the network comes back
+    /// after a sync was skipped for being offline.
+    _network_watch_thread: JoinHandle<()>,
+
+    /// The background task that triggers a sync once the device is charging
+    /// after a sync was deferred for a low battery.
+    _power_watch_thread: JoinHandle<()>,
+
+    /// The background task that triggers a sync once the saves a sync left
+    /// alone for being open in another process are closed.
+    _in_use_watch_thread: JoinHandle<()>,
+
+    /// The background task that pulls the latest saves whenever the device
+    /// wakes up from suspend.
+    _resume_watch_thread: JoinHandle<()>,
+
+    /// The background task that pulls a game's saves as it's launched, and
+    /// pushes them once it exits.
+    _game_watch_thread: JoinHandle<()>,
 }
 
 impl DaemonState {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
-        let (sync_trigger, _sync_actor_thread) = build_sync_actor_thread();
-        let (sync_loop_sleep, _sync_loop_thread) =
-            build_sync_loop_thread(Duration::MAX, sync_trigger.clone());
+    pub async fn new(config: Config) -> Self {
+        // Loaded before the sync actor starts so that a daemon restarted while
+        // paused never gets to start a sync.
+        let paused = match load_paused(&config).await {
+            Ok(paused) => {
+                if paused {
+                    info!("Syncing was paused before the daemon restarted; staying paused.");
+                }
+                paused
+            }
+            Err(e) => {
+                error!("Error loading paused state: {e:?}");
+                false
+            }
+        };
+        let config = watch::Sender::new(Arc::new(config));
+        let sync_cancel = CancelToken::new();
+        let (sync_trigger, priority_sync, status, _sync_actor_thread) =
+            build_sync_actor_thread(config.subscribe(), sync_cancel.clone(), paused);
+        let _sync_loop_thread = build_sync_loop_thread(config.subscribe(), sync_trigger.clone());
         let (fs_watch_paths, _fs_watch_thread) = build_fs_watch_thread(sync_trigger.clone());
-        let retvl = Self {
-            sync_loop_sleep,
+        let _network_watch_thread =
+            build_network_watch_thread(sync_trigger.clone(), status.subscribe());
+        let _resume_watch_thread =
+            build_resume_watch_thread(priority_sync.clone(), status.subscribe());
+        let _game_watch_thread = build_game_watch_thread(
+            config.subscribe(),
+            priority_sync.clone(),
+            sync_cancel.clone(),
+            status.subscribe(),
+        );
+        let _power_watch_thread =
+            build_power_watch_thread(config.subscribe(), sync_trigger.clone(), status.subscribe());
+        let _in_use_watch_thread =
+            build_in_use_watch_thread(sync_trigger.clone(), status.subscribe());
+        apply_config(&config.borrow(), &fs_watch_paths);
+        let (config_reload, _config_reload_thread) =
+            build_config_reload_thread(config.clone(), status.clone(), fs_watch_paths);
+        let _config_watch_thread = build_config_watch_thread(config_reload.clone());
+        Self {
+            config,
+            config_reload,
+            _config_reload_thread,
+            _config_watch_thread,
             _sync_loop_thread,
             sync_trigger,
+            priority_sync,
+            sync_cancel,
+            status,
             _sync_actor_thread,
-            fs_watch_paths,
             _fs_watch_thread,
-        };
-        retvl.run_command(&DaemonCommand::new(DaemonCommandBody::ReloadConfig));
-        retvl
+            _network_watch_thread,
+            _power_watch_thread,
+            _in_use_watch_thread,
+            _resume_watch_thread,
+            _game_watch_thread,
+        }
+    }
+    /// The config currently in effect.
+    fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.borrow())
     }
-    pub fn run_command(&self, cmd: &DaemonCommand) {
+
+    /// Runs a command received from a UI, returning the reply to send back if
+    /// the command expects one.
+    pub async fn run_command(&self, cmd: &DaemonCommand) -> Option<DaemonResponse> {
         match cmd.body {
             DaemonCommandBody::DoSync => {
-                self.sync_trigger.trigger();
+                // Sent ahead of the regular syncs so the `system.schedule`
+                // doesn't hold it back.
+                self.priority_sync
+                    .send(PrioritySync::requested())
+                    .await
+                    .ok();
+                None
+            }
+            DaemonCommandBody::GetStatus => {
+                let status = self.status.borrow().clone();
+                Some(DaemonResponse::new(DaemonResponseBody::Status(status)))
+            }
+            DaemonCommandBody::CancelSync => {
+                if self.status.borrow().sync == SyncStatus::Syncing {
+                    info!("Cancelling the in-progress sync.");
+                    self.sync_cancel.cancel();
+                }
+                None
+            }
+            DaemonCommandBody::Pause => {
+                self.set_paused(true);
+                None
+            }
+            DaemonCommandBody::Resume => {
+                self.set_paused(false);
+                None
             }
             DaemonCommandBody::ReloadConfig => {
-                let sync_loop_sleep = self.sync_loop_sleep.clone();
-                let fs_watch_paths = self.fs_watch_paths.clone();
-                tokio::task::spawn(async move {
-                    let cfg = match load_config().await {
-                        Ok(cfg) => cfg,
-                        Err(e) => {
-                            error!("Error reloading config: {e:?}");
-                            return;
-                        }
-                    };
-                    sync_loop_sleep.set(*cfg.system.poll_interval);
-                    let new_watch_paths = if cfg.system.sync_on_file_change {
-                        cfg.save_roots().collect()
-                    } else {
-                        Vec::new()
-                    };
-                    fs_watch_paths.send_replace(new_watch_paths);
-                });
+                self.config_reload.trigger();
+                None
+            }
+            DaemonCommandBody::FinalPush => {
+                self.run_final_push().await;
+                let status = self.status.borrow().clone();
+                Some(DaemonResponse::new(DaemonResponseBody::Status(status)))
             }
         }
     }
+
+    /// Stops any sync in progress and pushes changed saves ahead of any other
+    /// sync, waiting for the push to finish.
+    ///
+    /// Does nothing if `system.power.final_push` is off.
+    async fn run_final_push(&self) {
+        if !self.config().system.power.final_push() {
+            debug!("system.power.final_push is off; skipping the final push.");
+            return;
+        }
+        info!("Pushing changed saves before the device powers down.");
+        if self.status.borrow().sync == SyncStatus::Syncing {
+            self.sync_cancel.cancel();
+        }
+        let (done, finished) = oneshot::channel();
+        let request = PrioritySync::final_push(done);
+        if self.priority_sync.send(request).await.is_err() {
+            return;
+        }
+        // The push goes ahead even while paused or on low battery, so the
+        // request is only dropped without a reply if the sync actor is gone.
+        finished.await.ok();
+    }
+
+    fn set_paused(&self, paused: bool) {
+        info!("{} syncing.", if paused { "Pausing" } else { "Resuming" });
+        self.status.send_modify(|status| status.paused = paused);
+        if paused {
+            self.sync_cancel.cancel();
+        } else {
+            self.sync_trigger.trigger();
+        }
+        let config = self.config();
+        tokio::task::spawn(async move {
+            if let Err(e) = persist_paused(&config, paused).await {
+                error!("Error saving paused state: {e:?}");
+            }
+        });
+    }
+
+    /// Runs a final push, then stops any in-progress sync before the daemon
+    /// exits.
+    ///
+    /// Saves that are mid-transfer are rolled back rather than left half
+    /// written; see [`run_sync`] for details.
+    pub async fn shutdown(&self) {
+        if tokio::time::timeout(FINAL_PUSH_TIMEOUT, self.run_final_push())
+            .await
+            .is_err()
+        {
+            warn!("The final push did not finish within {FINAL_PUSH_TIMEOUT:?}; stopping it.");
+        }
+        if self.status.borrow().sync != SyncStatus::Syncing {
+            return;
+        }
+        info!("Cancelling the in-progress sync before exiting.");
+        self.sync_cancel.cancel();
+        let mut status = self.status.subscribe();
+        let finished = status.wait_for(|status| status.sync != SyncStatus::Syncing);
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, finished)
+            .await
+            .is_err()
+        {
+            warn!("Sync did not stop within {SHUTDOWN_TIMEOUT:?}; exiting anyway.");
+        }
+    }
+}
+
+/// How long we give the final push before exiting after a SIGTERM.
+const FINAL_PUSH_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// How long we give an in-progress sync to wind down after a SIGTERM.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Applies the parts of `cfg` that the background tasks read, rather than
+/// reading the config on each use.
+fn apply_config(cfg: &Config, fs_watch_paths: &watch::Sender<Vec<PathBuf>>) {
+    let new_watch_paths = if cfg.system.sync_on_file_change {
+        cfg.save_roots().collect()
+    } else {
+        Vec::new()
+    };
+    fs_watch_paths.send_if_modified(|paths| {
+        let modified = *paths != new_watch_paths;
+        *paths = new_watch_paths;
+        modified
+    });
+}
+
+fn build_config_reload_thread(
+    config: watch::Sender<Arc<Config>>,
+    status: watch::Sender<DaemonStatus>,
+    fs_watch_paths: watch::Sender<Vec<PathBuf>>,
+) -> (EventTrigger, JoinHandle<()>) {
+    let (snd, mut trigger) = EventTrigger::new();
+    let thread = tokio::spawn(async move {
+        loop {
+            trigger.wait_and_reset().await;
+            let cfg = match load_config().await {
+                Ok(cfg) => cfg,
+                Err(e) => {
+                    error!("Error reloading config; keeping the previous one: {e:?}");
+                    status.send_modify(|status| status.config_error = Some(format!("{e:#}")));
+                    continue;
+                }
+            };
+            status.send_if_modified(|status| status.config_error.take().is_some());
+            if **config.borrow() == cfg {
+                debug!("Config is unchanged.");
+                continue;
+            }
+            info!("Config changed; now using: {cfg:?}");
+            apply_config(&cfg, &fs_watch_paths);
+            config.send_replace(Arc::new(cfg));
+        }
+    });
+    (snd, thread)
+}
+
+/// How long to wait for more changes after a config file is modified, so an
+/// editor writing a file in several steps only causes one reload.
+const CONFIG_WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// The directories to watch for config changes, and whether an event for a
+/// given path is relevant.
+///
+/// Directories are watched rather than the files themselves so that files
+/// replaced by a rename, or created after the daemon started, are noticed.
+struct ConfigWatchPaths {
+    dirs: Vec<PathBuf>,
+    files: HashSet<PathBuf>,
+}
+
+impl ConfigWatchPaths {
+    fn current_platform() -> Self {
+        let mut dirs = Vec::new();
+        let mut files = HashSet::new();
+        for file in Platform::get().config_input_paths() {
+            let (Some(name), Some(parent)) = (file.file_name(), file.parent()) else {
+                continue;
+            };
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            // Events are reported with absolute paths.
+            let Ok(parent) = parent.canonicalize() else {
+                debug!("Not watching {file:?}, since its directory doesn't exist.");
+                continue;
+            };
+            let dropin_dir = parent.join(DROPIN_DIR_NAME);
+            files.insert(parent.join(name));
+            files.insert(dropin_dir.clone());
+            dirs.push(parent);
+            if dropin_dir.is_dir() {
+                dirs.push(dropin_dir);
+            }
+        }
+        Self { dirs, files }
+    }
+
+    fn is_relevant(&self, path: &Path) -> bool {
+        self.files.contains(path)
+            || path.parent().is_some_and(|parent| {
+                parent.file_name() == Some(DROPIN_DIR_NAME.as_ref())
+                    && self.dirs.iter().any(|dir| dir == parent)
+            })
+    }
+}
+
+fn build_config_watch_thread(config_reload: EventTrigger) -> JoinHandle<()> {
+    tokio::task::spawn(async move {
+        loop {
+            // Rebuild the watcher after every change, so a `conf.d` directory
+            // created since the last one gets watched too.
+            let paths = ConfigWatchPaths::current_platform();
+            let (evt_snd, mut evt_rcv) = mpsc::unbounded_channel();
+            let watcher = notify::recommended_watcher(move |evt| {
+                evt_snd.send(evt).ok();
+            });
+            let mut watcher = match watcher {
+                Ok(w) => w,
+                Err(e) => {
+                    error!("Error starting config watcher thread: {e:?}");
+                    return;
+                }
+            };
+            for dir in &paths.dirs {
+                if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    error!("Error watching config directory {dir:?}: {e:?}");
+                }
+            }
+
+            loop {
+                match evt_rcv.recv().await {
+                    None => return,
+                    Some(Err(e)) => {
+                        error!("Error in config watcher thread: {e:?}");
+                    }
+                    Some(Ok(evt)) => {
+                        if evt.kind.is_access() || !evt.paths.iter().any(|p| paths.is_relevant(p)) {
+                            continue;
+                        }
+                        debug!(
+                            "Got FS notification {:?} for config paths {:?}.",
+                            evt.kind, evt.paths
+                        );
+                        break;
+                    }
+                }
+            }
+            tokio::time::sleep(CONFIG_WATCH_DEBOUNCE).await;
+            info!("Config files changed; reloading.");
+            config_reload.trigger();
+        }
+    })
 }
 
 fn build_fs_watch_thread(
@@ -212,51 +629,826 @@ fn build_fs_watch_thread(
     (snd, tokio::task::spawn(task))
 }
 
+/// The longest we wait before re-checking the clock for the next of the
+/// `system.schedule.at` times.
+const AT_RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often we check whether the device has been plugged in or unplugged,
+/// so the matching poll interval takes effect.
+const POWER_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The device's current power supply, assuming it's plugged in if that can't
+/// be checked.
+async fn current_power_state() -> PowerState {
+    power::power_state().await.unwrap_or_else(|e| {
+        warn!("Error checking power supply state: {e:?}");
+        PowerState::EXTERNAL
+    })
+}
+
 fn build_sync_loop_thread(
-    initial_duration: Duration,
+    config: watch::Receiver<Arc<Config>>,
     sync_trigger: EventTrigger,
-) -> (ConfigurableSleepSetter, JoinHandle<()>) {
-    let (mut rcv, snd) = ConfigurableSleep::new(initial_duration);
-    let task = async move {
+) -> JoinHandle<()> {
+    let (mut rcv, snd) = ConfigurableSleep::new(Duration::MAX);
+    let mut interval_config = config.clone();
+    // Keeps the interval in line with the config & the current power source;
+    // the sleep in progress picks up any change without starting over.
+    let update_interval = async move {
         loop {
-            rcv.sleep().await;
-            sync_trigger.trigger();
+            let power = current_power_state().await;
+            let interval = interval_config
+                .borrow_and_update()
+                .system
+                .poll_interval_on(power.source);
+            snd.set(interval);
+            tokio::select! {
+                changed = interval_config.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                }
+                () = tokio::time::sleep(POWER_POLL_INTERVAL) => {}
+            }
         }
     };
-    let thread = tokio::spawn(task);
-    (snd, thread)
+    let poll = {
+        let sync_trigger = sync_trigger.clone();
+        async move {
+            loop {
+                rcv.sleep().await;
+                sync_trigger.trigger();
+            }
+        }
+    };
+    let mut at_config = config;
+    let at_times = async move {
+        loop {
+            let next = at_config
+                .borrow_and_update()
+                .system
+                .schedule
+                .next_at(&Local::now());
+            let Some(next) = next else {
+                if at_config.changed().await.is_err() {
+                    return;
+                }
+                continue;
+            };
+            // Timers stop while the device is suspended but the clock doesn't,
+            // so wait in steps rather than for the whole time at once.
+            loop {
+                let until_next = (next - Local::now()).to_std().unwrap_or_default();
+                if until_next.is_zero() {
+                    sync_trigger.trigger();
+                    break;
+                }
+                tokio::select! {
+                    changed = at_config.changed() => {
+                        if changed.is_err() {
+                            return;
+                        }
+                        break;
+                    }
+                    () = tokio::time::sleep(until_next.min(AT_RECHECK_INTERVAL)) => {}
+                }
+            }
+        }
+    };
+    tokio::spawn(async move {
+        tokio::join!(update_interval, poll, at_times);
+    })
+}
+
+fn build_power_watch_thread(
+    config: watch::Receiver<Arc<Config>>,
+    sync_trigger: EventTrigger,
+    status: watch::Receiver<DaemonStatus>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(POWER_POLL_INTERVAL).await;
+            if status.borrow().sync != SyncStatus::LowBattery {
+                continue;
+            }
+            let power = current_power_state().await;
+            if config.borrow().system.power.low_battery(power).is_none() {
+                info!("Battery is no longer low; triggering the deferred sync.");
+                sync_trigger.trigger();
+            }
+        }
+    })
 }
-fn build_sync_actor_thread() -> (EventTrigger, JoinHandle<()>) {
+
+/// How often we check whether the saves the last sync left open have been
+/// closed.
+const IN_USE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Syncs the saves the last sync skipped for being open in another process,
+/// once they're all closed.
+///
+/// Only polls while the last sync left saves behind.
+fn build_in_use_watch_thread(
+    sync_trigger: EventTrigger,
+    mut status: watch::Receiver<DaemonStatus>,
+) -> JoinHandle<()> {
+    let deferred =
+        |status: &DaemonStatus| status.sync == SyncStatus::Synced && !status.in_use.is_empty();
+    tokio::spawn(async move {
+        loop {
+            if status.wait_for(deferred).await.is_err() {
+                break;
+            }
+            tokio::time::sleep(IN_USE_POLL_INTERVAL).await;
+            // Another sync may have run while we slept.
+            let in_use = {
+                let status = status.borrow();
+                if !deferred(&status) {
+                    continue;
+                }
+                status.in_use.clone()
+            };
+            let open_files = match OpenFiles::snapshot().await {
+                Ok(open_files) => open_files,
+                Err(e) => {
+                    warn!("Error listing open files: {e:?}");
+                    continue;
+                }
+            };
+            let mut still_open = false;
+            for path in &in_use {
+                still_open |= open_files.contains(path).await;
+            }
+            if !still_open {
+                info!("The saves left open last sync are closed; triggering a sync.");
+                sync_trigger.trigger();
+            }
+        }
+    })
+}
+
+/// How often we check whether the device was suspended in between.
+const RESUME_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long the pull after waking keeps waiting for the network to come back
+/// before it's given up on.
+const RESUME_OFFLINE_WAIT: Duration = Duration::from_secs(2 * 60);
+
+/// How often the pull after waking checks whether the network is back.
+const RESUME_RETRY_INTERVAL: Duration = Duration::from_secs(3);
+
+fn build_resume_watch_thread(
+    priority_sync: mpsc::Sender<PrioritySync>,
+    status: watch::Receiver<DaemonStatus>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut detector = ResumeDetector::new().await;
+        loop {
+            tokio::time::sleep(RESUME_POLL_INTERVAL).await;
+            let Some(asleep) = detector.check().await else {
+                continue;
+            };
+            info!(
+                "Woke up after about {}s suspended; pulling the latest saves.",
+                asleep.as_secs()
+            );
+            if !pull_after_resume(&priority_sync, &status).await {
+                break;
+            }
+        }
+    })
+}
+
+/// Runs the pull after waking, retrying it while Wi-Fi reconnects for up to
+/// [`RESUME_OFFLINE_WAIT`]; returns false once the sync actor is gone.
+async fn pull_after_resume(
+    priority_sync: &mpsc::Sender<PrioritySync>,
+    status: &watch::Receiver<DaemonStatus>,
+) -> bool {
+    let give_up_at = Instant::now() + RESUME_OFFLINE_WAIT;
+    loop {
+        // Wi-Fi usually takes a few seconds to reconnect after waking.
+        while !network::has_network_interface().await.unwrap_or(true) {
+            if Instant::now() >= give_up_at {
+                info!(
+                    "Network still down {}s after waking; leaving the pull to the next sync.",
+                    RESUME_OFFLINE_WAIT.as_secs()
+                );
+                return true;
+            }
+            tokio::time::sleep(RESUME_RETRY_INTERVAL).await;
+        }
+        let (done, finished) = oneshot::channel();
+        if priority_sync
+            .send(PrioritySync::resume(done))
+            .await
+            .is_err()
+        {
+            return false;
+        }
+        finished.await.ok();
+        if status.borrow().sync != SyncStatus::Offline {
+            return true;
+        }
+        // The interface can be up before the server is reachable, eg while
+        // still getting an address.
+        if Instant::now() >= give_up_at {
+            info!("Server still unreachable after waking; leaving the pull to the next sync.");
+            return true;
+        }
+        debug!("Offline right after waking; retrying the pull.");
+        tokio::time::sleep(RESUME_RETRY_INTERVAL).await;
+    }
+}
+
+/// How often we check whether a launched game is still running.
+const GAME_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Pulls each game's saves as the platform's frontend launches it, and pushes
+/// them once it exits.
+///
+/// A sync in progress is cancelled to make way for the pull, since the game
+/// loads its save as soon as it starts.
+fn build_game_watch_thread(
+    config: watch::Receiver<Arc<Config>>,
+    priority_sync: mpsc::Sender<PrioritySync>,
+    sync_cancel: CancelToken,
+    status: watch::Receiver<DaemonStatus>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let platform = Platform::get();
+        let Some(command_path) = platform.game_command_path() else {
+            return;
+        };
+        let (evt_snd, mut evt_rcv) = mpsc::unbounded_channel();
+        let watcher = notify::recommended_watcher(move |evt: notify::Result<notify::Event>| {
+            evt_snd.send(evt).ok();
+        });
+        // Without a watcher we still notice launches, just up to a poll late.
+        let _watcher = match watcher {
+            Ok(mut watcher) => {
+                let dir = command_path.parent().unwrap_or(Path::new("/"));
+                if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    warn!("Error watching {dir:?} for game launches: {e:?}");
+                }
+                Some(watcher)
+            }
+            Err(e) => {
+                warn!("Error starting game launch watcher: {e:?}");
+                None
+            }
+        };
+        let mut tracker = GameTracker::default();
+        loop {
+            tokio::select! {
+                Some(evt) = evt_rcv.recv() => {
+                    let relevant = evt.is_ok_and(|evt| evt.paths.iter().any(|path| path == command_path));
+                    if !relevant {
+                        continue;
+                    }
+                }
+                () = tokio::time::sleep(GAME_POLL_INTERVAL) => {}
+            }
+            let launched = match games::launched_rom(command_path).await {
+                Ok(launched) => launched,
+                Err(e) => {
+                    warn!("Error reading the game launch command: {e:?}");
+                    continue;
+                }
+            };
+            let is_running = |rom: PathBuf| async move {
+                games::rom_is_running(&rom).await.unwrap_or_else(|e| {
+                    warn!("Error checking whether {rom:?} is still running: {e:?}");
+                    true
+                })
+            };
+            let events = tracker.update(launched, Instant::now(), is_running).await;
+            let syncs = events.into_iter().map(|event| match event {
+                GameEvent::Launched { rom, at } => {
+                    info!("Game {rom:?} launched; pulling its saves.");
+                    PrioritySync::game_launched(rom, at)
+                }
+                GameEvent::Exited { rom } => {
+                    info!("Game {rom:?} exited; pushing its saves.");
+                    PrioritySync::game_exited(rom_name(&rom))
+                }
+            });
+            if !config.borrow().system.game_hooks {
+                continue;
+            }
+            for sync in syncs {
+                if sync.launched.is_some() && status.borrow().sync == SyncStatus::Syncing {
+                    info!("Cancelling the in-progress sync to pull the launched game's saves.");
+                    sync_cancel.cancel();
+                }
+                if priority_sync.send(sync).await.is_err() {
+                    return;
+                }
+            }
+        }
+    })
+}
+
+/// Checks whether the pull for the game launched from `rom` at `launched_at`
+/// is still worth running.
+///
+/// The launch script is running by the time we see the launch, so there's no
+/// telling from the process list whether the emulator has loaded the save yet.
+/// Instead the sync skips any save the emulator already has open, and pulls
+/// that are held up for long enough that it surely has are dropped here.
+fn launch_pull_in_time(rom: &Path, launched_at: Instant) -> bool {
+    if launched_at.elapsed() < GAME_LAUNCH_GRACE {
+        return true;
+    }
+    info!(
+        "Game {rom:?} was launched {}s ago; dropping the pull for it.",
+        launched_at.elapsed().as_secs()
+    );
+    false
+}
+
+/// The name saves for `rom` go by, ie its file name without the extension.
+fn rom_name(rom: &Path) -> String {
+    rom.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// How often we check whether the device's network interfaces have changed
+/// state.
+const NETWORK_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long we first wait before retrying a sync that found the server
+/// unreachable even though the network was up; doubled after each failed
+/// retry, up to [`OFFLINE_RETRY_MAX`].
+const OFFLINE_RETRY_MIN: Duration = Duration::from_secs(30);
+
+/// The longest we wait between retries while the server is unreachable.
+const OFFLINE_RETRY_MAX: Duration = Duration::from_secs(15 * 60);
+
+/// Retries a sync that was skipped for being offline, as soon as the network
+/// comes back up, or with a backoff if the network was up but the server
+/// couldn't be reached.
+fn build_network_watch_thread(
+    sync_trigger: EventTrigger,
+    status: watch::Receiver<DaemonStatus>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut was_up = network::has_network_interface().await.unwrap_or(true);
+        let mut retry_in = OFFLINE_RETRY_MIN;
+        let mut next_retry: Option<Instant> = None;
+        loop {
+            tokio::time::sleep(NETWORK_POLL_INTERVAL).await;
+            let is_up = match network::has_network_interface().await {
+                Ok(b) => b,
+                Err(e) => {
+                    warn!("Error checking network interface state: {e:?}");
+                    continue;
+                }
+            };
+            let sync = status.borrow().sync;
+            match sync {
+                SyncStatus::Offline => {}
+                // Our own retry; wait to see how it went.
+                SyncStatus::Syncing => {
+                    was_up = is_up;
+                    continue;
+                }
+                _ => {
+                    retry_in = OFFLINE_RETRY_MIN;
+                    next_retry = None;
+                    was_up = is_up;
+                    continue;
+                }
+            }
+            if is_up && !was_up {
+                info!("Network connection restored; triggering sync.");
+                sync_trigger.trigger();
+                retry_in = OFFLINE_RETRY_MIN;
+                next_retry = Some(Instant::now() + retry_in);
+            } else if is_up {
+                match next_retry {
+                    None => next_retry = Some(Instant::now() + retry_in),
+                    Some(at) if Instant::now() >= at => {
+                        info!("Server was unreachable; retrying sync.");
+                        sync_trigger.trigger();
+                        retry_in = (retry_in * 2).min(OFFLINE_RETRY_MAX);
+                        next_retry = Some(Instant::now() + retry_in);
+                    }
+                    Some(_) => {}
+                }
+            }
+            was_up = is_up;
+        }
+    })
+}
+
+/// Which saves a sync moves, and which way.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+struct SyncScope {
+    /// Only moves saves this way, if set.
+    only: Option<Transfer>,
+    /// Only syncs the saves for this ROM, if set.
+    rom: Option<String>,
+}
+
+impl fmt::Display for SyncScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.only {
+            None => "full",
+            Some(Transfer::Push) => "push-only",
+            Some(Transfer::Pull) => "pull-only",
+        })?;
+        if let Some(rom) = &self.rom {
+            write!(f, " ({rom})")?;
+        }
+        Ok(())
+    }
+}
+
+/// A sync that goes ahead of any regular ones on the `_sync_actor_thread`.
+struct PrioritySync {
+    scope: SyncScope,
+    /// Answered once the sync has finished; dropped unanswered if it's
+    /// skipped.
+    done: Option<oneshot::Sender<()>>,
+    /// Runs even while syncing is paused or the battery is low, for syncs that
+    /// won't get another chance.
+    forced: bool,
+    /// For a pull on launching a game, the ROM and when it was launched; the
+    /// pull is dropped if it can't start before the game loads its save.
+    launched: Option<(PathBuf, Instant)>,
+}
+
+impl PrioritySync {
+    /// Syncs everything at a UI's request.
+    fn requested() -> Self {
+        Self {
+            scope: SyncScope::default(),
+            done: None,
+            forced: false,
+            launched: None,
+        }
+    }
+
+    /// Pulls the latest saves as soon as the device wakes up, so they're
+    /// there before the user starts playing.
+    fn resume(done: oneshot::Sender<()>) -> Self {
+        Self {
+            scope: SyncScope {
+                only: Some(Transfer::Pull),
+                rom: None,
+            },
+            done: Some(done),
+            forced: false,
+            launched: None,
+        }
+    }
+
+    /// Pushes changed saves before the device powers down.
+    fn final_push(done: oneshot::Sender<()>) -> Self {
+        Self {
+            scope: SyncScope {
+                only: Some(Transfer::Push),
+                rom: None,
+            },
+            done: Some(done),
+            forced: true,
+            launched: None,
+        }
+    }
+
+    /// Pulls the saves for a game as it's launched.
+    fn game_launched(rom: PathBuf, launched_at: Instant) -> Self {
+        Self {
+            scope: SyncScope {
+                only: Some(Transfer::Pull),
+                rom: Some(rom_name(&rom)),
+            },
+            done: None,
+            forced: false,
+            launched: Some((rom, launched_at)),
+        }
+    }
+
+    /// Pushes the saves for a game once it exits.
+    fn game_exited(rom: String) -> Self {
+        Self {
+            scope: SyncScope {
+                only: Some(Transfer::Push),
+                rom: Some(rom),
+            },
+            done: None,
+            forced: false,
+            launched: None,
+        }
+    }
+}
+
+fn build_sync_actor_thread(
+    config: watch::Receiver<Arc<Config>>,
+    cancel: CancelToken,
+    paused: bool,
+) -> (
+    EventTrigger,
+    mpsc::Sender<PrioritySync>,
+    watch::Sender<DaemonStatus>,
+    JoinHandle<()>,
+) {
     let (snd, mut trigger) = EventTrigger::new();
+    let (priority_snd, mut priority) = mpsc::channel::<PrioritySync>(4);
+    let status_snd = watch::Sender::new(DaemonStatus {
+        paused,
+        ..Default::default()
+    });
+    let status = status_snd.clone();
     let thread = tokio::spawn(async move {
         loop {
-            trigger.wait_and_reset().await;
-            if let Err(e) = do_sync().await {
-                error!("Error during sync: {e:?}");
+            let (request, automatic) = tokio::select! {
+                biased;
+                Some(request) = priority.recv() => (request, false),
+                () = trigger.wait_and_reset() => (PrioritySync::requested(), true),
+            };
+            let PrioritySync {
+                scope,
+                done,
+                forced,
+                launched,
+            } = request;
+            // Reset before checking the paused flag so that a `Pause` arriving
+            // in between still cancels the sync we're about to start.
+            cancel.reset();
+            if let Some((rom, launched_at)) = &launched {
+                if !launch_pull_in_time(rom, *launched_at) {
+                    continue;
+                }
+            }
+            if !forced && status_snd.borrow().paused {
+                debug!("Syncing is paused; ignoring sync trigger.");
+                status_snd.send_modify(|status| status.sync = SyncStatus::Paused);
+                continue;
+            }
+            let cfg = Arc::clone(&config.borrow());
+            let power = if forced {
+                None
+            } else {
+                Some(current_power_state().await)
+            };
+            // Syncs the daemon starts by itself, whether on a timer or because
+            // a save changed, the network came back or the device is charging.
+            let blocked = match power {
+                Some(power) if automatic => {
+                    cfg.system.schedule.blocked_by(&Local::now(), power.source)
+                }
+                _ => None,
+            };
+            if let Some(reason) = blocked {
+                debug!("Skipping sync, since {reason}.");
+                continue;
+            }
+            if let Some(level) = power.and_then(|power| cfg.system.power.low_battery(power)) {
+                info!("Battery is at {level}%; deferring sync until the device is charging.");
+                status_snd.send_modify(|status| status.sync = SyncStatus::LowBattery);
+                continue;
+            }
+            status_snd.send_modify(|status| status.sync = SyncStatus::Syncing);
+            let res = do_sync(&cfg, &scope, &status_snd, &cancel).await;
+            status_snd.send_modify(|status| match res {
+                Ok(sync) => {
+                    status.sync = sync;
+                    status.error = None;
+                }
+                Err(e) if e.is::<SyncCancelled>() => {
+                    status.sync = if status.paused {
+                        SyncStatus::Paused
+                    } else {
+                        SyncStatus::Cancelled
+                    };
+                    status.error = None;
+                }
+                Err(e) => {
+                    error!("Error during sync: {e:?}");
+                    status.sync = SyncStatus::Failed;
+                    status.error = Some(format!("{e:#}"));
+                }
+            });
+            if let Some(done) = done {
+                done.send(()).ok();
             }
         }
     });
-    (snd, thread)
+    (snd, priority_snd, status, thread)
 }
 
 async fn load_config() -> Result<Config, anyhow::Error> {
-    let cfg = Config::load(Platform::get().config_input_paths()).await?;
+    let cfg = Config::load_current_platform().await?;
     cfg.validate()?;
     Ok(cfg)
 }
 
-async fn do_sync() -> Result<(), anyhow::Error> {
-    info!("Performing sync.");
-    let cfg = load_config().await?;
-    let db = SaveMetaDatabase::open(cfg.system.database.as_deref().unwrap()).await?;
+async fn do_sync(
+    cfg: &Config,
+    scope: &SyncScope,
+    status: &watch::Sender<DaemonStatus>,
+    cancel: &CancelToken,
+) -> Result<SyncStatus, anyhow::Error> {
+    info!("Performing {scope} sync.");
+    let db = open_database(cfg).await?;
     debug!("Performing sync with config: {cfg:?}");
-    let cl = RommClient::new(
-        cfg.romm.url.clone().unwrap(),
-        cfg.romm.api_key.clone().unwrap(),
-    );
-
-    run_sync(&cfg, &cl, &db).await?;
+    let report = match cfg.system.remote() {
+        RemoteKind::Romm => sync_with_romm(cfg, scope, &db, status, cancel).await?,
+        RemoteKind::Directory => {
+            status.send_modify(|status| status.server_version = None);
+            sync_with_directory(cfg, scope, &db, cancel).await?
+        }
+        RemoteKind::WebDav => {
+            status.send_modify(|status| status.server_version = None);
+            sync_with_webdav(cfg, scope, &db, cancel).await?
+        }
+    };
+    let Some(report) = report else {
+        return Ok(SyncStatus::Offline);
+    };
+    if !report.suppressed.is_empty() {
+        info!(
+            "Sync direction settings held back {} transfer(s).",
+            report.suppressed.len()
+        );
+    }
+    if !report.in_use.is_empty() {
+        info!(
+            "Left {} save(s) that are open in another process for later.",
+            report.in_use.len()
+        );
+    }
+    status.send_modify(|status| {
+        status.suppressed = report.suppressed;
+        status.in_use = report.in_use;
+    });
     info!("Finished sync.");
+    Ok(SyncStatus::Synced)
+}
+
+/// Runs a sync of `local`'s saves, narrowed down to `scope`.
+async fn run_scoped_sync<L, R, S>(
+    cfg: &Config,
+    scope: &SyncScope,
+    local: &L,
+    remote: &R,
+    state: &S,
+    cancel: &CancelToken,
+) -> Result<SyncReport, anyhow::Error>
+where
+    L: LocalStore,
+    R: RemoteStore,
+    S: StateStore,
+{
+    let mut local = ScopedSaves::new(cfg, local);
+    if let Some(transfer) = scope.only {
+        local = local.only(transfer);
+    }
+    if let Some(rom) = &scope.rom {
+        local = local.rom(rom.clone());
+    }
+    run_sync(&local, remote, state, &cfg.system.concurrency, cancel).await
+}
+
+/// Syncs each profile that saves are bound to with its ROMM server, returning
+/// `None` if none of them can be reached.
+///
+/// A profile whose server can't be reached, or whose sync fails, doesn't stop
+/// the others from syncing.
+async fn sync_with_romm(
+    cfg: &Config,
+    scope: &SyncScope,
+    db: &SaveMetaDatabase,
+    status: &watch::Sender<DaemonStatus>,
+    cancel: &CancelToken,
+) -> Result<Option<SyncReport>, anyhow::Error> {
+    if !network::has_network_interface().await? {
+        info!("No network interface is up; skipping sync.");
+        return Ok(None);
+    }
+    let mut report: Option<SyncReport> = None;
+    let mut errors = Vec::new();
+    for name in cfg.used_profiles() {
+        let label = name.unwrap_or("romm");
+        let res =
+            sync_with_romm_profile(cfg, name, scope, db, status, report.is_none(), cancel).await;
+        match res {
+            Ok(Some(synced)) => {
+                let report = report.get_or_insert_with(SyncReport::default);
+                report.suppressed.extend(synced.suppressed);
+                report.in_use.extend(synced.in_use);
+            }
+            Ok(None) => {}
+            Err(e) if cancel.is_cancelled() => return Err(e),
+            Err(e) => {
+                error!("Error syncing profile {label}: {e:?}");
+                errors.push(e.context(format!("Error syncing profile {label}")));
+            }
+        }
+    }
+    // TODO: Do something with the rest of the errors
+    errors.pop().map_or(Ok(report), Err)
+}
+
+/// Syncs the saves bound to the profile `name` with its ROMM server, returning
+/// `None` if it can't be reached.
+///
+/// Only the first profile reached in a sync reports its server version.
+async fn sync_with_romm_profile(
+    cfg: &Config,
+    name: Option<&str>,
+    scope: &SyncScope,
+    db: &SaveMetaDatabase,
+    status: &watch::Sender<DaemonStatus>,
+    report_version: bool,
+    cancel: &CancelToken,
+) -> Result<Option<SyncReport>, anyhow::Error> {
+    let label = name.unwrap_or("romm");
+    let profile = cfg
+        .profile(name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown profile {label}"))?;
+    let cl = RommClient::connect(
+        profile.url.clone().unwrap(),
+        profile.api_key.clone().unwrap(),
+    )
+    .await;
+    let cl = match cl {
+        Ok(cl) => cl.with_format(profile.format.clone()),
+        Err(e) if e.is_unreachable() => {
+            info!("ROMM server for profile {label} is unreachable ({e}); skipping it.");
+            return Ok(None);
+        }
+        Err(e) => {
+            return Err(e.into());
+        }
+    };
+    if report_version {
+        let server_version = cl.capabilities().version.to_string();
+        status.send_modify(|status| status.server_version = Some(server_version));
+    }
+
+    let saves = ProfileSaves::new(cfg, name);
+    let history = db.profile(name);
+    let report = run_scoped_sync(cfg, scope, &saves, &cl, &history, cancel).await?;
+    Ok(Some(report))
+}
+
+/// Syncs with the configured directory, returning `None` if it can't be reached.
+async fn sync_with_directory(
+    cfg: &Config,
+    scope: &SyncScope,
+    db: &SaveMetaDatabase,
+    cancel: &CancelToken,
+) -> Result<Option<SyncReport>, anyhow::Error> {
+    let root = cfg.directory.path.clone().unwrap();
+    let remote = DirectoryRemote::new(root.clone(), cfg.directory.format());
+    if !remote.is_available().await {
+        info!("Sync directory {root:?} is not available; skipping sync.");
+        return Ok(None);
+    }
+    let report = run_scoped_sync(cfg, scope, cfg, &remote, db, cancel).await?;
+    Ok(Some(report))
+}
+
+/// Syncs with the configured WebDAV share, returning `None` if it can't be reached.
+async fn sync_with_webdav(
+    cfg: &Config,
+    scope: &SyncScope,
+    db: &SaveMetaDatabase,
+    cancel: &CancelToken,
+) -> Result<Option<SyncReport>, anyhow::Error> {
+    if !network::has_network_interface().await? {
+        info!("No network interface is up; skipping sync.");
+        return Ok(None);
+    }
+    let url = cfg.webdav.url.clone().unwrap();
+    let mut remote = WebDavRemote::new(url.clone(), cfg.webdav.format());
+    if let Some(username) = cfg.webdav.username.clone() {
+        remote = remote.with_credentials(username, cfg.webdav.password.clone());
+    }
+    if !remote.is_available().await? {
+        info!("WebDAV share {url} is unreachable; skipping sync.");
+        return Ok(None);
+    }
+    let report = run_scoped_sync(cfg, scope, cfg, &remote, db, cancel).await?;
+    Ok(Some(report))
+}
+
+async fn open_database(cfg: &Config) -> Result<SaveMetaDatabase, anyhow::Error> {
+    let db = SaveMetaDatabase::open(cfg.system.database.as_deref().unwrap()).await?;
+    Ok(db)
+}
+
+async fn load_paused(cfg: &Config) -> Result<bool, anyhow::Error> {
+    Ok(open_database(cfg).await?.is_paused().await?)
+}
+
+async fn persist_paused(cfg: &Config, paused: bool) -> Result<(), anyhow::Error> {
+    open_database(cfg).await?.set_paused(paused).await?;
     Ok(())
 }
 
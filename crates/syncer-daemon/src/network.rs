@@ -0,0 +1,88 @@
+//! Helpers for detecting whether the local device currently has a usable
+//! network connection.
+
+use std::io;
+use std::path::Path;
+
+use tokio::fs;
+use tracing::trace;
+
+/// Where the kernel exposes the state of each network interface.
+const NET_CLASS_ROOT: &str = "/sys/class/net";
+
+/// Checks whether any non-loopback network interface is currently up.
+///
+/// On handhelds Wi-Fi is frequently turned off, so this lets us skip a sync
+/// cheaply before trying (and failing) to talk to the server at all. If the
+/// platform doesn't expose `/sys/class/net` we assume we're connected and let
+/// the server probe decide.
+pub async fn has_network_interface() -> io::Result<bool> {
+    interfaces_up(Path::new(NET_CLASS_ROOT)).await
+}
+
+async fn interfaces_up(root: &Path) -> io::Result<bool> {
+    let mut rdr = match fs::read_dir(root).await {
+        Ok(rdr) => rdr,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(true);
+        }
+        Err(e) => {
+            return Err(e);
+        }
+    };
+    while let Some(ent) = rdr.next_entry().await? {
+        if ent.file_name() == "lo" {
+            continue;
+        }
+        let state = match fs::read_to_string(ent.path().join("operstate")).await {
+            Ok(state) => state,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                continue;
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        };
+        trace!("Interface {:?} has state {}", ent.file_name(), state.trim());
+        // Some drivers (tunnels, a few Wi-Fi chipsets) never report anything
+        // but `unknown` even while passing traffic.
+        if matches!(state.trim(), "up" | "unknown") {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syncer_test_support::TempDir;
+
+    #[test]
+    fn test_interfaces_up() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let dir = TempDir::new("romm-syncer-net").unwrap();
+                let root = dir.path();
+                fs::create_dir_all(root.join("lo")).await.unwrap();
+                fs::write(root.join("lo/operstate"), "unknown\n")
+                    .await
+                    .unwrap();
+                fs::create_dir_all(root.join("wlan0")).await.unwrap();
+                fs::write(root.join("wlan0/operstate"), "down\n")
+                    .await
+                    .unwrap();
+                assert!(!interfaces_up(root).await.unwrap());
+
+                fs::write(root.join("wlan0/operstate"), "up\n")
+                    .await
+                    .unwrap();
+                assert!(interfaces_up(root).await.unwrap());
+
+                assert!(interfaces_up(&root.join("missing")).await.unwrap());
+            });
+    }
+}
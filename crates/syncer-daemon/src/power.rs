@@ -0,0 +1,150 @@
+//! Helpers for detecting whether the local device is currently plugged in or
+//! running off its battery, and how full that battery is.
+
+use std::io;
+use std::path::Path;
+
+use syncer_model::config::{PowerSource, PowerState};
+use tokio::fs;
+use tracing::trace;
+
+/// Where the kernel exposes the state of each power supply.
+const POWER_SUPPLY_ROOT: &str = "/sys/class/power_supply";
+
+/// Checks where the device is currently getting its power from, and how full
+/// its battery is.
+///
+/// A device counts as plugged in if any mains or USB supply is online, or if a
+/// battery reports charging or full. If the platform doesn't expose
+/// `/sys/class/power_supply`, or doesn't list a battery, we assume it's
+/// plugged in so that schedules & battery thresholds only ever hold back syncs
+/// on devices we know are on battery.
+pub async fn power_state() -> io::Result<PowerState> {
+    supplies_power_state(Path::new(POWER_SUPPLY_ROOT)).await
+}
+
+/// Reads a single attribute of a power supply, returning `None` if the driver
+/// doesn't provide it.
+async fn read_attribute(supply: &Path, name: &str) -> io::Result<Option<String>> {
+    match fs::read_to_string(supply.join(name)).await {
+        Ok(value) => Ok(Some(value.trim().to_owned())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+async fn supplies_power_state(root: &Path) -> io::Result<PowerState> {
+    let mut rdr = match fs::read_dir(root).await {
+        Ok(rdr) => rdr,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(PowerState::EXTERNAL);
+        }
+        Err(e) => {
+            return Err(e);
+        }
+    };
+    let mut has_battery = false;
+    let mut plugged_in = false;
+    let mut battery: Option<u8> = None;
+    while let Some(ent) = rdr.next_entry().await? {
+        let supply = ent.path();
+        let Some(kind) = read_attribute(&supply, "type").await? else {
+            continue;
+        };
+        trace!("Power supply {:?} has type {kind}", ent.file_name());
+        match kind.as_str() {
+            "Battery" => {
+                has_battery = true;
+                let status = read_attribute(&supply, "status").await?;
+                trace!("Battery {:?} has status {status:?}", ent.file_name());
+                if matches!(status.as_deref(), Some("Charging" | "Full")) {
+                    plugged_in = true;
+                }
+                // With more than one battery, the emptiest one runs out first.
+                let capacity = read_attribute(&supply, "capacity").await?;
+                if let Some(capacity) = capacity.and_then(|raw| raw.parse::<u8>().ok()) {
+                    battery = Some(battery.map_or(capacity, |level| level.min(capacity)));
+                }
+            }
+            // Everything else (`Mains`, `USB`, `UPS`, ...) is something the
+            // device can be plugged into.
+            _ => {
+                if read_attribute(&supply, "online").await?.as_deref() == Some("1") {
+                    plugged_in = true;
+                }
+            }
+        }
+    }
+    let source = if has_battery && !plugged_in {
+        PowerSource::Battery
+    } else {
+        PowerSource::External
+    };
+    Ok(PowerState { source, battery })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syncer_test_support::TempDir;
+
+    #[test]
+    fn test_supplies_power_state() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let dir = TempDir::new("romm-syncer-power").unwrap();
+                let root = dir.path();
+                fs::create_dir_all(root.join("usb")).await.unwrap();
+                fs::write(root.join("usb/type"), "USB\n").await.unwrap();
+                fs::write(root.join("usb/online"), "0\n").await.unwrap();
+                assert_eq!(
+                    supplies_power_state(root).await.unwrap().source,
+                    PowerSource::External
+                );
+
+                fs::create_dir_all(root.join("battery")).await.unwrap();
+                fs::write(root.join("battery/type"), "Battery\n")
+                    .await
+                    .unwrap();
+                fs::write(root.join("battery/status"), "Discharging\n")
+                    .await
+                    .unwrap();
+                fs::write(root.join("battery/capacity"), "42\n")
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    supplies_power_state(root).await.unwrap(),
+                    PowerState {
+                        source: PowerSource::Battery,
+                        battery: Some(42),
+                    }
+                );
+
+                fs::write(root.join("usb/online"), "1\n").await.unwrap();
+                assert_eq!(
+                    supplies_power_state(root).await.unwrap().source,
+                    PowerSource::External
+                );
+
+                fs::write(root.join("usb/online"), "0\n").await.unwrap();
+                fs::write(root.join("battery/status"), "Charging\n")
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    supplies_power_state(root).await.unwrap().source,
+                    PowerSource::External
+                );
+
+                assert_eq!(
+                    supplies_power_state(&root.join("missing"))
+                        .await
+                        .unwrap()
+                        .source,
+                    PowerSource::External
+                );
+            });
+    }
+}
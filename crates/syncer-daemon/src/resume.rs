@@ -0,0 +1,136 @@
+//! Helpers for noticing when the local device wakes up from suspend.
+//!
+//! The monotonic clock that every `tokio` timer runs on stops while the device
+//! is suspended, so a handheld that sleeps overnight wakes up with its timers
+//! picking up where they left off. The boot clock (as reported by
+//! `/proc/uptime`) keeps counting through a suspend, so the two drifting apart
+//! means the device was asleep in between.
+
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio::fs;
+use tracing::trace;
+
+/// Where the kernel reports the time since boot, including time spent
+/// suspended.
+const UPTIME_PATH: &str = "/proc/uptime";
+
+/// How far the clocks need to drift apart before we count it as a suspend,
+/// rather than the scheduler running us late.
+const RESUME_THRESHOLD: Duration = Duration::from_secs(30);
+
+/// Watches for the device having been suspended.
+pub struct ResumeDetector {
+    monotonic: Instant,
+    suspend_aware: SuspendAwareTime,
+}
+
+/// A reading of a clock that keeps counting while the device is suspended.
+#[derive(Clone, Copy, Debug)]
+enum SuspendAwareTime {
+    /// The time since boot, from `/proc/uptime`.
+    Uptime(Duration),
+    /// The wall clock, on platforms without `/proc/uptime`; this also jumps
+    /// when the clock is set, eg when the device first gets network time.
+    Wall(SystemTime),
+}
+
+impl SuspendAwareTime {
+    async fn now() -> Self {
+        match read_uptime(Path::new(UPTIME_PATH)).await {
+            Ok(uptime) => Self::Uptime(uptime),
+            Err(e) => {
+                trace!("Can't read uptime, falling back to the wall clock: {e:?}");
+                Self::Wall(SystemTime::now())
+            }
+        }
+    }
+
+    /// How much time passed between `earlier` & `self`, if they came from the
+    /// same clock.
+    fn since(self, earlier: Self) -> Option<Duration> {
+        match (self, earlier) {
+            (Self::Uptime(now), Self::Uptime(then)) => now.checked_sub(then),
+            (Self::Wall(now), Self::Wall(then)) => now.duration_since(then).ok(),
+            _ => None,
+        }
+    }
+}
+
+impl ResumeDetector {
+    pub async fn new() -> Self {
+        Self {
+            monotonic: Instant::now(),
+            suspend_aware: SuspendAwareTime::now().await,
+        }
+    }
+
+    /// Checks whether the device was suspended since the last check,
+    /// returning roughly how long for if it was.
+    pub async fn check(&mut self) -> Option<Duration> {
+        let monotonic = Instant::now();
+        let suspend_aware = SuspendAwareTime::now().await;
+        let awake = monotonic.duration_since(self.monotonic);
+        let passed = suspend_aware.since(self.suspend_aware);
+        self.monotonic = monotonic;
+        self.suspend_aware = suspend_aware;
+        trace!("{awake:?} awake & {passed:?} passed since the last check");
+        time_asleep(awake, passed?)
+    }
+}
+
+/// How long the device was suspended for, if a suspend-aware clock says
+/// `passed` while the monotonic clock only counted `awake`.
+fn time_asleep(awake: Duration, passed: Duration) -> Option<Duration> {
+    let asleep = passed.saturating_sub(awake);
+    (asleep >= RESUME_THRESHOLD).then_some(asleep)
+}
+
+async fn read_uptime(path: &Path) -> io::Result<Duration> {
+    let raw = fs::read_to_string(path).await?;
+    parse_uptime(&raw).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unexpected uptime format {raw:?}"),
+        )
+    })
+}
+
+/// Parses the first field of `/proc/uptime`, the seconds since boot.
+fn parse_uptime(raw: &str) -> Option<Duration> {
+    let secs = raw.split_whitespace().next()?.parse::<f64>().ok()?;
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_detection() {
+        assert_eq!(
+            parse_uptime("12345.50 54321.00\n"),
+            Some(Duration::from_millis(12_345_500))
+        );
+        assert_eq!(parse_uptime(""), None);
+        assert_eq!(parse_uptime("-1 2"), None);
+
+        let secs = Duration::from_secs;
+        assert_eq!(time_asleep(secs(10), secs(11)), None);
+        assert_eq!(time_asleep(secs(10), secs(5)), None);
+        assert_eq!(time_asleep(secs(10), secs(3610)), Some(secs(3600)));
+
+        let boot = SuspendAwareTime::Uptime(secs(100));
+        assert_eq!(
+            SuspendAwareTime::Uptime(secs(160)).since(boot),
+            Some(secs(60))
+        );
+        assert_eq!(
+            SuspendAwareTime::Wall(SystemTime::now())
+                .since(SuspendAwareTime::Uptime(Duration::ZERO)),
+            None
+        );
+    }
+}
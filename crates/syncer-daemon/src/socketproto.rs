@@ -1,13 +1,15 @@
+use std::io;
 use std::sync::Arc;
+use std::time::Duration;
 
 use interprocess::local_socket::tokio::Stream;
-use interprocess::local_socket::traits::tokio::Listener as _;
+use interprocess::local_socket::traits::tokio::{Listener as _, Stream as _};
 use interprocess::local_socket::{GenericFilePath, ListenerOptions, ToFsName};
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::task::JoinHandle;
 use tracing::{debug, error, trace};
 
-use syncer_model::commands::DaemonCommand;
+use syncer_model::commands::{DaemonCommand, DaemonResponse};
 use syncer_model::platforms::Platform;
 
 use crate::DaemonState;
@@ -45,16 +47,25 @@ async fn handle_stream(state: Arc<DaemonState>, mut stream: Stream) {
     debug!("Received new connection on daemon command socket.");
     let mut buffer = Vec::new();
     loop {
-        if let Err(e) = stream.read_buf(&mut buffer).await {
-            error!("Error pulling data from stream: {e:?}");
+        match stream.read_buf(&mut buffer).await {
+            Ok(0) => {
+                debug!("Connection on daemon command socket closed.");
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("Error pulling data from stream: {e:?}");
+                return;
+            }
         }
+        let mut responses = Vec::new();
         let mut des =
             serde_json::Deserializer::from_slice(buffer.as_slice()).into_iter::<DaemonCommand>();
         let new_start = loop {
             match des.next() {
                 Some(Ok(evt)) => {
                     trace!("Parsed command from socket: {evt:?}");
-                    state.run_command(&evt);
+                    responses.extend(state.run_command(&evt).await);
                 }
                 Some(Err(e)) if e.is_eof() => {
                     break des.byte_offset();
@@ -70,5 +81,22 @@ async fn handle_stream(state: Arc<DaemonState>, mut stream: Stream) {
         };
         let new_buffer = buffer.split_off(new_start);
         buffer = new_buffer;
+        for response in responses {
+            trace!("Sending response over socket: {response:?}");
+            if let Err(e) = stream.write_all(response.serialize().as_bytes()).await {
+                error!("Error sending response: {e:?}");
+                return;
+            }
+        }
     }
 }
+
+/// Sends a command to the running daemon and waits up to `timeout` for its
+/// reply.
+pub async fn query_daemon(cmd: &DaemonCommand, timeout: Duration) -> io::Result<DaemonResponse> {
+    let path = Platform::get()
+        .socket_path()
+        .to_fs_name::<GenericFilePath>()?;
+    let mut stream = Stream::connect(path).await?;
+    cmd.query(&mut stream, timeout).await
+}
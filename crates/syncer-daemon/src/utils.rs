@@ -1,66 +1,10 @@
-use std::io;
-use std::path::Path;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::{Duration, Instant, SystemTime};
+use std::time::{Duration, Instant};
 
-use chrono::{DateTime, Utc};
 use futures::future::Either;
-use futures::pin_mut;
-use futures::{Stream, TryStreamExt};
-use thiserror::Error;
-use tokio::fs::{self, File};
-use tokio::io::AsyncWriteExt;
 use tokio::sync::watch;
 use tracing::trace;
 
-static INCREMENTING_ID: AtomicUsize = AtomicUsize::new(0xa0_00);
-
-/// Returns a new ID to use for debugging purposes.
-///
-/// Guranteed to not repeat across multiple calls, even in async/multi-threaded
-/// environments.
-pub fn new_id() -> usize {
-    INCREMENTING_ID.fetch_add(1, Ordering::Relaxed)
-}
-
-#[derive(Debug, Error, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum EitherError<A, B> {
-    #[error(transparent)]
-    A(#[from] A),
-    #[error(transparent)]
-    B(B),
-}
-
-/// Atomically writes a file from a stream to the given location.
-///
-/// We do this by first writing the data to a temporary file path with a
-/// timestamp-derived name and moving it to the correct location only after the
-/// data has been completely written to the file.
-pub async fn download<S, B, E>(data: S, dst: &Path) -> Result<(), EitherError<io::Error, E>>
-where
-    S: Stream<Item = Result<B, E>>,
-    B: AsRef<[u8]>,
-{
-    let tmp_fname = dst.with_extension(timestamp_now().to_rfc3339());
-    let mut fh = File::create_new(&tmp_fname).await?;
-
-    pin_mut!(data);
-    while let Some(chunk) = data.try_next().await.map_err(EitherError::B)? {
-        fh.write_all(chunk.as_ref()).await?;
-    }
-    fh.flush().await?;
-    drop(fh);
-    fs::rename(tmp_fname, dst).await?;
-    Ok(())
-}
-
-pub fn timestamp_now() -> DateTime<Utc> {
-    let dt = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_nanos() as _;
-    DateTime::from_timestamp_nanos(dt)
-}
+use syncer_engine::utils::new_id;
 
 /// A tool for sleeping for a configurable amount of time, where the time to
 /// sleep is possible to change externally while a sleep is ongoing.
@@ -122,11 +66,12 @@ impl ConfigurableSleep {
         trace!("Starting sleep on ID {} ({} s)", self.id, dt.as_secs_f64());
         loop {
             let dt = *self.configuration_cb.borrow_and_update();
-            if dt >= start.elapsed() {
+            let elapsed = start.elapsed();
+            if elapsed >= dt {
                 break;
             }
             let change_fut = self.configuration_cb.changed();
-            let sleep_fut = tokio::time::sleep(dt);
+            let sleep_fut = tokio::time::sleep(dt - elapsed);
             futures::pin_mut!(change_fut);
             futures::pin_mut!(sleep_fut);
             match futures::future::select(sleep_fut, change_fut).await {
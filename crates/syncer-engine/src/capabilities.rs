@@ -0,0 +1,178 @@
+//! Detection of which ROMM server versions we can talk to.
+//!
+//! The `romm-api` models are a snapshot of a single ROMM release, so rather
+//! than letting a too-old server fail with an opaque deserialization error we
+//! read the server's version from its heartbeat up front and refuse servers
+//! older than [`MIN_SUPPORTED_VERSION`].
+//!
+//! Optional API features aren't tied to a version here. The ones the syncer
+//! uses, paginated rom listings and hashed save records, are told apart by the
+//! shape of the server's responses as the client reads them.
+
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use thiserror::Error;
+use tracing::warn;
+
+/// A `MAJOR.MINOR.PATCH` ROMM release version.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ServerVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl ServerVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum VersionParseError {
+    #[error("Empty version string")]
+    Empty,
+    #[error("Too many version components in {0:?}")]
+    TooManyComponents(String),
+    #[error(transparent)]
+    InvalidInteger(#[from] ParseIntError),
+}
+
+impl FromStr for ServerVersion {
+    type Err = VersionParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim().trim_start_matches(['v', 'V']);
+        // Ignore any pre-release or build metadata, eg `3.8.0-beta.1`.
+        let core = trimmed.split(['-', '+']).next().unwrap_or_default();
+        if core.is_empty() {
+            return Err(VersionParseError::Empty);
+        }
+        let mut parts = core.split('.');
+        let major = parts.next().unwrap_or_default().parse()?;
+        let minor = parts.next().map(str::parse).transpose()?.unwrap_or(0);
+        let patch = parts.next().map(str::parse).transpose()?.unwrap_or(0);
+        if parts.next().is_some() {
+            return Err(VersionParseError::TooManyComponents(s.to_owned()));
+        }
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+/// The oldest ROMM release whose save API we know how to use.
+pub const MIN_SUPPORTED_VERSION: ServerVersion = ServerVersion::new(3, 0, 0);
+
+/// The newest ROMM release the syncer has been checked against.
+///
+/// Newer servers are still used, but we warn since their API may have drifted
+/// from our models.
+pub const LATEST_KNOWN_VERSION: ServerVersion = ServerVersion::new(3, 10, 0);
+
+/// What we've learned about a particular ROMM server.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ServerCapabilities {
+    /// The version the server reported, or the version we're treating it as
+    /// if it reported something we couldn't parse.
+    pub version: ServerVersion,
+}
+
+impl ServerCapabilities {
+    /// The capabilities of a server of exactly the given version.
+    pub fn for_version(version: ServerVersion) -> Self {
+        Self { version }
+    }
+
+    /// Works out the capabilities of a server from the raw version string in
+    /// its heartbeat.
+    ///
+    /// Development builds and unparseable versions are treated as the latest
+    /// version we know about.
+    ///
+    /// # Errors
+    /// * The server is older than [`MIN_SUPPORTED_VERSION`].
+    pub fn negotiate(raw_version: &str) -> Result<Self, UnsupportedServerError> {
+        let version = match raw_version.parse::<ServerVersion>() {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(
+                    "Could not parse ROMM server version {raw_version:?} ({e}); assuming {LATEST_KNOWN_VERSION}."
+                );
+                LATEST_KNOWN_VERSION
+            }
+        };
+        if version < MIN_SUPPORTED_VERSION {
+            return Err(UnsupportedServerError {
+                found: version,
+                minimum: MIN_SUPPORTED_VERSION,
+            });
+        }
+        if version > LATEST_KNOWN_VERSION {
+            warn!(
+                "ROMM server version {version} is newer than the latest tested version {LATEST_KNOWN_VERSION}; some features may not work."
+            );
+        }
+        Ok(Self::for_version(version))
+    }
+}
+
+impl Default for ServerCapabilities {
+    fn default() -> Self {
+        Self::for_version(MIN_SUPPORTED_VERSION)
+    }
+}
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error(
+    "ROMM server version {found} is too old; the syncer needs at least version {minimum}. Please upgrade your ROMM server."
+)]
+pub struct UnsupportedServerError {
+    pub found: ServerVersion,
+    pub minimum: ServerVersion,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            "3.8.1".parse::<ServerVersion>().unwrap(),
+            ServerVersion::new(3, 8, 1)
+        );
+        assert_eq!(
+            "v4.0.0-beta.2".parse::<ServerVersion>().unwrap(),
+            ServerVersion::new(4, 0, 0)
+        );
+        assert_eq!(
+            "3.7".parse::<ServerVersion>().unwrap(),
+            ServerVersion::new(3, 7, 0)
+        );
+        assert!("development".parse::<ServerVersion>().is_err());
+        assert!("1.2.3.4".parse::<ServerVersion>().is_err());
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert!(ServerCapabilities::negotiate("2.3.1").is_err());
+
+        let old = ServerCapabilities::negotiate("3.5.0").unwrap();
+        assert_eq!(old.version, ServerVersion::new(3, 5, 0));
+        let min = ServerCapabilities::negotiate("3.0.0").unwrap();
+        assert_eq!(min, ServerCapabilities::default());
+
+        let dev = ServerCapabilities::negotiate("development").unwrap();
+        assert_eq!(dev.version, LATEST_KNOWN_VERSION);
+    }
+}
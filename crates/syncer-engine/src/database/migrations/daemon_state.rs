@@ -0,0 +1,21 @@
+use super::*;
+use rusqlite::Connection;
+
+pub const fn daemon_state_schema() -> DatabaseMigration {
+    DatabaseMigration {
+        version: 3,
+        forward: create_daemon_state_table,
+        backwards: delete_daemon_state_table,
+    }
+}
+
+fn create_daemon_state_table(con: &mut Connection) -> Result<(), rusqlite::Error> {
+    con.execute("CREATE TABLE daemon_state (paused INTEGER NOT NULL);", ())?;
+    con.execute("INSERT INTO daemon_state (paused) VALUES (0);", ())?;
+    Ok(())
+}
+
+fn delete_daemon_state_table(con: &mut Connection) -> Result<(), rusqlite::Error> {
+    con.execute("DROP TABLE daemon_state;", ())?;
+    Ok(())
+}
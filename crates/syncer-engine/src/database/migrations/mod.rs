@@ -16,6 +16,8 @@ use std::iter;
 use rusqlite::Connection;
 use thiserror::Error;
 mod base;
+mod daemon_state;
+mod profiles;
 mod scaffolding;
 
 #[derive(Debug, Error)]
@@ -157,7 +159,12 @@ impl DatabaseMigration {
     }
 }
 
-const MIGRATIONS: &[DatabaseMigration] = &[scaffolding::metadata_migration(), base::base_schema()];
+const MIGRATIONS: &[DatabaseMigration] = &[
+    scaffolding::metadata_migration(),
+    base::base_schema(),
+    daemon_state::daemon_state_schema(),
+    profiles::profiles_schema(),
+];
 
 /// Compile time checks for sanity of [`MIGRATIONS`].
 ///
@@ -0,0 +1,63 @@
+use super::*;
+use rusqlite::Connection;
+
+pub const fn profiles_schema() -> DatabaseMigration {
+    DatabaseMigration {
+        version: 4,
+        forward: add_profile_column,
+        backwards: remove_profile_column,
+    }
+}
+
+// SQLite can't change a table's `UNIQUE` constraint in place, so both
+// directions rebuild the `saves` table.
+
+fn add_profile_column(con: &mut Connection) -> Result<(), rusqlite::Error> {
+    let tx = con.transaction()?;
+    tx.execute_batch(
+        r#"
+CREATE TABLE saves_new(
+    profile TEXT NOT NULL DEFAULT '',
+    rom TEXT NOT NULL,
+    name TEXT NOT NULL,
+    ext TEXT NOT NULL,
+    emulator TEXT,
+    created TEXT NOT NULL,
+    updated TEXT NOT NULL,
+    md5 BLOB NOT NULL,
+    size INTEGER NOT NULL,
+    UNIQUE (profile, name, rom, emulator)
+);
+INSERT INTO saves_new(rom, name, ext, emulator, created, updated, md5, size)
+    SELECT rom, name, ext, emulator, created, updated, md5, size FROM saves;
+DROP TABLE saves;
+ALTER TABLE saves_new RENAME TO saves;"#,
+    )?;
+    tx.commit()
+}
+
+/// Only the history of the default profile survives; the old schema has
+/// nowhere to keep the others.
+fn remove_profile_column(con: &mut Connection) -> Result<(), rusqlite::Error> {
+    let tx = con.transaction()?;
+    tx.execute_batch(
+        r#"
+CREATE TABLE saves_old(
+    rom TEXT NOT NULL,
+    name TEXT NOT NULL,
+    ext TEXT NOT NULL,
+    emulator TEXT,
+    created TEXT NOT NULL,
+    updated TEXT NOT NULL,
+    md5 BLOB NOT NULL,
+    size INTEGER NOT NULL,
+    UNIQUE (name, rom, emulator)
+);
+INSERT INTO saves_old(rom, name, ext, emulator, created, updated, md5, size)
+    SELECT rom, name, ext, emulator, created, updated, md5, size FROM saves
+    WHERE profile = '';
+DROP TABLE saves;
+ALTER TABLE saves_old RENAME TO saves;"#,
+    )?;
+    tx.commit()
+}
@@ -9,7 +9,7 @@ use thiserror::Error;
 use tokio::sync::mpsc;
 use tracing::warn;
 
-use crate::{md5hash::Md5Hash, SaveMeta};
+use crate::{md5hash::Md5Hash, stores::StateStore, SaveMeta};
 
 mod migrations;
 use migrations::{apply_migrations, MigrationError};
@@ -17,8 +17,8 @@ use migrations::{apply_migrations, MigrationError};
 /// A database containing metadata around previously seen save versions.
 ///
 /// Used for detecting when a save can be safely synced to/from the device and
-/// when there is a conflict; see this crate's `README` for more details as to
-/// the exact process used for deciding when & how a save is synced.
+/// when there is a conflict; see `syncer-daemon`'s `README` for more details
+/// as to the exact process used for deciding when & how a save is synced.
 pub struct SaveMetaDatabase {
     snd: mpsc::UnboundedSender<DatabaseCallback>,
     _thread: JoinHandle<()>,
@@ -38,11 +38,8 @@ impl SaveMetaDatabase {
         Ok(Self { snd, _thread })
     }
 
-    /// Opens a temporary database in memory.
-    ///
-    /// Use only for tests.
-    #[cfg_attr(not(test), expect(unused))]
-    async fn new_in_memory() -> Result<Self, MigrationError> {
+    /// Opens a temporary database in memory, eg for tests.
+    pub async fn new_in_memory() -> Result<Self, MigrationError> {
         let con = tokio::task::spawn_blocking(move || {
             let mut con = Connection::open_in_memory().map_err(MigrationError::from_raw)?;
             apply_migrations(&mut con)?;
@@ -54,27 +51,37 @@ impl SaveMetaDatabase {
         Ok(Self { snd, _thread })
     }
 
-    /// Pulls the latest metadata seen for a given save file from the database.
+    /// The sync history of the profile `name`, where [`None`] is the default
+    /// `[romm]` section.
+    pub fn profile<'a>(&'a self, name: Option<&'a str>) -> ProfileHistory<'a> {
+        ProfileHistory { db: self, name }
+    }
+
+    /// Pulls the latest metadata seen for a given save file from the database,
+    /// in the history of `profile`.
     pub async fn query_metadata(
         &self,
+        profile: Option<&str>,
         rom: &str,
         name: &str,
         emulator: Option<&str>,
     ) -> Result<SaveMeta, DatabaseError> {
+        let profile = profile_key(profile);
         let rom = rom.to_owned();
         let name = name.to_owned();
-        let mut sql = "SELECT * FROM saves WHERE rom = ?1 AND name = ?2".to_owned();
+        let mut sql =
+            "SELECT * FROM saves WHERE profile = ?1 AND rom = ?2 AND name = ?3".to_owned();
         if emulator.is_some() {
-            sql.push_str(" AND emulator = ?3");
+            sql.push_str(" AND emulator = ?4");
         } else {
             sql.push_str(" AND emulator IS NULL");
         }
         let emulator = emulator.map(|s| s.to_owned());
         run_on_connection(&self.snd, move |con| {
             let params: &[&str] = if let Some(emulator) = emulator.as_deref() {
-                &[rom.as_str(), name.as_str(), emulator]
+                &[profile.as_str(), rom.as_str(), name.as_str(), emulator]
             } else {
-                &[rom.as_str(), name.as_str()]
+                &[profile.as_str(), rom.as_str(), name.as_str()]
             };
             let mut stmt = con.prepare(&sql)?;
             let mut rows = stmt.query_map(params_from_iter(params), |row| {
@@ -117,13 +124,17 @@ impl SaveMetaDatabase {
         .await
     }
 
-    /// Pushes new metadata into the database after a sync.
-    pub async fn upsert_metadata(&self, metadata: &SaveMeta) -> Result<(), DatabaseError> {
+    /// Pushes new metadata into the history of `profile` after a sync.
+    pub async fn upsert_metadata(
+        &self,
+        profile: Option<&str>,
+        metadata: &SaveMeta,
+    ) -> Result<(), DatabaseError> {
         const QUERY: &str = r#"
 INSERT INTO saves(
-    name, rom, ext, emulator, created, updated, md5, size
+    name, rom, ext, emulator, created, updated, md5, size, profile
 ) VALUES 
-    (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) 
+    (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9) 
 ON CONFLICT DO UPDATE SET
     name = ?1,
     rom = ?2,
@@ -133,6 +144,7 @@ ON CONFLICT DO UPDATE SET
     updated = ?6, 
     md5 = ?7,
     size = ?8"#;
+        let profile = profile_key(profile);
         let metadata = metadata.clone();
         run_on_connection(&self.snd, move |con| {
             let modified = con.execute(
@@ -146,6 +158,7 @@ ON CONFLICT DO UPDATE SET
                     metadata.updated,
                     metadata.hash.as_bytes(),
                     metadata.size,
+                    profile,
                 ),
             )?;
             if modified != 1 {
@@ -156,6 +169,64 @@ ON CONFLICT DO UPDATE SET
         .await?;
         Ok(())
     }
+
+    /// Whether the user has paused syncing.
+    pub async fn is_paused(&self) -> Result<bool, DatabaseError> {
+        run_on_connection(&self.snd, |con| {
+            let paused = con.query_row("SELECT paused FROM daemon_state", (), |row| row.get(0))?;
+            Ok(paused)
+        })
+        .await
+    }
+
+    /// Records whether the user has paused syncing, so that the daemon stays
+    /// paused across restarts.
+    pub async fn set_paused(&self, paused: bool) -> Result<(), DatabaseError> {
+        run_on_connection(&self.snd, move |con| {
+            con.execute("UPDATE daemon_state SET paused = ?1", [paused])?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// The sync history of the default profile.
+impl StateStore for SaveMetaDatabase {
+    async fn last_synced(&self, save: &SaveMeta) -> Result<SaveMeta, anyhow::Error> {
+        self.profile(None).last_synced(save).await
+    }
+
+    async fn record_synced(&self, meta: &SaveMeta) -> Result<(), anyhow::Error> {
+        self.profile(None).record_synced(meta).await
+    }
+}
+
+/// The sync history of a single profile, kept apart from the others so that
+/// the same save synced with two servers doesn't mix up their histories.
+pub struct ProfileHistory<'a> {
+    db: &'a SaveMetaDatabase,
+    name: Option<&'a str>,
+}
+
+impl StateStore for ProfileHistory<'_> {
+    async fn last_synced(&self, save: &SaveMeta) -> Result<SaveMeta, anyhow::Error> {
+        let found = self
+            .db
+            .query_metadata(self.name, save.rom(), &save.name, save.emulator.as_deref())
+            .await?;
+        Ok(found)
+    }
+
+    async fn record_synced(&self, meta: &SaveMeta) -> Result<(), anyhow::Error> {
+        self.db.upsert_metadata(self.name, meta).await?;
+        Ok(())
+    }
+}
+
+/// How `profile` is stored in the database; the default profile is stored as
+/// an empty string, since `NULL`s never conflict in a `UNIQUE` constraint.
+fn profile_key(profile: Option<&str>) -> String {
+    profile.unwrap_or_default().to_owned()
 }
 
 #[derive(Error, Debug)]
@@ -223,17 +294,19 @@ mod tests {
                 };
                 assert!(db
                     .query_metadata(
-                        &test_rom.rom(),
+                        None,
+                        test_rom.rom(),
                         &test_rom.name,
                         test_rom.emulator.as_deref()
                     )
                     .await
                     .unwrap()
                     .is_empty());
-                db.upsert_metadata(&test_rom).await.unwrap();
+                db.upsert_metadata(None, &test_rom).await.unwrap();
                 assert_eq!(
                     db.query_metadata(
-                        &test_rom.rom(),
+                        None,
+                        test_rom.rom(),
                         &test_rom.name,
                         test_rom.emulator.as_deref()
                     )
@@ -242,7 +315,7 @@ mod tests {
                     test_rom
                 );
                 assert!(db
-                    .query_metadata(&test_rom.rom(), &test_rom.name, None)
+                    .query_metadata(None, test_rom.rom(), &test_rom.name, None)
                     .await
                     .unwrap()
                     .is_empty());
@@ -252,10 +325,11 @@ mod tests {
                 updated_rom.hash = Md5Hash::from_raw(std::array::from_fn(|n| (n + 0xB) as u8));
                 updated_rom.size = 15;
 
-                db.upsert_metadata(&updated_rom).await.unwrap();
+                db.upsert_metadata(None, &updated_rom).await.unwrap();
                 assert_eq!(
                     db.query_metadata(
-                        &test_rom.rom(),
+                        None,
+                        test_rom.rom(),
                         &test_rom.name,
                         test_rom.emulator.as_deref()
                     )
@@ -275,21 +349,32 @@ mod tests {
                     size: 9,
                 };
                 assert!(db
-                    .query_metadata(&new_rom.rom(), &new_rom.name, new_rom.emulator.as_deref())
+                    .query_metadata(
+                        None,
+                        new_rom.rom(),
+                        &new_rom.name,
+                        new_rom.emulator.as_deref()
+                    )
                     .await
                     .unwrap()
                     .is_empty());
-                db.upsert_metadata(&new_rom).await.unwrap();
+                db.upsert_metadata(None, &new_rom).await.unwrap();
                 assert_eq!(
-                    db.query_metadata(&new_rom.rom(), &new_rom.name, new_rom.emulator.as_deref())
-                        .await
-                        .unwrap(),
+                    db.query_metadata(
+                        None,
+                        new_rom.rom(),
+                        &new_rom.name,
+                        new_rom.emulator.as_deref()
+                    )
+                    .await
+                    .unwrap(),
                     new_rom
                 );
 
                 assert_eq!(
                     db.query_metadata(
-                        &test_rom.rom(),
+                        None,
+                        test_rom.rom(),
                         &test_rom.name,
                         test_rom.emulator.as_deref()
                     )
@@ -297,6 +382,46 @@ mod tests {
                     .unwrap(),
                     updated_rom
                 );
+
+                // Each profile keeps its own history of the same save.
+                let friend = Some("friend");
+                assert!(db
+                    .query_metadata(
+                        friend,
+                        test_rom.rom(),
+                        &test_rom.name,
+                        test_rom.emulator.as_deref()
+                    )
+                    .await
+                    .unwrap()
+                    .is_empty());
+                db.upsert_metadata(friend, &test_rom).await.unwrap();
+                assert_eq!(
+                    db.query_metadata(
+                        friend,
+                        test_rom.rom(),
+                        &test_rom.name,
+                        test_rom.emulator.as_deref()
+                    )
+                    .await
+                    .unwrap(),
+                    test_rom
+                );
+                assert_eq!(
+                    db.query_metadata(
+                        None,
+                        test_rom.rom(),
+                        &test_rom.name,
+                        test_rom.emulator.as_deref()
+                    )
+                    .await
+                    .unwrap(),
+                    updated_rom
+                );
+
+                assert!(!db.is_paused().await.unwrap());
+                db.set_paused(true).await.unwrap();
+                assert!(db.is_paused().await.unwrap());
             });
     }
 }
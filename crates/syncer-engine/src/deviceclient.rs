@@ -0,0 +1,393 @@
+use std::{
+    borrow::Cow,
+    io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
+use syncer_model::{
+    commands::Transfer,
+    config::{Config, SavePolicy, SyncDirection},
+};
+use tokio::{
+    fs::{self, File},
+    io::AsyncReadExt,
+};
+use tracing::debug;
+
+use crate::{
+    md5hash::md5_stream,
+    stores::{LocalSave, LocalStore},
+    utils::timestamp_now,
+    SaveMeta,
+};
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct DeviceMeta {
+    pub path: PathBuf,
+    pub meta: SaveMeta,
+    /// How this save should be synced, according to its `system.saves` entry
+    /// and any matching rules.
+    pub policy: SavePolicy,
+}
+
+impl DeviceMeta {
+    pub fn new(path: PathBuf, meta: SaveMeta) -> Self {
+        Self {
+            path,
+            meta,
+            policy: SavePolicy::default(),
+        }
+    }
+    #[tracing::instrument]
+    pub async fn from_path(path: &Path) -> io::Result<Self> {
+        debug!("Building device-level metadata for save at path {path:?}");
+        let path = path.to_owned();
+        let fs_meta = fs::metadata(&path).await?;
+        let created = unwrap_timestamp(fs_meta.created())?;
+        let updated = unwrap_timestamp(fs_meta.modified())?;
+        let size = fs_meta.size();
+        debug!("Retrieved metadata information. Now building md5 hash...");
+        let byte_stream = stream::try_unfold(File::open(&path).await?, |mut fh| async move {
+            let mut buf = vec![0; 4 * 1024 * 1024];
+            match fh.read(&mut buf).await {
+                Ok(0) => Ok(None),
+                Ok(n) => {
+                    buf.resize(n, 0);
+                    Ok(Some((buf, fh)))
+                }
+                Err(e) => Err(e),
+            }
+        });
+        let hash = md5_stream(byte_stream).await?;
+        debug!("Finished retrieving device save information.");
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let ext = path
+            .extension()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let meta = SaveMeta {
+            rom: None,
+            name,
+            ext,
+            created,
+            updated,
+            hash,
+            size,
+            emulator: None, //TODO: this
+        };
+        Ok(Self::new(path, meta))
+    }
+}
+
+/// The saves found by walking the directories in `system.saves`.
+impl LocalStore for Config {
+    async fn discover(&self) -> Vec<Result<LocalSave, anyhow::Error>> {
+        // Walk the save directories up front and take ownership of each
+        // match; the walk is cheap next to hashing & transfers, and owned
+        // items keep the compiler from needing to prove the concurrent sync
+        // pipeline is `Send` for every possible borrow.
+        self.possible_saves()
+            .map_ok(|(path, root, vars)| LocalSave {
+                path,
+                format: root.format.clone(),
+                vars,
+                direction: root.direction,
+                profile: root.profile.clone(),
+            })
+            .map_err(anyhow::Error::from)
+            .collect()
+            .await
+    }
+
+    fn policy(&self, save: &LocalSave) -> SavePolicy {
+        policy_for_save(self, save)
+    }
+
+    async fn metadata(&self, save: &LocalSave) -> Result<DeviceMeta, anyhow::Error> {
+        let mut device_meta = DeviceMeta::from_path(&save.path).await?;
+        device_meta.meta.apply_format_variables(save.vars.clone())?;
+        device_meta.policy = self.policy(save);
+        Ok(device_meta)
+    }
+
+    async fn backup(&self, save: &DeviceMeta, keep: usize) -> Result<(), anyhow::Error> {
+        let dir = self
+            .system
+            .backup_dir()
+            .ok_or_else(|| anyhow::anyhow!("No backup directory is configured."))?;
+        let mut dir = dir.join(save.meta.emulator.as_deref().unwrap_or("_"));
+        dir.push(save.meta.rom());
+        backup_file(&save.path, &dir, keep).await?;
+        Ok(())
+    }
+}
+
+/// Works out the policy for `save` from its path alone, so it can be done
+/// before the save is read.
+///
+/// This picks the same ROM & emulator as [`SaveMeta::apply_format_variables`]
+/// does for a save read from disk.
+fn policy_for_save(config: &Config, save: &LocalSave) -> SavePolicy {
+    let var = |name: &str| save.vars.get(name).map(String::as_str);
+    config.policy_for(
+        save.direction,
+        save.profile.as_deref(),
+        &rom_for_save(save),
+        var("$EMULATOR"),
+        var("$PLATFORM"),
+    )
+}
+
+/// Works out the ROM `save` belongs to from its path alone.
+fn rom_for_save(save: &LocalSave) -> Cow<'_, str> {
+    let var = |name: &str| {
+        save.vars
+            .get(name)
+            .map(|value| Cow::Borrowed(value.as_str()))
+    };
+    var("$ROM")
+        .or_else(|| var("$NAME"))
+        .or_else(|| save.path.file_stem().map(|stem| stem.to_string_lossy()))
+        .unwrap_or_default()
+}
+
+/// The saves in a [`Config`] that sync with one of its profiles.
+///
+/// Each profile is synced separately, against its own server & with its own
+/// history; this keeps each sync to the saves bound to its profile, without
+/// reading the others.
+pub struct ProfileSaves<'a> {
+    config: &'a Config,
+    profile: Option<&'a str>,
+}
+
+impl<'a> ProfileSaves<'a> {
+    /// The saves in `config` bound to `profile`, where [`None`] is the
+    /// default `[romm]` section.
+    pub fn new(config: &'a Config, profile: Option<&'a str>) -> Self {
+        Self { config, profile }
+    }
+}
+
+impl LocalStore for ProfileSaves<'_> {
+    async fn discover(&self) -> Vec<Result<LocalSave, anyhow::Error>> {
+        let mut saves = self.config.discover().await;
+        saves.retain(|save| match save {
+            Ok(save) => policy_for_save(self.config, save).profile.as_deref() == self.profile,
+            Err(_) => true,
+        });
+        saves
+    }
+
+    fn policy(&self, save: &LocalSave) -> SavePolicy {
+        self.config.policy(save)
+    }
+
+    async fn metadata(&self, save: &LocalSave) -> Result<DeviceMeta, anyhow::Error> {
+        self.config.metadata(save).await
+    }
+
+    async fn backup(&self, save: &DeviceMeta, keep: usize) -> Result<(), anyhow::Error> {
+        self.config.backup(save, keep).await
+    }
+}
+
+/// The saves of another [`LocalStore`], narrowed down to one ROM's and/or
+/// only moved one way.
+///
+/// Used for a last push before the device powers down, when there's no point
+/// replacing saves on it; for a quick pull when it wakes up, before the user
+/// starts playing; and for syncing just the game being launched or exited.
+pub struct ScopedSaves<'a, L> {
+    config: &'a Config,
+    inner: &'a L,
+    transfer: Option<Transfer>,
+    rom: Option<String>,
+}
+
+impl<'a, L: LocalStore> ScopedSaves<'a, L> {
+    /// Wraps `inner`, whose saves are configured by `config`, without
+    /// narrowing it down yet.
+    pub fn new(config: &'a Config, inner: &'a L) -> Self {
+        Self {
+            config,
+            inner,
+            transfer: None,
+            rom: None,
+        }
+    }
+
+    /// Only moves saves the way of `transfer`.
+    pub fn only(mut self, transfer: Transfer) -> Self {
+        self.transfer = Some(transfer);
+        self
+    }
+
+    /// Only syncs the saves for `rom`, as named in the save paths.
+    pub fn rom(mut self, rom: impl Into<String>) -> Self {
+        self.rom = Some(rom.into());
+        self
+    }
+
+    fn includes(&self, save: &LocalSave) -> bool {
+        if self
+            .rom
+            .as_deref()
+            .is_some_and(|rom| rom != rom_for_save(save))
+        {
+            return false;
+        }
+        let direction = policy_for_save(self.config, save).direction;
+        match self.transfer {
+            Some(Transfer::Push) => direction.allows_push(),
+            Some(Transfer::Pull) => direction.allows_pull(),
+            None => true,
+        }
+    }
+
+    /// Narrows `policy` down to only moving saves the way of `transfer`.
+    fn narrow(&self, policy: &mut SavePolicy) {
+        // The other directions already only go one way.
+        if policy.direction == SyncDirection::Bidirectional {
+            match self.transfer {
+                Some(Transfer::Push) => policy.direction = SyncDirection::PushOnly,
+                Some(Transfer::Pull) => policy.direction = SyncDirection::PullOnly,
+                None => {}
+            }
+        }
+    }
+}
+
+impl<L: LocalStore> LocalStore for ScopedSaves<'_, L> {
+    async fn discover(&self) -> Vec<Result<LocalSave, anyhow::Error>> {
+        let mut saves = self.inner.discover().await;
+        saves.retain(|save| match save {
+            Ok(save) => self.includes(save),
+            Err(_) => true,
+        });
+        saves
+    }
+
+    fn policy(&self, save: &LocalSave) -> SavePolicy {
+        let mut policy = self.inner.policy(save);
+        self.narrow(&mut policy);
+        policy
+    }
+
+    async fn metadata(&self, save: &LocalSave) -> Result<DeviceMeta, anyhow::Error> {
+        let mut device_meta = self.inner.metadata(save).await?;
+        self.narrow(&mut device_meta.policy);
+        Ok(device_meta)
+    }
+
+    async fn backup(&self, save: &DeviceMeta, keep: usize) -> Result<(), anyhow::Error> {
+        self.inner.backup(save, keep).await
+    }
+}
+
+/// How backups' timestamps are written in their file names; sorts
+/// chronologically.
+const BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3f";
+
+/// Copies `src` into `dir` under a timestamped name, then deletes all but the
+/// newest `keep` copies of it.
+async fn backup_file(src: &Path, dir: &Path, keep: usize) -> io::Result<()> {
+    let stem = src.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = src
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let prefix = format!("{stem}.");
+    fs::create_dir_all(dir).await?;
+    let timestamp = timestamp_now().format(BACKUP_TIMESTAMP_FORMAT);
+    let dst = dir.join(format!("{prefix}{timestamp}{ext}"));
+    debug!("Backing up {} to {}.", src.display(), dst.display());
+    fs::copy(src, &dst).await?;
+
+    // The timestamps sort chronologically, so the oldest copies come first.
+    let mut copies = Vec::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if is_backup_of(&name, &prefix, &ext) {
+            copies.push(entry.path());
+        }
+    }
+    copies.sort();
+    let excess = copies.len().saturating_sub(keep);
+    for old in &copies[..excess] {
+        debug!("Removing old backup {}.", old.display());
+        fs::remove_file(old).await?;
+    }
+    Ok(())
+}
+
+/// Checks whether `name` is exactly `{prefix}{timestamp}{ext}`, so that eg
+/// backups of `Pokemon.1.srm` aren't mistaken for backups of `Pokemon.srm`.
+fn is_backup_of(name: &str, prefix: &str, ext: &str) -> bool {
+    let Some(timestamp) = name
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_suffix(ext))
+    else {
+        return false;
+    };
+    NaiveDateTime::parse_from_str(timestamp, BACKUP_TIMESTAMP_FORMAT).is_ok()
+}
+
+/// Helper to unwrap a filesystem timestamp, defaulting to the unix epoch on
+/// filesystems that don't support timestamps.
+fn unwrap_timestamp(raw: Result<SystemTime, io::Error>) -> Result<DateTime<Utc>, io::Error> {
+    let systime = match raw {
+        Ok(t) => t,
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => SystemTime::UNIX_EPOCH,
+        Err(e) => {
+            return Err(e);
+        }
+    };
+    Ok(DateTime::from(systime))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syncer_test_support::TempDir;
+
+    #[test]
+    fn test_backups_are_pruned() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let root = TempDir::new("romm-syncer-backups").unwrap();
+                let src = root.path().join("Pokemon Emerald.sav");
+                let dir = root.path().join("backups");
+                // A backup of another save whose name starts the same way.
+                let other = dir.join("Pokemon Emerald.1.20250101T000000.000.sav");
+                fs::create_dir_all(&dir).await.unwrap();
+                fs::write(&other, b"other").await.unwrap();
+                for round in 0..4u8 {
+                    fs::write(&src, [round]).await.unwrap();
+                    backup_file(&src, &dir, 2).await.unwrap();
+                    // Make sure each backup gets a distinct timestamp.
+                    tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+                }
+                let mut kept = Vec::new();
+                let mut entries = fs::read_dir(&dir).await.unwrap();
+                while let Some(entry) = entries.next_entry().await.unwrap() {
+                    if entry.path() != other {
+                        kept.push(entry.path());
+                    }
+                }
+                kept.sort();
+                assert!(fs::try_exists(&other).await.unwrap());
+                assert_eq!(kept.len(), 2);
+                assert_eq!(fs::read(&kept[0]).await.unwrap(), [2]);
+                assert_eq!(fs::read(&kept[1]).await.unwrap(), [3]);
+            });
+    }
+}
@@ -0,0 +1,348 @@
+//! A [`RemoteStore`] that keeps saves in a plain directory, such as a NAS
+//! mount or a USB drive.
+//!
+//! Saves are laid out according to a format string, just like `romm.format`.
+//! Their metadata is kept in a sidecar JSON index at the root of the directory
+//! rather than read back from the filesystem, since copying a file around
+//! doesn't reliably preserve its timestamps; this keeps the three-way decision
+//! in [`decide_action`](crate::syncing::decide_action) working exactly as it
+//! does against ROMM.
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use futures::stream;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::fs;
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::{debug, info, trace};
+
+use syncer_model::path_format_strings::FormatString;
+
+use crate::md5hash::Md5ParseError;
+use crate::stores::{RemoteSave, RemoteStore};
+use crate::utils::{download, EitherError};
+use crate::SaveMeta;
+
+/// The name of the metadata index kept at the root of the directory.
+pub const INDEX_FILE_NAME: &str = ".romm-sync-index.json";
+
+pub struct DirectoryRemote {
+    root: PathBuf,
+    format: FormatString,
+    /// Serializes read-modify-write cycles of the index file.
+    index_lock: AsyncMutex<()>,
+}
+
+/// A save as stored in a [`DirectoryRemote`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DirectorySave {
+    /// Where the save is, relative to the directory root, or `None` if the
+    /// directory doesn't have a copy yet.
+    path: Option<String>,
+    meta: SaveMeta,
+}
+
+impl RemoteSave for DirectorySave {
+    fn meta(&self) -> &SaveMeta {
+        &self.meta
+    }
+}
+
+/// The sidecar metadata index, also used by
+/// [`WebDavRemote`](crate::webdav::WebDavRemote).
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct Index {
+    pub(crate) saves: Vec<IndexEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct IndexEntry {
+    /// Where the save is, relative to the root of the remote.
+    pub(crate) path: String,
+    rom: String,
+    name: String,
+    ext: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    emulator: Option<String>,
+    created: DateTime<Utc>,
+    updated: DateTime<Utc>,
+    md5: String,
+    size: u64,
+    /// The remote's ETag for the file when this entry was written, if it hands
+    /// them out; used to notice the file being replaced behind our back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) etag: Option<String>,
+}
+
+impl IndexEntry {
+    pub(crate) fn new(path: String, meta: &SaveMeta) -> Self {
+        Self {
+            path,
+            rom: meta.rom().to_owned(),
+            name: meta.name.clone(),
+            ext: meta.ext.clone(),
+            emulator: meta.emulator.clone(),
+            created: meta.created,
+            updated: meta.updated,
+            md5: meta.hash.to_string(),
+            size: meta.size,
+            etag: None,
+        }
+    }
+
+    pub(crate) fn is_for(&self, meta: &SaveMeta) -> bool {
+        self.rom == meta.rom() && self.name == meta.name && self.emulator == meta.emulator
+    }
+
+    pub(crate) fn meta(&self) -> Result<SaveMeta, Md5ParseError> {
+        Ok(SaveMeta {
+            rom: Some(self.rom.clone()),
+            name: self.name.clone(),
+            ext: self.ext.clone(),
+            emulator: self.emulator.clone(),
+            created: self.created,
+            updated: self.updated,
+            hash: self.md5.parse()?,
+            size: self.size,
+        })
+    }
+}
+
+/// Where a save without a stored copy should go on a remote laid out by
+/// `format`.
+pub(crate) fn new_save_path(meta: &SaveMeta, format: &FormatString) -> String {
+    // Local formats needn't capture `$ROM`, but the remote's may use it.
+    let meta = SaveMeta {
+        rom: Some(meta.rom().to_owned()),
+        ..meta.clone()
+    };
+    meta.output_target(format)
+}
+
+impl DirectoryRemote {
+    /// Syncs with the directory at `root`, laying saves out according to
+    /// `format`.
+    pub fn new(root: PathBuf, format: FormatString) -> Self {
+        Self {
+            root,
+            format,
+            index_lock: AsyncMutex::new(()),
+        }
+    }
+
+    /// Whether the directory is currently there, eg whether the NAS is
+    /// mounted or the USB drive plugged in.
+    pub async fn is_available(&self) -> bool {
+        fs::metadata(&self.root)
+            .await
+            .is_ok_and(|meta| meta.is_dir())
+    }
+
+    async fn read_index(&self) -> Result<Index, DirectoryError> {
+        let data = match fs::read(self.root.join(INDEX_FILE_NAME)).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Index::default());
+            }
+            Err(e) => {
+                return Err(e.into());
+            }
+        };
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    async fn write_index(&self, index: &Index) -> Result<(), DirectoryError> {
+        let data = serde_json::to_vec_pretty(index)?;
+        write_atomic(data, &self.root.join(INDEX_FILE_NAME)).await
+    }
+
+    /// Resolves a path from the index or format string, making sure it can't
+    /// point outside of the directory.
+    fn resolve(&self, relative: &str) -> Result<PathBuf, DirectoryError> {
+        let relative = Path::new(relative);
+        let is_contained = relative
+            .components()
+            .all(|comp| matches!(comp, Component::Normal(_) | Component::CurDir));
+        if !is_contained || relative.as_os_str().is_empty() {
+            return Err(DirectoryError::OutsideRoot(relative.to_owned()));
+        }
+        Ok(self.root.join(relative))
+    }
+
+    async fn find(&self, local: &SaveMeta) -> Result<DirectorySave, DirectoryError> {
+        let index = self.read_index().await?;
+        if let Some(entry) = index.saves.iter().find(|entry| entry.is_for(local)) {
+            // Someone may have cleaned up the directory by hand; treat the
+            // save as new rather than failing to pull a missing file.
+            if fs::try_exists(self.resolve(&entry.path)?).await? {
+                return Ok(DirectorySave {
+                    path: Some(entry.path.clone()),
+                    meta: entry.meta()?,
+                });
+            }
+            debug!(
+                "Indexed save {} is missing; treating it as new.",
+                entry.path
+            );
+        }
+        Ok(DirectorySave {
+            path: None,
+            meta: SaveMeta::new_empty(
+                local.rom().to_owned(),
+                local.name.clone(),
+                local.ext.clone(),
+                local.emulator.clone(),
+            ),
+        })
+    }
+
+    async fn copy_in(
+        &self,
+        src: &Path,
+        meta: &SaveMeta,
+        save: &DirectorySave,
+    ) -> Result<(), DirectoryError> {
+        let relative = match save.path.clone() {
+            Some(path) => path,
+            None => new_save_path(meta, &self.format),
+        };
+        let dst = self.resolve(&relative)?;
+        info!("Copying save {} to {}.", src.display(), dst.display());
+        let data = fs::read(src).await?;
+        write_atomic(data, &dst).await?;
+
+        let _guard = self.index_lock.lock().await;
+        let mut index = self.read_index().await?;
+        index.saves.retain(|entry| !entry.is_for(meta));
+        index.saves.push(IndexEntry::new(relative, meta));
+        self.write_index(&index).await?;
+        trace!("Updated directory index for {}.", meta.name);
+        Ok(())
+    }
+}
+
+impl RemoteStore for DirectoryRemote {
+    type Save = DirectorySave;
+
+    async fn find_save(&self, local: &SaveMeta) -> Result<Option<DirectorySave>, anyhow::Error> {
+        Ok(Some(self.find(local).await?))
+    }
+
+    async fn pull(&self, save: &DirectorySave, dst: &Path) -> Result<(), anyhow::Error> {
+        let relative = save
+            .path
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Save {} has no stored copy.", save.meta.name))?;
+        let src = self.resolve(relative)?;
+        info!("Copying save {} to {}.", src.display(), dst.display());
+        let data = fs::read(&src).await?;
+        write_atomic(data, dst).await?;
+        Ok(())
+    }
+
+    async fn push(
+        &self,
+        src: &Path,
+        meta: &SaveMeta,
+        save: &DirectorySave,
+    ) -> Result<(), anyhow::Error> {
+        self.copy_in(src, meta, save).await?;
+        Ok(())
+    }
+}
+
+/// Writes `data` to `dst` via a temporary file, creating any missing parent
+/// directories.
+async fn write_atomic(data: Vec<u8>, dst: &Path) -> Result<(), DirectoryError> {
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    download(stream::iter([io::Result::Ok(data)]), dst)
+        .await
+        .map_err(|e| match e {
+            EitherError::A(e) | EitherError::B(e) => e,
+        })?;
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum DirectoryError {
+    #[error("Save path {0:?} points outside of the sync directory")]
+    OutsideRoot(PathBuf),
+    #[error("Corrupt hash in directory index: {0}")]
+    Hash(#[from] Md5ParseError),
+    #[error("Error reading directory index: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{database::SaveMetaDatabase, syncing::run_sync, utils::CancelToken};
+    use syncer_model::config::Config;
+    use syncer_test_support::{TempDir, TestConfig};
+
+    #[test]
+    fn test_directory_roundtrip() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let device = TempDir::new("romm-syncer-device").unwrap();
+                let remote_dir = TempDir::new("romm-syncer-remote").unwrap();
+                let save_dir = device.path().join("saves/gpSP");
+                fs::create_dir_all(&save_dir).await.unwrap();
+                let save_path = save_dir.join("Pokemon Emerald.sav");
+                fs::write(&save_path, b"first").await.unwrap();
+
+                let cfg: Config = toml::from_str(
+                    &TestConfig::new()
+                        .saves(&format!(
+                            "{}/saves/$EMULATOR/$NAME.$EXT",
+                            device.path().display()
+                        ))
+                        .to_toml(),
+                )
+                .unwrap();
+                let remote = DirectoryRemote::new(
+                    remote_dir.path().to_owned(),
+                    "$EMULATOR/$ROM/$NAME.$EXT".into(),
+                );
+                let db = SaveMetaDatabase::new_in_memory().await.unwrap();
+                let cancel = CancelToken::new();
+                let stored = remote_dir
+                    .path()
+                    .join("gpSP/Pokemon Emerald/Pokemon Emerald.sav");
+
+                run_sync(&cfg, &remote, &db, &cfg.system.concurrency, &cancel)
+                    .await
+                    .unwrap();
+                assert_eq!(fs::read(&stored).await.unwrap(), b"first");
+                let index = remote.read_index().await.unwrap();
+                assert_eq!(index.saves.len(), 1);
+
+                // Pretend another device pushed a newer copy.
+                let mut newer = index.saves[0].meta().unwrap();
+                let newer_src = remote_dir.path().join("incoming.sav");
+                fs::write(&newer_src, b"second").await.unwrap();
+                newer.hash = crate::md5hash::md5(&b"second"[..]).unwrap();
+                newer.size = 6;
+                newer.updated = Utc::now();
+                remote
+                    .copy_in(&newer_src, &newer, &remote.find(&newer).await.unwrap())
+                    .await
+                    .unwrap();
+
+                run_sync(&cfg, &remote, &db, &cfg.system.concurrency, &cancel)
+                    .await
+                    .unwrap();
+                assert_eq!(fs::read(&save_path).await.unwrap(), b"second");
+            });
+    }
+}
@@ -0,0 +1,25 @@
+//! The save syncing engine shared by the daemon and anything else that wants to
+//! drive a sync.
+//!
+//! The engine compares a [`LocalStore`](stores::LocalStore), a
+//! [`RemoteStore`](stores::RemoteStore) and a
+//! [`StateStore`](stores::StateStore) and decides which way each save needs to
+//! move; see [`syncing::run_sync`]. Out of the box the config's save
+//! directories act as the local store, [`RommClient`](rommclient::RommClient)
+//! as the remote, and [`SaveMetaDatabase`](database::SaveMetaDatabase) as the
+//! state store. [`DirectoryRemote`](directory::DirectoryRemote) and
+//! [`WebDavRemote`](webdav::WebDavRemote) can stand in for ROMM.
+
+pub mod capabilities;
+pub mod database;
+pub mod deviceclient;
+pub mod directory;
+pub mod md5hash;
+pub mod model;
+pub use model::SaveMeta;
+pub mod openfiles;
+pub mod rommclient;
+pub mod stores;
+pub mod syncing;
+pub mod utils;
+pub mod webdav;
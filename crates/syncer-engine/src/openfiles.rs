@@ -0,0 +1,191 @@
+//! Finds the files other processes have open, so a sync never reads a save an
+//! emulator is halfway through writing, or replaces one under a running game.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use procfs::process::{self, FDTarget};
+use procfs::ProcError;
+use tokio::fs;
+
+/// The files other processes had open at one moment, from `/proc/*/fd`.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct OpenFiles {
+    paths: HashSet<PathBuf>,
+}
+
+impl OpenFiles {
+    /// Lists the files every other process has open.
+    ///
+    /// Processes we aren't allowed to look inside are skipped, so this only
+    /// sees every open file when run as root (as the daemon is on devices).
+    pub async fn snapshot() -> Result<Self, ProcError> {
+        tokio::task::spawn_blocking(|| {
+            let mut paths = HashSet::new();
+            for_each_open_file(|path| {
+                paths.insert(path);
+                false
+            })?;
+            Ok(Self { paths })
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Checks whether some other process had `path` open.
+    pub async fn contains(&self, path: &Path) -> bool {
+        match fs::canonicalize(path).await {
+            Ok(path) => self.paths.contains(&path),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Checks whether some other process has `path` open right now.
+///
+/// Stops looking at the first process found with it open, so this is cheaper
+/// than a full [`OpenFiles::snapshot`] when only one file matters.
+pub async fn is_open(path: &Path) -> Result<bool, ProcError> {
+    // The kernel reports the fully resolved path of each open file.
+    let path = match fs::canonicalize(path).await {
+        Ok(path) => path,
+        Err(_) => return Ok(false),
+    };
+    tokio::task::spawn_blocking(move || for_each_open_file(|open| open == path))
+        .await
+        .unwrap()
+}
+
+/// Calls `found` with the path of each file that another process has open,
+/// stopping as soon as it returns true; returns whether it did.
+fn for_each_open_file(mut found: impl FnMut(PathBuf) -> bool) -> Result<bool, ProcError> {
+    let me = process::Process::myself()?.pid;
+    for proc in process::all_processes()? {
+        // Processes can exit while we're looking at them.
+        let proc = match proc {
+            Ok(proc) => proc,
+            Err(ProcError::NotFound(_) | ProcError::PermissionDenied(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        if proc.pid == me {
+            continue;
+        }
+        let fds = match proc.fd() {
+            Ok(fds) => fds,
+            Err(ProcError::NotFound(_) | ProcError::PermissionDenied(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        // As can the files they have open.
+        for fd in fds.flatten() {
+            if let Some(path) = file_path(fd.target) {
+                if found(path) {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// The file an fd points at, if it's a file rather than a socket, pipe, etc.
+fn file_path(target: FDTarget) -> Option<PathBuf> {
+    match target {
+        FDTarget::Path(path) => Some(path),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::Duration;
+
+    use syncer_test_support::TempDir;
+    use tokio::process::{Child, Command};
+
+    use super::*;
+
+    /// Holds `path` open from another process until the child is dropped.
+    async fn hold_open(path: &Path) -> Child {
+        let child = Command::new("sh")
+            .args(["-c", r#"exec 3<"$0"; exec sleep 30"#])
+            .arg(path)
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let pid = child.id().unwrap() as i32;
+        // Wait for the shell to get as far as opening it.
+        let path = path.canonicalize().unwrap();
+        loop {
+            let fds = process::Process::new(pid).unwrap().fd().unwrap();
+            if fds
+                .flatten()
+                .any(|fd| file_path(fd.target).as_ref() == Some(&path))
+            {
+                return child;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[test]
+    fn test_file_path() {
+        let path = PathBuf::from("/saves/Tetris.sav");
+        assert_eq!(file_path(FDTarget::Path(path.clone())), Some(path));
+        assert_eq!(file_path(FDTarget::Socket(1)), None);
+        assert_eq!(file_path(FDTarget::Pipe(1)), None);
+        assert_eq!(file_path(FDTarget::AnonInode("[eventfd]".into())), None);
+    }
+
+    #[tokio::test]
+    async fn test_is_open() {
+        let dir = TempDir::new("openfiles").unwrap();
+        let open = dir.path().join("Tetris.sav");
+        let closed = dir.path().join("Zelda.sav");
+        std::fs::write(&open, b"tetris").unwrap();
+        std::fs::write(&closed, b"zelda").unwrap();
+        assert!(!is_open(&open).await.unwrap());
+
+        let mut game = hold_open(&open).await;
+        assert!(is_open(&open).await.unwrap());
+        assert!(!is_open(&closed).await.unwrap());
+        assert!(!is_open(&dir.path().join("missing.sav")).await.unwrap());
+        // Paths are matched once resolved, whichever way they're written.
+        let indirect = dir.path().join(".").join("Tetris.sav");
+        assert!(is_open(&indirect).await.unwrap());
+        let link = dir.path().join("link.sav");
+        std::os::unix::fs::symlink(&open, &link).unwrap();
+        assert!(is_open(&link).await.unwrap());
+
+        game.kill().await.unwrap();
+        game.wait().await.unwrap();
+        assert!(!is_open(&open).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_own_files_ignored() {
+        let dir = TempDir::new("openfiles-own").unwrap();
+        let path = dir.path().join("Tetris.sav");
+        let _file = File::create(&path).unwrap();
+        assert!(!is_open(&path).await.unwrap());
+        assert!(!OpenFiles::snapshot().await.unwrap().contains(&path).await);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot() {
+        let dir = TempDir::new("openfiles-snapshot").unwrap();
+        let open = dir.path().join("Tetris.sav");
+        let closed = dir.path().join("Zelda.sav");
+        std::fs::write(&open, b"tetris").unwrap();
+        std::fs::write(&closed, b"zelda").unwrap();
+
+        let mut game = hold_open(&open).await;
+        let snapshot = OpenFiles::snapshot().await.unwrap();
+        game.kill().await.unwrap();
+        game.wait().await.unwrap();
+        // A snapshot keeps what was open when it was taken.
+        assert!(snapshot.contains(&open).await);
+        assert!(!snapshot.contains(&closed).await);
+        assert!(!OpenFiles::snapshot().await.unwrap().contains(&open).await);
+    }
+}
@@ -11,9 +11,11 @@ use reqwest::Error as HttpError;
 use reqwest::{ClientBuilder, Response};
 use romm_api::{DetailedRomSchema, RomSchema, SaveSchema};
 use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use std::io;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 use std::{collections::HashMap, path::Path, sync::RwLock};
 use thiserror::Error;
 use tracing::warn;
@@ -22,6 +24,8 @@ use url::Url;
 
 use syncer_model::path_format_strings::FormatString;
 
+use crate::capabilities::{ServerCapabilities, UnsupportedServerError};
+use crate::stores::{RemoteSave, RemoteStore};
 use crate::utils::download;
 use crate::{
     md5hash::{md5_stream, Md5Hash},
@@ -71,23 +75,131 @@ impl RawClient {
         trace!("Calling GET on ROMM url {n}");
         self.client.get(n.as_str()).send().await?.error_for_status()
     }
+
+    /// Like [`RawClient::raw_get`], but gives up once `timeout` has elapsed
+    /// instead of waiting on the OS-level connection timeout.
+    pub async fn raw_get_with_timeout(
+        &self,
+        endpoint: &str,
+        timeout: Duration,
+    ) -> Result<Response, HttpError> {
+        let n = format!(
+            "{}/{}",
+            self.url_base.as_str().trim_end_matches('/'),
+            endpoint.trim_matches('/')
+        );
+        trace!("Calling GET on ROMM url {n} with timeout {timeout:?}");
+        self.client
+            .get(n.as_str())
+            .timeout(timeout)
+            .send()
+            .await?
+            .error_for_status()
+    }
     pub async fn get<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T, RommError> {
         let data = self.raw_get(endpoint).await?.text().await?;
         serde_json::from_str(&data).map_err(From::from)
     }
 }
 
+/// How long we wait on the server's heartbeat before deciding it is
+/// unreachable.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many roms we ask for per page on servers with paginated endpoints.
+const ROM_PAGE_SIZE: usize = 50;
+
+/// The envelope newer ROMM servers wrap list endpoints in.
+#[derive(Deserialize)]
+struct Page<T> {
+    items: Vec<T>,
+    #[serde(default)]
+    total: Option<usize>,
+}
+
+/// A list endpoint's response: paginated on newer servers, while older ones
+/// ignore the pagination parameters and return everything at once.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Listing<T> {
+    Page(Page<T>),
+    All(Vec<T>),
+}
+
 pub struct RommClient {
     raw: RawClient,
+    /// What the server we're talking to supports.
+    capabilities: ServerCapabilities,
+    /// The format used for naming saves on the server, if any; see
+    /// `romm.format` in the config.
+    format: Option<FormatString>,
     /// Cache of rom name to ROMM ID for quick lookup.
     rom_id_cache: RwLock<HashMap<String, i64>>,
 }
 
 impl RommClient {
+    /// Builds a client without talking to the server, assuming it is the
+    /// oldest version we support.
     pub fn new(url_base: Url, auth_value: String) -> Self {
         let raw = RawClient::new(url_base, auth_value);
         let rom_id_cache = RwLock::new(HashMap::new());
-        Self { raw, rom_id_cache }
+        Self {
+            raw,
+            capabilities: ServerCapabilities::default(),
+            format: None,
+            rom_id_cache,
+        }
+    }
+
+    /// Sets the format used for matching & naming saves on the server when the
+    /// client is used as a [`RemoteStore`].
+    pub fn with_format(mut self, format: Option<FormatString>) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Builds a client after checking the server is reachable and reading its
+    /// version from the heartbeat.
+    ///
+    /// # Errors
+    /// * The server could not be reached; see [`RommError::is_unreachable`].
+    /// * The server is a version we don't support.
+    pub async fn connect(url_base: Url, auth_value: String) -> Result<Self, RommError> {
+        let mut retvl = Self::new(url_base, auth_value);
+        let raw_version = retvl.server_version().await?;
+        retvl.capabilities = ServerCapabilities::negotiate(&raw_version)?;
+        info!(
+            "Connected to ROMM server version {} ({:?}).",
+            raw_version, retvl.capabilities
+        );
+        Ok(retvl)
+    }
+
+    /// What the server supports.
+    pub fn capabilities(&self) -> &ServerCapabilities {
+        &self.capabilities
+    }
+
+    /// Pings the server's heartbeat endpoint and pulls out the version string
+    /// it reports.
+    ///
+    /// We read the heartbeat as raw JSON rather than as a
+    /// [`romm_api::HeartbeatResponse`] since the rest of its shape changes
+    /// between ROMM releases.
+    #[tracing::instrument(skip(self))]
+    pub async fn server_version(&self) -> Result<String, RommError> {
+        let data = self
+            .raw
+            .raw_get_with_timeout("/api/heartbeat", HEARTBEAT_TIMEOUT)
+            .await?
+            .text()
+            .await?;
+        let parsed: serde_json::Value = serde_json::from_str(&data)?;
+        parsed
+            .pointer("/SYSTEM/VERSION")
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_owned())
+            .ok_or(RommError::MissingVersion)
     }
 
     #[tracing::instrument(skip(self))]
@@ -173,8 +285,7 @@ impl RommClient {
             },
         );
         let mut all_found = self
-            .raw
-            .get::<Vec<RomSchema>>(&format!("/api/roms?search_term={encoded}"))
+            .paginated_roms(&format!("/api/roms?search_term={encoded}"))
             .await?;
         let found = match all_found.len() {
             0 | 1 => all_found
@@ -194,6 +305,34 @@ impl RommClient {
         Ok(found)
     }
 
+    /// Collects every page of a rom listing endpoint, or its whole response
+    /// from servers that don't paginate it.
+    async fn paginated_roms(&self, endpoint: &str) -> Result<Vec<RomSchema>, RommError> {
+        let mut retvl = Vec::new();
+        loop {
+            let listing = self
+                .raw
+                .get::<Listing<RomSchema>>(&format!(
+                    "{endpoint}&limit={ROM_PAGE_SIZE}&offset={}",
+                    retvl.len()
+                ))
+                .await?;
+            let page = match listing {
+                Listing::Page(page) => page,
+                Listing::All(all) => return Ok(all),
+            };
+            let page_len = page.items.len();
+            retvl.extend(page.items);
+            let finished = match page.total {
+                Some(total) => retvl.len() >= total,
+                None => page_len < ROM_PAGE_SIZE,
+            };
+            if finished || page_len == 0 {
+                return Ok(retvl);
+            }
+        }
+    }
+
     #[tracing::instrument(skip(self))]
     async fn saves_for_rom(&self, rom: &str) -> Result<Vec<RommSaveMeta>, RommError> {
         let detailed_schema = self
@@ -318,10 +457,24 @@ async fn parse_romm_saves(
     Ok(retvl)
 }
 
+/// Works out a save's hash & size, from its record on servers that hash
+/// saves, or else by downloading it.
 async fn romm_save_md5_size(
     client: &RawClient,
     save: &SaveSchema,
 ) -> Result<(Md5Hash, u64), HttpError> {
+    match save.md5_hash.as_deref().map(str::parse::<Md5Hash>) {
+        Some(Ok(hash)) => {
+            return Ok((hash, save.file_size_bytes as u64));
+        }
+        Some(Err(e)) => {
+            warn!(
+                "Could not parse hash for save {}: {e}; downloading it instead.",
+                save.id
+            );
+        }
+        None => {}
+    }
     let raw_resp = client
         .raw_get(&save.download_path)
         .await?
@@ -372,6 +525,41 @@ impl RommSaveMeta {
     }
 }
 
+impl RemoteSave for RommSaveMeta {
+    fn meta(&self) -> &SaveMeta {
+        &self.meta
+    }
+}
+
+impl RemoteStore for RommClient {
+    type Save = RommSaveMeta;
+
+    async fn find_save(&self, local: &SaveMeta) -> Result<Option<RommSaveMeta>, anyhow::Error> {
+        match self.find_save_matching(local, self.format.as_ref()).await {
+            Ok(found) => Ok(Some(found)),
+            Err(RommError::RomNotFound(_)) => Ok(None),
+            Err(other) => Err(anyhow::anyhow!("Error finding save: {other:?}")),
+        }
+    }
+
+    async fn pull(&self, save: &RommSaveMeta, dst: &Path) -> Result<(), anyhow::Error> {
+        self.pull_save(dst, save).await
+    }
+
+    async fn push(
+        &self,
+        src: &Path,
+        meta: &SaveMeta,
+        save: &RommSaveMeta,
+    ) -> Result<(), anyhow::Error> {
+        let mut mapped = save.clone();
+        mapped.meta = meta.clone();
+        trace!("Pushing new meta: {mapped:?}");
+        self.push_save(src, &mapped, self.format.as_ref()).await?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum RommError {
     #[error("No rom found with name {0}")]
@@ -386,4 +574,19 @@ pub enum RommError {
     Io(#[from] io::Error),
     #[error(transparent)]
     Http(#[from] HttpError),
+    #[error(transparent)]
+    UnsupportedServer(#[from] UnsupportedServerError),
+    #[error("The ROMM server's heartbeat did not report a version")]
+    MissingVersion,
+}
+
+impl RommError {
+    /// Whether this error means we couldn't reach the server at all, as
+    /// opposed to the server rejecting or mangling our request.
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            RommError::Http(e) => e.is_connect() || e.is_timeout(),
+            _ => false,
+        }
+    }
 }
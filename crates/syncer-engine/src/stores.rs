@@ -0,0 +1,119 @@
+//! The extension points the sync engine is built on.
+//!
+//! Every sync compares three views of each save: the copy on the device
+//! ([`LocalStore`]), the copy on the remote ([`RemoteStore`]), and the copy
+//! both sides agreed on the last time the save was synced ([`StateStore`]).
+//! [`run_sync`](crate::syncing::run_sync) only talks to these traits, so a new
+//! backend only needs to implement the relevant trait to reuse the decision
+//! logic in [`decide_action`](crate::syncing::decide_action).
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+use syncer_model::config::{SavePolicy, SyncDirection};
+use syncer_model::path_format_strings::FormatString;
+
+use crate::{deviceclient::DeviceMeta, SaveMeta};
+
+/// A save file found on the device, before it has been read or hashed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LocalSave {
+    /// Where the save is.
+    pub path: PathBuf,
+    /// The format string the path matched; also used to decide where to put
+    /// the save if a newer copy is pulled from the remote.
+    pub format: FormatString,
+    /// The variables pulled out of `path` by `format`.
+    pub vars: HashMap<String, String>,
+    /// Which way the save is allowed to sync.
+    pub direction: SyncDirection,
+    /// The named profile the save's root is bound to, if any.
+    pub profile: Option<String>,
+}
+
+/// The saves on the local device.
+pub trait LocalStore: Send + Sync {
+    /// Finds every local save that should be synced.
+    ///
+    /// An error finding one save doesn't stop the others from being synced.
+    fn discover(&self) -> impl Future<Output = Vec<Result<LocalSave, anyhow::Error>>> + Send;
+
+    /// Works out which policy applies to a local save from its path alone, so
+    /// saves a rule turns off can be skipped without reading them.
+    fn policy(&self, save: &LocalSave) -> SavePolicy;
+
+    /// Reads and hashes a local save, applying any variables pulled from its
+    /// path and working out which policy applies to it.
+    fn metadata(
+        &self,
+        save: &LocalSave,
+    ) -> impl Future<Output = Result<DeviceMeta, anyhow::Error>> + Send;
+
+    /// Copies a local save somewhere safe before a pull replaces it, keeping
+    /// at most `keep` copies of it.
+    fn backup(
+        &self,
+        save: &DeviceMeta,
+        keep: usize,
+    ) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+}
+
+/// A save as known to a [`RemoteStore`].
+pub trait RemoteSave: Debug + Send + Sync {
+    /// The save's metadata, or an [empty](SaveMeta::new_empty) record if the
+    /// remote doesn't have a copy of the save yet.
+    fn meta(&self) -> &SaveMeta;
+}
+
+/// Somewhere saves are synced to and from, such as a ROMM server.
+pub trait RemoteStore: Send + Sync {
+    /// The remote's handle to a save, including whatever it needs to pull or
+    /// overwrite that save later.
+    type Save: RemoteSave;
+
+    /// Finds the remote copy of a local save.
+    ///
+    /// Returns `Ok(None)` if the remote has nowhere to put the save (eg ROMM
+    /// doesn't know about its rom), in which case the save is skipped.
+    fn find_save(
+        &self,
+        local: &SaveMeta,
+    ) -> impl Future<Output = Result<Option<Self::Save>, anyhow::Error>> + Send;
+
+    /// Downloads `save` to `dst`, replacing whatever is already there.
+    ///
+    /// If the returned future is dropped before it completes, `dst` must be
+    /// left untouched.
+    fn pull(
+        &self,
+        save: &Self::Save,
+        dst: &Path,
+    ) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+
+    /// Uploads the local file `src`, described by `meta`, as the new version
+    /// of `save`.
+    fn push(
+        &self,
+        src: &Path,
+        meta: &SaveMeta,
+        save: &Self::Save,
+    ) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+}
+
+/// The record of what each save looked like the last time it was synced.
+pub trait StateStore: Send + Sync {
+    /// The metadata `save` had when it was last synced, or an
+    /// [empty](SaveMeta::new_empty) record if it never has been.
+    fn last_synced(
+        &self,
+        save: &SaveMeta,
+    ) -> impl Future<Output = Result<SaveMeta, anyhow::Error>> + Send;
+
+    /// Records that both sides now hold the save described by `meta`.
+    fn record_synced(
+        &self,
+        meta: &SaveMeta,
+    ) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+}
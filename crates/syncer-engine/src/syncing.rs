@@ -0,0 +1,1119 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use futures::{stream, StreamExt};
+use thiserror::Error;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard, Semaphore};
+use tracing::{info, trace, warn};
+
+use syncer_model::commands::{SuppressedSync, Transfer};
+use syncer_model::config::{ConcurrencyConfig, ConflictPolicy, SavePolicy, SyncDirection};
+use syncer_model::path_format_strings::FormatString;
+
+use crate::{
+    deviceclient::DeviceMeta,
+    model::SaveMeta,
+    openfiles,
+    stores::{LocalSave, LocalStore, RemoteSave, RemoteStore, StateStore},
+    utils::CancelToken,
+};
+
+/// Bounds how much work a single [`run_sync`] does at once.
+pub struct SyncLimits {
+    /// Permits for hashing local save files.
+    hashing: Semaphore,
+    /// Permits for looking up save metadata on the remote.
+    metadata: Semaphore,
+    /// Permits for uploading or downloading a save.
+    transfers: Semaphore,
+    /// Per-ROM locks so that two saves for the same ROM never race each other.
+    roms: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+}
+
+impl SyncLimits {
+    pub fn new(cfg: &ConcurrencyConfig) -> Self {
+        Self {
+            hashing: Semaphore::new(cfg.hashing()),
+            metadata: Semaphore::new(cfg.metadata()),
+            transfers: Semaphore::new(cfg.transfers()),
+            roms: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The most saves worth having in flight at once; any more would just be
+    /// waiting on a permit.
+    pub fn max_in_flight(&self) -> usize {
+        self.hashing.available_permits()
+            + self.metadata.available_permits()
+            + self.transfers.available_permits()
+    }
+
+    /// Waits until no other save for `rom` is being synced.
+    pub async fn lock_rom(&self, rom: &str) -> OwnedMutexGuard<()> {
+        let lock = self
+            .roms
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(rom.to_owned())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+}
+
+/// Returned by [`run_sync`] when it stops early because its [`CancelToken`] was
+/// cancelled.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("Sync was cancelled")]
+pub struct SyncCancelled;
+
+/// Returned when syncing a save is put off because another process, such as
+/// the emulator playing it, has it open.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("{path:?} is open in another process")]
+pub struct SaveInUse {
+    pub path: PathBuf,
+}
+
+/// What a completed [`run_sync`] did that is worth telling the user about.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SyncReport {
+    /// The transfers skipped because of their save root's [`SyncDirection`].
+    pub suppressed: Vec<SuppressedSync>,
+    /// The saves skipped because another process had them open; see
+    /// [`SaveInUse`].
+    pub in_use: Vec<PathBuf>,
+}
+
+/// Syncs every save in `local` with its copy in `remote`, using `state` to
+/// work out which side changed since the last sync.
+///
+/// Cancelling `cancel` stops the sync cooperatively: saves that haven't started
+/// yet are skipped, and saves that are mid-flight are dropped at their next
+/// await point. [`RemoteStore::pull`] must leave its destination untouched when
+/// dropped and uploads only land on the remote once complete, so a cancelled
+/// save is left exactly as it was before the sync; at worst the sync state is
+/// left behind, which the next sync repairs via [`SyncDecision::ResyncDb`].
+///
+/// Saves that another process has open are skipped and listed in the report
+/// instead, so a save is never read while it's being written or replaced under
+/// a running game.
+pub async fn run_sync<L, R, S>(
+    local: &L,
+    remote: &R,
+    state: &S,
+    concurrency: &ConcurrencyConfig,
+    cancel: &CancelToken,
+) -> Result<SyncReport, anyhow::Error>
+where
+    L: LocalStore,
+    R: RemoteStore,
+    S: StateStore,
+{
+    let limits = SyncLimits::new(concurrency);
+    let limits = &limits;
+    let discovered = local.discover().await;
+    let results = stream::iter(discovered)
+        .map(|save| async move {
+            let save = save?;
+            if cancel.is_cancelled() {
+                return Err(SyncCancelled.into());
+            }
+            cancel
+                .run(sync_save(save, local, remote, state, limits))
+                .await
+                .unwrap_or_else(|| Err(SyncCancelled.into()))
+        })
+        .buffer_unordered(limits.max_in_flight());
+    let results = results.collect::<Vec<_>>().await;
+    if cancel.is_cancelled() {
+        info!("Sync cancelled.");
+        return Err(SyncCancelled.into());
+    }
+
+    let mut report = SyncReport::default();
+    let mut errors = Vec::new();
+    for res in results {
+        match res {
+            Ok(suppressed) => report.suppressed.extend(suppressed),
+            Err(e) => match e.downcast::<SaveInUse>() {
+                Ok(in_use) => report.in_use.push(in_use.path),
+                Err(e) => errors.push(e),
+            },
+        }
+    }
+    // TODO: Do something with the rest of the errors
+    errors.pop().map_or(Ok(report), Err)
+}
+
+/// Hashes a single local save and then syncs it while holding its ROM's lock.
+async fn sync_save<L, R, S>(
+    save: LocalSave,
+    local: &L,
+    remote: &R,
+    state: &S,
+    limits: &SyncLimits,
+) -> Result<Option<SuppressedSync>, anyhow::Error>
+where
+    L: LocalStore,
+    R: RemoteStore,
+    S: StateStore,
+{
+    if !local.policy(&save).enabled {
+        trace!("Syncing {:?} is disabled by a rule.", save.path);
+        return Ok(None);
+    }
+    let device_meta = {
+        let _permit = limits.hashing.acquire().await?;
+        // Checked only once we're about to hash it, as saves open & close
+        // throughout a long sync.
+        if save_in_use(&save.path).await {
+            info!(
+                "{:?} is open in another process; leaving it for now.",
+                save.path
+            );
+            return Err(SaveInUse { path: save.path }.into());
+        }
+        local.metadata(&save).await?
+    };
+    let _rom_guard = limits.lock_rom(device_meta.meta.rom()).await;
+    run_sync_for_save(&device_meta, &save.format, local, remote, state, limits).await
+}
+
+/// Syncs a single save, returning the transfer its [`SyncDirection`] kept us
+/// from making, if any.
+pub async fn run_sync_for_save<L, R, S>(
+    device_meta: &DeviceMeta,
+    device_format: &FormatString,
+    local: &L,
+    remote: &R,
+    state: &S,
+    limits: &SyncLimits,
+) -> Result<Option<SuppressedSync>, anyhow::Error>
+where
+    L: LocalStore,
+    R: RemoteStore,
+    S: StateStore,
+{
+    trace!(
+        "Starting decision making tree for path {}",
+        device_meta.path.display()
+    );
+    let remote_save = {
+        let _permit = limits.metadata.acquire().await?;
+        remote.find_save(&device_meta.meta).await?
+    };
+    let Some(remote_save) = remote_save else {
+        warn!(
+            "Missing rom in remote for local file {}",
+            device_meta.meta.rom()
+        );
+        return Ok(None);
+    };
+
+    let db_data = state.last_synced(&device_meta.meta).await?;
+    let direction = device_meta.policy.direction;
+    let decision = decide_directed_action(
+        &device_meta.meta,
+        remote_save.meta(),
+        &db_data,
+        &device_meta.policy,
+    )?;
+    let suppressed = decision.suppressed.and_then(|suppressed| {
+        info!(
+            "{:?} => {:?} suppressed by sync direction {:?}",
+            device_meta.path, suppressed, direction
+        );
+        Some(SuppressedSync {
+            path: device_meta.path.clone(),
+            direction,
+            transfer: suppressed.target()?.into(),
+        })
+    });
+    let action = decision.action;
+    let _permit = match action.target() {
+        Some(_) => Some(limits.transfers.acquire().await?),
+        None => None,
+    };
+    // The save may have been opened since it was hashed, eg by a game that was
+    // launched while we waited for a transfer permit. A pull can also write to
+    // a different file than the one we found, if the remote save's name maps
+    // elsewhere.
+    let mut touched = Vec::new();
+    if action.target().is_some() {
+        touched.push(device_meta.path.clone());
+    }
+    if action.target() == Some(PushTarget::Device) {
+        let target = PathBuf::from(remote_save.meta().output_target(device_format));
+        if target != device_meta.path {
+            touched.push(target);
+        }
+    }
+    for path in touched {
+        if save_in_use(&path).await {
+            info!("{path:?} was opened in another process; leaving it for now.");
+            return Err(SaveInUse { path }.into());
+        }
+    }
+    perform_action(
+        &action,
+        device_meta,
+        device_format,
+        &remote_save,
+        local,
+        remote,
+        state,
+    )
+    .await?;
+    Ok(suppressed)
+}
+
+/// Checks whether another process has the save at `path` open, assuming not if
+/// we can't tell.
+async fn save_in_use(path: &Path) -> bool {
+    openfiles::is_open(path).await.unwrap_or_else(|e| {
+        warn!("Error checking whether {path:?} is open: {e:?}");
+        false
+    })
+}
+
+pub async fn perform_action<L, R, S>(
+    action: &SyncDecision,
+    device_meta: &DeviceMeta,
+    device_format: &FormatString,
+    remote_save: &R::Save,
+    local: &L,
+    remote: &R,
+    state: &S,
+) -> Result<(), anyhow::Error>
+where
+    L: LocalStore,
+    R: RemoteStore,
+    S: StateStore,
+{
+    info!("{:?} ({:?}) => {:?}", device_meta.path, remote_save, action);
+    let new_meta = match action.target() {
+        Some(PushTarget::Device) => {
+            let keep = device_meta.policy.backups;
+            if keep > 0 && !device_meta.meta.is_empty() {
+                local.backup(device_meta, keep).await?;
+            }
+            let target = remote_save.meta().output_target(device_format);
+            remote.pull(remote_save, Path::new(&target)).await?;
+            remote_save.meta()
+        }
+        Some(PushTarget::Remote) => {
+            remote
+                .push(&device_meta.path, &device_meta.meta, remote_save)
+                .await?;
+            &device_meta.meta
+        }
+        None => {
+            // Should be equivalent, default to device arbitrarily
+            &device_meta.meta
+        }
+    };
+    if action.needs_db_resync() {
+        state.record_synced(new_meta).await?;
+    }
+    Ok(())
+}
+
+/// The full list of syncing decisions we could make between the local save
+/// file, the sync history database, and the remote ROMM save.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Default)]
+pub enum SyncDecision {
+    /// Do nothing; everything is in sync.
+    #[default]
+    Noop,
+    /// Push the save file to the remote ROMM server.
+    ///
+    /// Implies a database resync after the file is pulled.
+    PushToRemote,
+    /// Pull the save file from ROMM into the device.
+    ///
+    /// Implies a database resync after the save file is pulled.
+    PullToDevice,
+    /// The local save fiile and remote ROMM save are the same, but the local
+    /// sync DB is out of sync; fix the DB without touching either file.
+    ///
+    /// Note that this state implies something weird is going on; the user might
+    /// have manually uploaded/download a file, or a database corruption
+    /// occured, or a remote pull failed before resyncing the database.
+    ResyncDb,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum PushTarget {
+    /// We need to pull the save FROM the remote romm server TO the local
+    /// device.
+    Device,
+    /// We need to push the save FROM the local device TO the remote romm
+    /// server.
+    Remote,
+}
+
+impl From<PushTarget> for Transfer {
+    fn from(target: PushTarget) -> Self {
+        match target {
+            PushTarget::Device => Transfer::Pull,
+            PushTarget::Remote => Transfer::Push,
+        }
+    }
+}
+
+impl SyncDecision {
+    /// Where will the new save be pushed to, if that is needed?
+    pub const fn target(&self) -> Option<PushTarget> {
+        use SyncDecision::*;
+        match self {
+            Noop | ResyncDb => None,
+            PushToRemote => Some(PushTarget::Remote),
+            PullToDevice => Some(PushTarget::Device),
+        }
+    }
+    /// Do we need to update the local sync database?
+    pub const fn needs_db_resync(&self) -> bool {
+        !matches!(self, SyncDecision::Noop)
+    }
+}
+
+#[tracing::instrument]
+pub fn decide_action(
+    device_save: &SaveMeta,
+    remote_save: &SaveMeta,
+    in_db: &SaveMeta,
+) -> Result<SyncDecision, anyhow::Error> {
+    // New save on device; push.
+    if remote_save.is_empty() && !device_save.is_empty() {
+        return Ok(SyncDecision::PushToRemote);
+    }
+
+    // New save on remote; pull.
+    if device_save.is_empty() && !remote_save.is_empty() {
+        return Ok(SyncDecision::PullToDevice);
+    }
+
+    let device_is_db = device_save.same_file(in_db);
+    let remote_is_db = remote_save.same_file(in_db);
+    let device_is_remote = device_save.same_file(remote_save);
+
+    match (device_is_db, remote_is_db) {
+        // Everything is in sync; do nothing.
+        (true, true) => Ok(SyncDecision::Noop),
+        // The remote & device are in sync, but the database hasn't encountered
+        // either file; this means the user probably did a manual sync at some
+        // point. Update the database and move on.
+        (false, false) if device_is_remote => Ok(SyncDecision::ResyncDb),
+        // The database has seen the local file before, but not the remote; this
+        // implies the remote SHOULD have been created later.
+        //
+        // If not, we error since that implies the user has been manually
+        // messing with things.
+        (true, false) => {
+            if device_save.timestamp() < remote_save.timestamp() {
+                Ok(SyncDecision::PullToDevice)
+            } else {
+                Err(SyncConflict::RemoteNotNewer.into())
+            }
+        }
+        // The database has seen the remote file before, but not the local one; this
+        // implies the local file SHOULD have been created later.
+        //
+        // If not, we error since that implies the user has been manually
+        // messing with things.
+        (false, true) => {
+            if device_save.timestamp() > remote_save.timestamp() {
+                Ok(SyncDecision::PushToRemote)
+            } else {
+                Err(SyncConflict::DeviceNotNewer.into())
+            }
+        }
+        // None of the database, local file, or remote file are in sync; a
+        // conflict has occured that will require manual intervention.
+        (false, false) => Err(SyncConflict::Diverged.into()),
+    }
+}
+
+/// Returned by [`decide_action`] when it can't tell which copy of a save
+/// should win.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum SyncConflict {
+    /// Only the remote changed since the last sync, but not after the device.
+    #[error("TIMESTAMP: device >= remote, but not expected.")]
+    RemoteNotNewer,
+    /// Only the device changed since the last sync, but not after the remote.
+    #[error("TIMESTAMP: device <= remote, but not expected.")]
+    DeviceNotNewer,
+    /// Both copies changed since the last sync.
+    #[error("CONFLICT")]
+    Diverged,
+}
+
+/// What to do with a save once its [`SavePolicy`] is taken into account.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Default)]
+pub struct DirectedDecision {
+    /// What to actually do.
+    pub action: SyncDecision,
+    /// What [`decide_action`] would have done instead, if the direction
+    /// didn't allow it.
+    pub suppressed: Option<SyncDecision>,
+}
+
+/// Like [`decide_action`], but resolves conflicts according to the policy's
+/// [`ConflictPolicy`] and only ever moves saves the way its [`SyncDirection`]
+/// allows.
+pub fn decide_directed_action(
+    device_save: &SaveMeta,
+    remote_save: &SaveMeta,
+    in_db: &SaveMeta,
+    policy: &SavePolicy,
+) -> Result<DirectedDecision, anyhow::Error> {
+    let direction = policy.direction;
+    if direction == SyncDirection::MirrorFromRemote {
+        return Ok(decide_mirror_action(device_save, remote_save, in_db));
+    }
+    let action = match decide_action(device_save, remote_save, in_db) {
+        Ok(action) => action,
+        Err(e) => {
+            let Some(conflict) = e.downcast_ref::<SyncConflict>() else {
+                return Err(e);
+            };
+            let resolved = resolve_conflict(device_save, remote_save, policy.conflict);
+            info!(
+                "{conflict} resolved by {:?} => {resolved:?}",
+                policy.conflict
+            );
+            resolved.ok_or(e)?
+        }
+    };
+    let allowed = match action.target() {
+        Some(PushTarget::Remote) => direction.allows_push(),
+        Some(PushTarget::Device) => direction.allows_pull(),
+        None => true,
+    };
+    if allowed {
+        Ok(DirectedDecision {
+            action,
+            suppressed: None,
+        })
+    } else {
+        Ok(DirectedDecision {
+            action: SyncDecision::Noop,
+            suppressed: Some(action),
+        })
+    }
+}
+
+/// Picks a side in a [`SyncConflict`], if `policy` lets us.
+fn resolve_conflict(
+    device_save: &SaveMeta,
+    remote_save: &SaveMeta,
+    policy: ConflictPolicy,
+) -> Option<SyncDecision> {
+    match policy {
+        ConflictPolicy::Error => None,
+        ConflictPolicy::PreferDevice => Some(SyncDecision::PushToRemote),
+        ConflictPolicy::PreferRemote => Some(SyncDecision::PullToDevice),
+        ConflictPolicy::PreferNewest => {
+            match device_save.timestamp().cmp(&remote_save.timestamp()) {
+                std::cmp::Ordering::Greater => Some(SyncDecision::PushToRemote),
+                std::cmp::Ordering::Less => Some(SyncDecision::PullToDevice),
+                std::cmp::Ordering::Equal => None,
+            }
+        }
+    }
+}
+
+/// The remote always wins; any local change it hasn't seen is discarded,
+/// which we report as a suppressed push.
+fn decide_mirror_action(
+    device_save: &SaveMeta,
+    remote_save: &SaveMeta,
+    in_db: &SaveMeta,
+) -> DirectedDecision {
+    if device_save.same_file(remote_save) {
+        let action = if device_save.same_file(in_db) {
+            SyncDecision::Noop
+        } else {
+            SyncDecision::ResyncDb
+        };
+        return DirectedDecision {
+            action,
+            suppressed: None,
+        };
+    }
+    let changed_locally = !device_save.is_empty() && !device_save.same_file(in_db);
+    DirectedDecision {
+        // Nothing to mirror if the remote has no copy, and we never delete
+        // local saves.
+        action: if remote_save.is_empty() {
+            SyncDecision::Noop
+        } else {
+            SyncDecision::PullToDevice
+        },
+        suppressed: changed_locally.then_some(SyncDecision::PushToRemote),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::SaveMetaDatabase,
+        deviceclient::{ProfileSaves, ScopedSaves},
+        rommclient::RommClient,
+    };
+    use syncer_model::config::Config;
+    use syncer_test_support::{FakeRommServer, TempDir, TestConfig};
+
+    /// Pushes a new local save, checks a second sync is a no-op, then pulls a
+    /// newer copy from the server.
+    async fn sync_roundtrip(legacy: bool) {
+        let server = if legacy {
+            FakeRommServer::start_legacy().await.unwrap()
+        } else {
+            FakeRommServer::start().await.unwrap()
+        };
+        let rom_id = server.add_rom("Pokemon Emerald.gba");
+
+        let root = TempDir::new("romm-syncer-e2e").unwrap();
+        let save_dir = root.path().join("saves/gpSP");
+        tokio::fs::create_dir_all(&save_dir).await.unwrap();
+        let save_path = save_dir.join("Pokemon Emerald.sav");
+        tokio::fs::write(&save_path, b"first").await.unwrap();
+
+        let cfg: Config = toml::from_str(
+            &TestConfig::new()
+                .romm(server.url(), "Bearer test")
+                .saves(&format!(
+                    "{}/saves/$EMULATOR/$NAME.$EXT",
+                    root.path().display()
+                ))
+                .to_toml(),
+        )
+        .unwrap();
+        let cl = RommClient::connect(server.url(), "Bearer test".to_owned())
+            .await
+            .unwrap();
+        let db = SaveMetaDatabase::new_in_memory().await.unwrap();
+        let cancel = CancelToken::new();
+
+        run_sync(&cfg, &cl, &db, &cfg.system.concurrency, &cancel)
+            .await
+            .unwrap();
+        let saves = server.saves();
+        assert_eq!(saves.len(), 1);
+        assert_eq!(saves[0].rom_id, rom_id);
+        assert_eq!(saves[0].file_name, "Pokemon Emerald.sav");
+        assert_eq!(saves[0].emulator.as_deref(), Some("gpSP"));
+        assert_eq!(saves[0].data, b"first");
+
+        run_sync(&cfg, &cl, &db, &cfg.system.concurrency, &cancel)
+            .await
+            .unwrap();
+        assert_eq!(server.saves(), saves);
+
+        server.add_save(rom_id, "Pokemon Emerald.sav", Some("gpSP"), "second");
+        run_sync(&cfg, &cl, &db, &cfg.system.concurrency, &cancel)
+            .await
+            .unwrap();
+        assert_eq!(tokio::fs::read(&save_path).await.unwrap(), b"second");
+        assert_eq!(server.saves().len(), 1);
+
+        // A push-only sync never pulls, even when the server has a newer copy,
+        // while a pull-only one does.
+        server.add_save(rom_id, "Pokemon Emerald.sav", Some("gpSP"), "third");
+        let push_only = ScopedSaves::new(&cfg, &cfg).only(Transfer::Push);
+        let report = run_sync(&push_only, &cl, &db, &cfg.system.concurrency, &cancel)
+            .await
+            .unwrap();
+        assert_eq!(tokio::fs::read(&save_path).await.unwrap(), b"second");
+        assert_eq!(report.suppressed.len(), 1);
+        let pull_only = ScopedSaves::new(&cfg, &cfg).only(Transfer::Pull);
+        run_sync(&pull_only, &cl, &db, &cfg.system.concurrency, &cancel)
+            .await
+            .unwrap();
+        assert_eq!(tokio::fs::read(&save_path).await.unwrap(), b"third");
+    }
+
+    /// Syncs two profiles, each bound to different saves, against their own
+    /// servers.
+    async fn sync_profiles() {
+        let home = FakeRommServer::start().await.unwrap();
+        let friend = FakeRommServer::start().await.unwrap();
+        home.add_rom("Pokemon Emerald.gba");
+        home.add_rom("Tetris.gb");
+        let friend_emerald = friend.add_rom("Pokemon Emerald.gba");
+        let friend_tetris = friend.add_rom("Tetris.gb");
+
+        let root = TempDir::new("romm-syncer-profiles").unwrap();
+        for dir in ["personal/gpSP", "shared/gpSP"] {
+            tokio::fs::create_dir_all(root.path().join(dir))
+                .await
+                .unwrap();
+        }
+        let personal = root.path().join("personal/gpSP/Pokemon Emerald.sav");
+        let shared = root.path().join("shared/gpSP/Pokemon Emerald.sav");
+        let tetris = root.path().join("personal/gpSP/Tetris.sav");
+        tokio::fs::write(&personal, b"personal").await.unwrap();
+        tokio::fs::write(&shared, b"shared").await.unwrap();
+        tokio::fs::write(&tetris, b"tetris").await.unwrap();
+
+        let root_dir = root.path().display();
+        let cfg: Config = toml::from_str(
+            &TestConfig::new()
+                .romm(home.url(), "Bearer home")
+                .profile("friend", friend.url(), "Bearer friend")
+                .saves(&format!("{root_dir}/personal/$EMULATOR/$NAME.$EXT"))
+                .profile_saves(&format!("{root_dir}/shared/$EMULATOR/$NAME.$EXT"), "friend")
+                .system("database", &format!("{root_dir}/db.sqlite"))
+                .raw("[[rules]]\nrom = \"Tetris\"\nprofile = \"friend\"\n")
+                .to_toml(),
+        )
+        .unwrap();
+        cfg.validate().unwrap();
+        assert_eq!(
+            cfg.used_profiles().into_iter().collect::<Vec<_>>(),
+            [None, Some("friend")]
+        );
+
+        let db = SaveMetaDatabase::new_in_memory().await.unwrap();
+        let cancel = CancelToken::new();
+        for (name, server, api_key) in [
+            (None, &home, "Bearer home"),
+            (Some("friend"), &friend, "Bearer friend"),
+        ] {
+            let cl = RommClient::connect(server.url(), api_key.to_owned())
+                .await
+                .unwrap();
+            let saves = ProfileSaves::new(&cfg, name);
+            run_sync(
+                &saves,
+                &cl,
+                &db.profile(name),
+                &cfg.system.concurrency,
+                &cancel,
+            )
+            .await
+            .unwrap();
+        }
+        let data = |server: &FakeRommServer| {
+            let mut data = server
+                .saves()
+                .into_iter()
+                .map(|save| save.data)
+                .collect::<Vec<_>>();
+            data.sort();
+            data
+        };
+        assert_eq!(data(&home), [b"personal".to_vec()]);
+        assert_eq!(data(&friend), [b"shared".to_vec(), b"tetris".to_vec()]);
+        // Each profile has its own history of the same save.
+        for (name, data) in [(None, b"personal".as_slice()), (Some("friend"), b"shared")] {
+            let synced = db
+                .query_metadata(name, "Pokemon Emerald", "Pokemon Emerald", Some("gpSP"))
+                .await
+                .unwrap();
+            assert_eq!(synced.hash, crate::md5hash::md5(data).unwrap());
+        }
+
+        // A sync scoped to one ROM leaves the others' saves alone.
+        friend.add_save(
+            friend_emerald,
+            "Pokemon Emerald.sav",
+            Some("gpSP"),
+            "shared 2",
+        );
+        friend.add_save(friend_tetris, "Tetris.sav", Some("gpSP"), "tetris 2");
+        let cl = RommClient::connect(friend.url(), "Bearer friend".to_owned())
+            .await
+            .unwrap();
+        let saves = ProfileSaves::new(&cfg, Some("friend"));
+        let tetris_only = ScopedSaves::new(&cfg, &saves).rom("Tetris");
+        let history = db.profile(Some("friend"));
+        run_sync(
+            &tetris_only,
+            &cl,
+            &history,
+            &cfg.system.concurrency,
+            &cancel,
+        )
+        .await
+        .unwrap();
+        assert_eq!(tokio::fs::read(&tetris).await.unwrap(), b"tetris 2");
+        assert_eq!(tokio::fs::read(&shared).await.unwrap(), b"shared");
+    }
+
+    /// Holds a save open from another process, as a running game would, and
+    /// checks it's left alone until it's closed.
+    async fn sync_open_save() {
+        let server = FakeRommServer::start().await.unwrap();
+        server.add_rom("Pokemon Emerald.gba");
+        server.add_rom("Tetris.gb");
+
+        let root = TempDir::new("romm-syncer-open").unwrap();
+        let save_dir = root.path().join("saves/gpSP");
+        tokio::fs::create_dir_all(&save_dir).await.unwrap();
+        let save_path = save_dir.join("Pokemon Emerald.sav");
+        tokio::fs::write(&save_path, b"playing").await.unwrap();
+        // Saves a rule turns off never count as in use, open or not.
+        let disabled_path = save_dir.join("Tetris.sav");
+        tokio::fs::write(&disabled_path, b"disabled").await.unwrap();
+        let cfg: Config = toml::from_str(
+            &TestConfig::new()
+                .romm(server.url(), "Bearer test")
+                .saves(&format!(
+                    "{}/saves/$EMULATOR/$NAME.$EXT",
+                    root.path().display()
+                ))
+                .raw("[[rules]]\nrom = \"Tetris\"\nenabled = false\n")
+                .to_toml(),
+        )
+        .unwrap();
+        let cl = RommClient::connect(server.url(), "Bearer test".to_owned())
+            .await
+            .unwrap();
+        let db = SaveMetaDatabase::new_in_memory().await.unwrap();
+        let cancel = CancelToken::new();
+
+        let hold_open = |path: &Path| {
+            tokio::process::Command::new("sh")
+                .args(["-c", r#"exec 3<"$0"; exec sleep 30"#])
+                .arg(path)
+                .kill_on_drop(true)
+                .spawn()
+                .unwrap()
+        };
+        let mut game = hold_open(&save_path);
+        let _disabled_game = hold_open(&disabled_path);
+        for path in [&save_path, &disabled_path] {
+            while !openfiles::is_open(path).await.unwrap() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        }
+        let report = run_sync(&cfg, &cl, &db, &cfg.system.concurrency, &cancel)
+            .await
+            .unwrap();
+        assert_eq!(report.in_use, [save_path.as_path()]);
+        assert!(server.saves().is_empty());
+
+        game.kill().await.unwrap();
+        let report = run_sync(&cfg, &cl, &db, &cfg.system.concurrency, &cancel)
+            .await
+            .unwrap();
+        assert!(report.in_use.is_empty());
+        assert_eq!(server.saves().len(), 1);
+        assert_eq!(server.saves()[0].data, b"playing");
+    }
+
+    /// Counts how many calls of each kind are in flight at once, and whether
+    /// two calls for the same rom ever were.
+    #[derive(Default)]
+    struct Probe {
+        active: Mutex<HashMap<&'static str, usize>>,
+        peaks: Mutex<HashMap<&'static str, usize>>,
+        roms: Mutex<HashMap<String, usize>>,
+        rom_overlaps: Mutex<usize>,
+        calls: Mutex<HashMap<&'static str, usize>>,
+    }
+
+    impl Probe {
+        /// Records a call of kind `kind` that takes a little while, tracking
+        /// `rom` as busy for its duration if given.
+        async fn measure(&self, kind: &'static str, rom: Option<&str>) {
+            {
+                let mut active = self.active.lock().unwrap();
+                let count = active.entry(kind).or_default();
+                *count += 1;
+                let mut peaks = self.peaks.lock().unwrap();
+                let peak = peaks.entry(kind).or_default();
+                *peak = (*peak).max(*count);
+                *self.calls.lock().unwrap().entry(kind).or_default() += 1;
+            }
+            if let Some(rom) = rom {
+                let mut roms = self.roms.lock().unwrap();
+                let busy = roms.entry(rom.to_owned()).or_default();
+                if *busy > 0 {
+                    *self.rom_overlaps.lock().unwrap() += 1;
+                }
+                *busy += 1;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            if let Some(rom) = rom {
+                *self.roms.lock().unwrap().get_mut(rom).unwrap() -= 1;
+            }
+            *self.active.lock().unwrap().get_mut(kind).unwrap() -= 1;
+        }
+
+        fn peak(&self, kind: &str) -> usize {
+            self.peaks.lock().unwrap().get(kind).copied().unwrap_or(0)
+        }
+
+        fn calls(&self, kind: &str) -> usize {
+            self.calls.lock().unwrap().get(kind).copied().unwrap_or(0)
+        }
+    }
+
+    /// A device with a few saves for each of a few roms; half of them are
+    /// empty, so they get pulled, and the rest get pushed.
+    struct FakeLocal<'a>(&'a Probe);
+
+    impl FakeLocal<'_> {
+        const ROMS: [&'static str; 4] = ["Tetris", "Zelda", "Metroid", "Kirby"];
+        const SAVES_PER_ROM: usize = 3;
+    }
+
+    impl LocalStore for FakeLocal<'_> {
+        async fn discover(&self) -> Vec<Result<LocalSave, anyhow::Error>> {
+            let mut saves = Vec::new();
+            for rom in Self::ROMS {
+                for n in 0..Self::SAVES_PER_ROM {
+                    saves.push(Ok(LocalSave {
+                        path: format!("/nonexistent/saves/{rom}-{n}.sav").into(),
+                        format: "/nonexistent/saves/".into(),
+                        vars: HashMap::from([("$ROM".to_owned(), rom.to_owned())]),
+                        direction: SyncDirection::Bidirectional,
+                        profile: None,
+                    }));
+                }
+            }
+            saves
+        }
+
+        fn policy(&self, _save: &LocalSave) -> SavePolicy {
+            SavePolicy::default()
+        }
+
+        async fn metadata(&self, save: &LocalSave) -> Result<DeviceMeta, anyhow::Error> {
+            self.0.measure("hashing", None).await;
+            let name = save.path.file_stem().unwrap().to_str().unwrap();
+            let mut meta =
+                SaveMeta::new_empty(save.vars["$ROM"].clone(), name.into(), "sav".into(), None);
+            if !name.ends_with('1') {
+                meta.hash = crate::md5hash::md5(name.as_bytes()).unwrap();
+                meta.size = name.len() as u64;
+            }
+            Ok(DeviceMeta::new(save.path.clone(), meta))
+        }
+
+        async fn backup(&self, _save: &DeviceMeta, _keep: usize) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    struct FakeSave(SaveMeta);
+
+    impl RemoteSave for FakeSave {
+        fn meta(&self) -> &SaveMeta {
+            &self.0
+        }
+    }
+
+    /// A remote holding a copy of only the saves missing from the device.
+    struct FakeRemote<'a>(&'a Probe);
+
+    impl RemoteStore for FakeRemote<'_> {
+        type Save = FakeSave;
+
+        async fn find_save(&self, local: &SaveMeta) -> Result<Option<FakeSave>, anyhow::Error> {
+            self.0.measure("find_save", Some(local.rom())).await;
+            let mut meta = SaveMeta::new_empty(
+                local.rom().to_owned(),
+                local.name.clone(),
+                local.ext.clone(),
+                None,
+            );
+            if local.is_empty() {
+                meta.hash = crate::md5hash::md5(&b"remote"[..]).unwrap();
+                meta.size = 6;
+            }
+            Ok(Some(FakeSave(meta)))
+        }
+
+        async fn pull(&self, save: &FakeSave, _dst: &Path) -> Result<(), anyhow::Error> {
+            self.0.measure("transfer", Some(save.0.rom())).await;
+            Ok(())
+        }
+
+        async fn push(
+            &self,
+            _src: &Path,
+            meta: &SaveMeta,
+            _save: &FakeSave,
+        ) -> Result<(), anyhow::Error> {
+            self.0.measure("transfer", Some(meta.rom())).await;
+            Ok(())
+        }
+    }
+
+    /// Sync state that's never seen any of the saves.
+    struct FakeState;
+
+    impl StateStore for FakeState {
+        async fn last_synced(&self, save: &SaveMeta) -> Result<SaveMeta, anyhow::Error> {
+            Ok(SaveMeta::new_empty(
+                save.rom().to_owned(),
+                save.name.clone(),
+                save.ext.clone(),
+                None,
+            ))
+        }
+
+        async fn record_synced(&self, _meta: &SaveMeta) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+    }
+
+    /// Checks the `system.concurrency` limits hold over a sync of many saves,
+    /// and that saves for the same rom are never synced at once.
+    async fn sync_limits() {
+        let probe = Probe::default();
+        let concurrency = ConcurrencyConfig {
+            hashing: Some(2),
+            metadata: Some(3),
+            transfers: Some(2),
+        };
+        run_sync(
+            &FakeLocal(&probe),
+            &FakeRemote(&probe),
+            &FakeState,
+            &concurrency,
+            &CancelToken::new(),
+        )
+        .await
+        .unwrap();
+
+        let saves = FakeLocal::ROMS.len() * FakeLocal::SAVES_PER_ROM;
+        assert_eq!(probe.calls("hashing"), saves);
+        assert_eq!(probe.calls("find_save"), saves);
+        assert_eq!(probe.calls("transfer"), saves);
+        assert_eq!(probe.peak("hashing"), 2);
+        assert!((2..=3).contains(&probe.peak("find_save")));
+        assert_eq!(probe.peak("transfer"), 2);
+        assert_eq!(*probe.rom_overlaps.lock().unwrap(), 0);
+    }
+
+    fn meta(data: &[u8], updated: i64) -> SaveMeta {
+        SaveMeta {
+            hash: crate::md5hash::md5(data).unwrap(),
+            size: data.len() as u64,
+            updated: chrono::DateTime::from_timestamp(updated, 0).unwrap(),
+            ..SaveMeta::new_empty("rom".into(), "save".into(), "sav".into(), None)
+        }
+    }
+
+    #[test]
+    fn test_directed_decisions() {
+        use SyncDecision::*;
+        use SyncDirection::*;
+        let synced = meta(b"synced", 1);
+        let changed = meta(b"changed", 2);
+        let policy = |direction, conflict| SavePolicy {
+            direction,
+            conflict,
+            ..Default::default()
+        };
+        let decide = |device: &SaveMeta, remote: &SaveMeta, direction| {
+            let policy = policy(direction, ConflictPolicy::Error);
+            let res = decide_directed_action(device, remote, &synced, &policy).unwrap();
+            (res.action, res.suppressed)
+        };
+
+        // Only remote changed.
+        assert_eq!(
+            decide(&synced, &changed, Bidirectional),
+            (PullToDevice, None)
+        );
+        assert_eq!(
+            decide(&synced, &changed, PushOnly),
+            (Noop, Some(PullToDevice))
+        );
+        assert_eq!(decide(&synced, &changed, PullOnly), (PullToDevice, None));
+        assert_eq!(
+            decide(&synced, &changed, MirrorFromRemote),
+            (PullToDevice, None)
+        );
+
+        // Only device changed.
+        assert_eq!(
+            decide(&changed, &synced, Bidirectional),
+            (PushToRemote, None)
+        );
+        assert_eq!(decide(&changed, &synced, PushOnly), (PushToRemote, None));
+        assert_eq!(
+            decide(&changed, &synced, PullOnly),
+            (Noop, Some(PushToRemote))
+        );
+        assert_eq!(
+            decide(&changed, &synced, MirrorFromRemote),
+            (PullToDevice, Some(PushToRemote))
+        );
+
+        // Both changed; mirroring or a conflict policy resolves it.
+        let other = meta(b"other", 3);
+        let conflicted = |direction, conflict| {
+            decide_directed_action(&changed, &other, &synced, &policy(direction, conflict))
+                .map(|res| (res.action, res.suppressed))
+                .ok()
+        };
+        assert_eq!(conflicted(PullOnly, ConflictPolicy::Error), None);
+        assert_eq!(
+            decide(&changed, &other, MirrorFromRemote),
+            (PullToDevice, Some(PushToRemote))
+        );
+        assert_eq!(
+            conflicted(Bidirectional, ConflictPolicy::PreferDevice),
+            Some((PushToRemote, None))
+        );
+        assert_eq!(
+            conflicted(Bidirectional, ConflictPolicy::PreferNewest),
+            Some((PullToDevice, None))
+        );
+        assert_eq!(
+            conflicted(PushOnly, ConflictPolicy::PreferRemote),
+            Some((Noop, Some(PullToDevice)))
+        );
+    }
+
+    #[test]
+    fn test_sync_profiles() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(sync_profiles());
+    }
+
+    #[test]
+    fn test_sync_open_save() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(sync_open_save());
+    }
+
+    #[test]
+    fn test_sync_limits() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(sync_limits());
+    }
+
+    #[test]
+    fn test_sync_roundtrip() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        // Once against a server without paginated roms or save hashes, and
+        // once against one with both.
+        rt.block_on(sync_roundtrip(true));
+        rt.block_on(sync_roundtrip(false));
+    }
+}
@@ -0,0 +1,201 @@
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use futures::pin_mut;
+use futures::{Stream, TryStreamExt};
+use thiserror::Error;
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+use tracing::{trace, warn};
+
+use syncer_model::path_format_strings::DEFAULT_TIMESTAMP_FORMAT;
+
+static INCREMENTING_ID: AtomicUsize = AtomicUsize::new(0xa0_00);
+
+/// Returns a new ID to use for debugging purposes.
+///
+/// Guranteed to not repeat across multiple calls, even in async/multi-threaded
+/// environments.
+pub fn new_id() -> usize {
+    INCREMENTING_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EitherError<A, B> {
+    #[error(transparent)]
+    A(#[from] A),
+    #[error(transparent)]
+    B(B),
+}
+
+/// Atomically writes a file from a stream to the given location.
+///
+/// We do this by first writing the data to a temporary file path with a
+/// timestamp-derived name and moving it to the correct location only after the
+/// data has been completely written to the file.
+///
+/// If the download fails or the returned future is dropped part way through
+/// (eg because the sync was cancelled) the temporary file is removed and `dst`
+/// is left untouched.
+pub async fn download<S, B, E>(data: S, dst: &Path) -> Result<(), EitherError<io::Error, E>>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+{
+    let stamp = timestamp_now().format(DEFAULT_TIMESTAMP_FORMAT);
+    let tmp_fname = dst.with_extension(stamp.to_string());
+    let mut fh = File::create_new(&tmp_fname).await?;
+    let tmp_guard = RemoveOnDrop(Some(tmp_fname));
+
+    pin_mut!(data);
+    while let Some(chunk) = data.try_next().await.map_err(EitherError::B)? {
+        fh.write_all(chunk.as_ref()).await?;
+    }
+    fh.flush().await?;
+    drop(fh);
+    fs::rename(tmp_guard.path(), dst).await?;
+    tmp_guard.disarm();
+    Ok(())
+}
+
+/// Deletes a partially written file when dropped, unless disarmed first.
+struct RemoveOnDrop(Option<PathBuf>);
+
+impl RemoveOnDrop {
+    fn path(&self) -> &Path {
+        self.0.as_deref().unwrap_or(Path::new(""))
+    }
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        let Some(path) = self.0.take() else {
+            return;
+        };
+        // We can't await in `drop`, but this is a single unlink of a file we
+        // just created so blocking briefly is fine.
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                trace!("Rolled back partial download {path:?}");
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!("Error removing partial download {path:?}: {e:?}");
+            }
+        }
+    }
+}
+
+pub fn timestamp_now() -> DateTime<Utc> {
+    let dt = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as _;
+    DateTime::from_timestamp_nanos(dt)
+}
+
+/// A cooperative cancellation flag shared between a running sync and anything
+/// that may want to stop it early.
+///
+/// Cancelling doesn't interrupt anything by itself; long-running work is
+/// expected to check [`CancelToken::is_cancelled`] between steps or to wrap
+/// its futures in [`CancelToken::run`].
+#[derive(Clone)]
+pub struct CancelToken {
+    id: usize,
+    inner: watch::Sender<bool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self {
+            id: new_id(),
+            inner: watch::Sender::new(false),
+        }
+    }
+
+    /// Asks the current holder of the token to stop.
+    pub fn cancel(&self) {
+        trace!("Cancelling: {}", self.id);
+        self.inner.send_replace(true);
+    }
+
+    /// Clears a previous cancellation so the token can be reused for the next
+    /// run.
+    pub fn reset(&self) {
+        self.inner.send_replace(false);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.inner.borrow()
+    }
+
+    /// Waits until the token is cancelled.
+    pub async fn cancelled(&self) {
+        // We hold a sender ourselves, so this can never return an error.
+        self.inner.subscribe().wait_for(|b| *b).await.ok();
+    }
+
+    /// Runs `fut` to completion, or drops it and returns `None` if the token
+    /// is cancelled first.
+    pub async fn run<F: Future>(&self, fut: F) -> Option<F::Output> {
+        tokio::select! {
+            res = fut => Some(res),
+            () = self.cancelled() => None,
+        }
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use syncer_test_support::TempDir;
+    use tokio::sync::Notify;
+
+    #[test]
+    fn test_cancelled_download_rolls_back() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let dir = TempDir::new("romm-syncer-dl").unwrap();
+            let dst = dir.path().join("save.srm");
+
+            let token = CancelToken::new();
+            // Asking for a second chunk means the first has been written to
+            // the temporary file.
+            let written = Notify::new();
+            let first = futures::stream::iter([Ok::<_, io::Error>(b"partial".to_vec())]);
+            let rest = futures::stream::once(async {
+                written.notify_one();
+                futures::future::pending().await
+            });
+            let data = first.chain(rest);
+            let cancel_later = async {
+                written.notified().await;
+                token.cancel();
+            };
+            let (res, ()) = futures::join!(token.run(download(data, &dst)), cancel_later);
+            assert!(res.is_none());
+
+            let mut rdr = fs::read_dir(dir.path()).await.unwrap();
+            assert!(rdr.next_entry().await.unwrap().is_none());
+        })
+    }
+}
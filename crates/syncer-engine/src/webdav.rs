@@ -0,0 +1,508 @@
+//! A [`RemoteStore`] that keeps saves on a WebDAV share, such as a Nextcloud
+//! folder.
+//!
+//! Saves are laid out according to a format string and tracked in the same
+//! sidecar index as a [`DirectoryRemote`](crate::directory::DirectoryRemote),
+//! stored at the root of the share. WebDAV servers don't hand out content
+//! hashes, so each index entry also records the file's ETag (or, failing that,
+//! its size) when we last wrote it; as long as that still matches we trust the
+//! indexed hash, and otherwise download the file to hash it ourselves.
+
+use std::path::Path;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use futures::{stream, TryStreamExt};
+use reqwest::header::{HeaderValue, ETAG};
+use reqwest::Client as HttpClient;
+use reqwest::Error as HttpError;
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use thiserror::Error;
+use tokio::fs;
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::{debug, info, trace};
+use url::Url;
+
+use syncer_model::path_format_strings::FormatString;
+
+use crate::directory::{new_save_path, Index, IndexEntry, INDEX_FILE_NAME};
+use crate::md5hash::{md5_stream, Md5ParseError};
+use crate::stores::{RemoteSave, RemoteStore};
+use crate::utils::download;
+use crate::SaveMeta;
+
+/// The properties we ask for when looking up a file.
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop><d:resourcetype/><d:getetag/><d:getcontentlength/><d:getlastmodified/></d:prop>
+</d:propfind>"#;
+
+pub struct WebDavRemote {
+    client: HttpClient,
+    /// The URL of the share, always ending in a `/`.
+    url: Url,
+    credentials: Option<(String, Option<String>)>,
+    format: FormatString,
+    /// Serializes read-modify-write cycles of the index file.
+    index_lock: AsyncMutex<()>,
+}
+
+/// A save as stored on a [`WebDavRemote`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WebDavSave {
+    /// Where the save is, relative to the share, or `None` if the share doesn't
+    /// have a copy yet.
+    path: Option<String>,
+    meta: SaveMeta,
+}
+
+impl RemoteSave for WebDavSave {
+    fn meta(&self) -> &SaveMeta {
+        &self.meta
+    }
+}
+
+/// What a `PROPFIND` told us about a file.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+struct FileProps {
+    etag: Option<String>,
+    size: Option<u64>,
+    modified: Option<DateTime<Utc>>,
+}
+
+impl FileProps {
+    /// Whether the file is still the one `entry` was written for.
+    fn matches(&self, entry: &IndexEntry, size: u64) -> bool {
+        match (&self.etag, &entry.etag) {
+            (Some(current), Some(indexed)) => current == indexed,
+            _ => self.size == Some(size),
+        }
+    }
+}
+
+impl WebDavRemote {
+    /// Syncs with the share at `url`, laying saves out according to `format`.
+    pub fn new(mut url: Url, format: FormatString) -> Self {
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        Self {
+            client: HttpClient::new(),
+            url,
+            credentials: None,
+            format,
+            index_lock: AsyncMutex::new(()),
+        }
+    }
+
+    /// Logs in to the share with HTTP basic auth.
+    pub fn with_credentials(self, username: String, password: Option<String>) -> Self {
+        Self {
+            credentials: Some((username, password)),
+            ..self
+        }
+    }
+
+    /// Whether the share can currently be reached.
+    ///
+    /// Errors other than failing to connect at all, such as the server
+    /// rejecting our credentials, are returned rather than treated as the
+    /// share being unavailable.
+    pub async fn is_available(&self) -> Result<bool, WebDavError> {
+        match self.propfind(self.url.clone()).await {
+            Ok(_) => Ok(true),
+            Err(WebDavError::Http(e)) if e.is_connect() || e.is_timeout() => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let req = self.client.request(method, url);
+        match &self.credentials {
+            Some((user, pass)) => req.basic_auth(user, pass.as_ref()),
+            None => req,
+        }
+    }
+
+    /// The URL of `relative` on the share, making sure it can't point outside
+    /// of it.
+    fn resolve(&self, relative: &str) -> Result<Url, WebDavError> {
+        let segments = relative.split('/').collect::<Vec<_>>();
+        let is_contained = segments
+            .iter()
+            .all(|seg| !seg.is_empty() && *seg != "." && *seg != "..");
+        if !is_contained {
+            return Err(WebDavError::OutsideRoot(relative.to_owned()));
+        }
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .unwrap()
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url)
+    }
+
+    /// Looks up a file, returning `None` if it doesn't exist.
+    async fn propfind(&self, url: Url) -> Result<Option<FileProps>, WebDavError> {
+        trace!("Calling PROPFIND on WebDAV url {url}");
+        let resp = self
+            .request(Method::from_bytes(b"PROPFIND").unwrap(), url)
+            .header("Depth", "0")
+            .header("Content-Type", "application/xml; charset=utf-8")
+            .body(PROPFIND_BODY)
+            .send()
+            .await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let text = resp.error_for_status()?.text().await?;
+        Ok(Some(parse_props(&text)?))
+    }
+
+    /// Starts downloading a file, returning `None` if it doesn't exist.
+    async fn get(&self, url: Url) -> Result<Option<Response>, WebDavError> {
+        trace!("Calling GET on WebDAV url {url}");
+        let resp = self.request(Method::GET, url).send().await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(resp.error_for_status()?))
+    }
+
+    /// Creates every missing collection above `relative`.
+    async fn make_parents(&self, relative: &str) -> Result<(), WebDavError> {
+        let Some((parents, _)) = relative.rsplit_once('/') else {
+            return Ok(());
+        };
+        let mut path = String::new();
+        for segment in parents.split('/') {
+            if !path.is_empty() {
+                path.push('/');
+            }
+            path.push_str(segment);
+            let mut url = self.resolve(&path)?;
+            url.path_segments_mut().unwrap().push("");
+            trace!("Calling MKCOL on WebDAV url {url}");
+            let resp = self
+                .request(Method::from_bytes(b"MKCOL").unwrap(), url)
+                .send()
+                .await?;
+            // 405 means the collection is already there.
+            if resp.status() != StatusCode::METHOD_NOT_ALLOWED {
+                resp.error_for_status()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Uploads `data` to `url`, returning the new file's ETag if the server
+    /// gave us one.
+    async fn put(&self, url: Url, data: Vec<u8>) -> Result<Option<String>, WebDavError> {
+        trace!("Calling PUT on WebDAV url {url}");
+        let resp = self
+            .request(Method::PUT, url.clone())
+            .body(data)
+            .send()
+            .await?
+            .error_for_status()?;
+        let etag = resp.headers().get(ETAG).and_then(etag_value);
+        match etag {
+            Some(etag) => Ok(Some(etag)),
+            // Not every server returns the ETag from a PUT; ask for it.
+            None => Ok(self.propfind(url).await?.and_then(|props| props.etag)),
+        }
+    }
+
+    async fn read_index(&self) -> Result<Index, WebDavError> {
+        let Some(resp) = self.get(self.resolve(INDEX_FILE_NAME)?).await? else {
+            return Ok(Index::default());
+        };
+        Ok(serde_json::from_slice(&resp.bytes().await?)?)
+    }
+
+    async fn write_index(&self, index: &Index) -> Result<(), WebDavError> {
+        let data = serde_json::to_vec_pretty(index)?;
+        self.put(self.resolve(INDEX_FILE_NAME)?, data).await?;
+        Ok(())
+    }
+
+    /// Downloads & hashes a file we have no trustworthy index entry for.
+    ///
+    /// `meta` is what we last knew about the file, if anything.
+    async fn hash_file(
+        &self,
+        url: Url,
+        props: &FileProps,
+        mut meta: SaveMeta,
+    ) -> Result<SaveMeta, WebDavError> {
+        debug!("Hashing {url} to compare it.");
+        let Some(resp) = self.get(url).await? else {
+            return Ok(meta);
+        };
+        let mut size = 0;
+        let chunks = resp.bytes_stream().inspect_ok(|chunk| size += chunk.len());
+        meta.hash = md5_stream(chunks).await?;
+        meta.size = size as u64;
+        // `getlastmodified` only has a resolution of a second, but a file
+        // replaced since we last indexed it must be newer than what we indexed.
+        let known_older = meta.updated + TimeDelta::seconds(1);
+        meta.updated = props.modified.unwrap_or_else(Utc::now).max(known_older);
+        meta.created = meta.created.min(meta.updated);
+        Ok(meta)
+    }
+
+    async fn find(&self, local: &SaveMeta) -> Result<WebDavSave, WebDavError> {
+        let index = self.read_index().await?;
+        let entry = index.saves.iter().find(|entry| entry.is_for(local));
+        let path = match entry {
+            Some(entry) => entry.path.clone(),
+            None => new_save_path(local, &self.format),
+        };
+        let url = self.resolve(&path)?;
+        let empty = SaveMeta::new_empty(
+            local.rom().to_owned(),
+            local.name.clone(),
+            local.ext.clone(),
+            local.emulator.clone(),
+        );
+        let Some(props) = self.propfind(url.clone()).await? else {
+            if entry.is_some() {
+                debug!("Indexed save {path} is missing; treating it as new.");
+            }
+            return Ok(WebDavSave {
+                path: None,
+                meta: empty,
+            });
+        };
+        let meta = match entry {
+            Some(entry) => {
+                let meta = entry.meta()?;
+                if props.matches(entry, meta.size) {
+                    meta
+                } else {
+                    debug!("Save {path} was replaced since we last synced it.");
+                    self.hash_file(url, &props, meta).await?
+                }
+            }
+            // Someone put a save where we would have; adopt it.
+            None => {
+                let created = props.modified.unwrap_or_else(Utc::now);
+                let meta = SaveMeta { created, ..empty };
+                self.hash_file(url, &props, meta).await?
+            }
+        };
+        Ok(WebDavSave {
+            path: Some(path),
+            meta,
+        })
+    }
+
+    async fn upload(
+        &self,
+        src: &Path,
+        meta: &SaveMeta,
+        save: &WebDavSave,
+    ) -> Result<(), WebDavError> {
+        let relative = match save.path.clone() {
+            Some(path) => path,
+            None => new_save_path(meta, &self.format),
+        };
+        let url = self.resolve(&relative)?;
+        info!("Uploading save {} to {url}.", src.display());
+        self.make_parents(&relative).await?;
+        let etag = self.put(url, fs::read(src).await?).await?;
+
+        let _guard = self.index_lock.lock().await;
+        let mut index = self.read_index().await?;
+        index.saves.retain(|entry| !entry.is_for(meta));
+        let mut entry = IndexEntry::new(relative, meta);
+        entry.etag = etag;
+        index.saves.push(entry);
+        self.write_index(&index).await?;
+        trace!("Updated WebDAV index for {}.", meta.name);
+        Ok(())
+    }
+}
+
+impl RemoteStore for WebDavRemote {
+    type Save = WebDavSave;
+
+    async fn find_save(&self, local: &SaveMeta) -> Result<Option<WebDavSave>, anyhow::Error> {
+        Ok(Some(self.find(local).await?))
+    }
+
+    async fn pull(&self, save: &WebDavSave, dst: &Path) -> Result<(), anyhow::Error> {
+        let relative = save
+            .path
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Save {} has no stored copy.", save.meta.name))?;
+        let url = self.resolve(relative)?;
+        info!("Downloading save {url} to {}.", dst.display());
+        let resp = self
+            .get(url.clone())
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Save {url} disappeared from the share."))?;
+        let dl_stream = stream::try_unfold(resp, move |mut resp| async move {
+            match resp.chunk().await {
+                Err(e) => Err(e),
+                Ok(None) => Ok(None),
+                Ok(Some(chunk)) => Ok(Some((chunk, resp))),
+            }
+        });
+        download(dl_stream, dst).await?;
+        Ok(())
+    }
+
+    async fn push(
+        &self,
+        src: &Path,
+        meta: &SaveMeta,
+        save: &WebDavSave,
+    ) -> Result<(), anyhow::Error> {
+        self.upload(src, meta, save).await?;
+        Ok(())
+    }
+}
+
+fn etag_value(value: &HeaderValue) -> Option<String> {
+    value.to_str().ok().map(str::to_owned)
+}
+
+/// Pulls the properties of the first resource out of a `PROPFIND` response.
+fn parse_props(xml: &str) -> Result<FileProps, WebDavError> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let is_dav = |node: &roxmltree::Node, name: &str| {
+        node.is_element()
+            && node.tag_name().namespace() == Some("DAV:")
+            && node.tag_name().name() == name
+    };
+    let Some(response) = doc.descendants().find(|node| is_dav(node, "response")) else {
+        return Ok(FileProps::default());
+    };
+    let prop = |name: &str| {
+        response
+            .descendants()
+            .find(|node| is_dav(node, name))
+            .and_then(|node| node.text())
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+    };
+    Ok(FileProps {
+        etag: prop("getetag").map(str::to_owned),
+        size: prop("getcontentlength").and_then(|size| size.parse().ok()),
+        modified: prop("getlastmodified")
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+            .map(|date| date.with_timezone(&Utc)),
+    })
+}
+
+#[derive(Debug, Error)]
+pub enum WebDavError {
+    #[error("Save path {0:?} points outside of the WebDAV share")]
+    OutsideRoot(String),
+    #[error("Corrupt hash in WebDAV index: {0}")]
+    Hash(#[from] Md5ParseError),
+    #[error("Error reading WebDAV index: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Malformed WebDAV response: {0}")]
+    Xml(#[from] roxmltree::Error),
+    #[error(transparent)]
+    Http(#[from] HttpError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{database::SaveMetaDatabase, syncing::run_sync, utils::CancelToken};
+    use syncer_model::config::Config;
+    use syncer_test_support::{FakeWebDavServer, TempDir, TestConfig};
+
+    #[test]
+    fn test_webdav_roundtrip() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                // "user:pass"
+                let server = FakeWebDavServer::start_with_auth(Some("Basic dXNlcjpwYXNz"))
+                    .await
+                    .unwrap();
+                let device = TempDir::new("romm-syncer-device").unwrap();
+                let save_dir = device.path().join("saves/gpSP");
+                fs::create_dir_all(&save_dir).await.unwrap();
+                let save_path = save_dir.join("Pokemon Emerald.sav");
+                fs::write(&save_path, b"first").await.unwrap();
+
+                let cfg: Config = toml::from_str(
+                    &TestConfig::new()
+                        .saves(&format!(
+                            "{}/saves/$EMULATOR/$NAME.$EXT",
+                            device.path().display()
+                        ))
+                        .to_toml(),
+                )
+                .unwrap();
+                let remote = WebDavRemote::new(server.url(), "$EMULATOR/$ROM/$NAME.$EXT".into());
+                let wrong_password = WebDavRemote::new(server.url(), "$ROM/$NAME.$EXT".into())
+                    .with_credentials("user".into(), Some("wrong".into()));
+                assert!(wrong_password.is_available().await.is_err());
+                let remote = remote.with_credentials("user".into(), Some("pass".into()));
+                assert!(remote.is_available().await.unwrap());
+
+                let db = SaveMetaDatabase::new_in_memory().await.unwrap();
+                let cancel = CancelToken::new();
+                let stored = "gpSP/Pokemon Emerald/Pokemon Emerald.sav";
+
+                run_sync(&cfg, &remote, &db, &cfg.system.concurrency, &cancel)
+                    .await
+                    .unwrap();
+                assert_eq!(server.file(stored).unwrap(), b"first");
+                assert_eq!(
+                    server.files(),
+                    vec![INDEX_FILE_NAME.to_owned(), stored.to_owned()]
+                );
+
+                // Nothing changed, so nothing should be uploaded again.
+                run_sync(&cfg, &remote, &db, &cfg.system.concurrency, &cancel)
+                    .await
+                    .unwrap();
+                assert_eq!(server.file(stored).unwrap(), b"first");
+
+                // Another client replaces the save without updating the index;
+                // the new ETag should make us hash & pull it.
+                server.put_file(stored, "second");
+                run_sync(&cfg, &remote, &db, &cfg.system.concurrency, &cancel)
+                    .await
+                    .unwrap();
+                assert_eq!(fs::read(&save_path).await.unwrap(), b"second");
+            });
+    }
+
+    #[test]
+    fn test_parse_props() {
+        let xml = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:oc="http://owncloud.org/ns">
+  <d:response>
+    <d:href>/remote.php/dav/files/user/saves/Pokemon%20Emerald.sav</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:getlastmodified>Tue, 03 Jun 2025 10:11:12 GMT</d:getlastmodified>
+        <d:getcontentlength>131072</d:getcontentlength>
+        <d:resourcetype/>
+        <d:getetag>"6836a0e0c8b1f"</d:getetag>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+</d:multistatus>"#;
+        let props = parse_props(xml).unwrap();
+        assert_eq!(props.etag.as_deref(), Some("\"6836a0e0c8b1f\""));
+        assert_eq!(props.size, Some(131072));
+        assert_eq!(
+            props.modified.unwrap().to_rfc3339(),
+            "2025-06-03T10:11:12+00:00"
+        );
+    }
+}
@@ -1,12 +1,18 @@
 //! The protocol used to communicate between the daemon and different UI crates
 //! while the daemon is running.
 
+use std::io;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 use serde_json::Error as JsError;
 use serde_json::Value as JsValue;
 use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::config::SyncDirection;
 
 /// The version of the daemon's RPC API.
 pub const VERSION: u32 = 1;
@@ -32,6 +38,30 @@ impl DaemonCommand {
     pub fn serialize(&self) -> String {
         serde_json::to_string(&self).unwrap()
     }
+
+    /// Sends this command over `stream`, a connection to the daemon's socket,
+    /// and waits up to `timeout` for each part of the daemon's reply.
+    pub async fn query<S>(&self, stream: &mut S, timeout: Duration) -> io::Result<DaemonResponse>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        stream.write_all(self.serialize().as_bytes()).await?;
+        let mut buffer = Vec::new();
+        loop {
+            let read = tokio::time::timeout(timeout, stream.read_buf(&mut buffer))
+                .await
+                .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+            match serde_json::from_slice(&buffer) {
+                Ok(response) => {
+                    return Ok(response);
+                }
+                Err(e) if e.is_eof() && read != 0 => {}
+                Err(e) => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                }
+            }
+        }
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
@@ -41,6 +71,133 @@ pub enum DaemonCommandBody {
 
     /// Reloads the configuration from disk.
     ReloadConfig,
+
+    /// Asks the daemon to reply with a [`DaemonResponseBody::Status`].
+    GetStatus,
+
+    /// Stops the sync that is currently running, if any, after the saves that
+    /// are mid-transfer have been finished or rolled back.
+    CancelSync,
+
+    /// Cancels any running sync and stops the daemon from starting new ones
+    /// until it receives a [`DaemonCommandBody::Resume`].
+    ///
+    /// The paused state is remembered across daemon restarts.
+    Pause,
+
+    /// Lets the daemon sync again after a [`DaemonCommandBody::Pause`].
+    Resume,
+
+    /// Stops any running sync and pushes changed saves, without pulling,
+    /// ahead of any other sync; meant to be sent right before the device
+    /// suspends or shuts down.
+    ///
+    /// The daemon replies with a [`DaemonResponseBody::Status`] once the push
+    /// has finished or been skipped.
+    FinalPush,
+}
+
+/// A reply the daemon sends back over the socket for commands that need one.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonResponse {
+    /// The version of the protocol being used.
+    pub version: u32,
+    #[serde(flatten)]
+    pub body: DaemonResponseBody,
+}
+
+impl DaemonResponse {
+    pub const fn new(body: DaemonResponseBody) -> Self {
+        Self {
+            version: VERSION,
+            body,
+        }
+    }
+
+    pub fn serialize(&self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum DaemonResponseBody {
+    /// What the daemon is currently doing; the reply to
+    /// [`DaemonCommandBody::GetStatus`].
+    Status(DaemonStatus),
+}
+
+/// A snapshot of the daemon's current state, for displaying in a UI.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    /// The outcome of the most recent sync.
+    pub sync: SyncStatus,
+    /// The version reported by the ROMM server the last time we talked to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_version: Option<String>,
+    /// A user-facing description of why the last sync failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// A user-facing description of why the config files couldn't be
+    /// reloaded, if they couldn't; the daemon keeps running with the last
+    /// config that loaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_error: Option<String>,
+    /// Whether syncing has been paused by the user.
+    #[serde(default)]
+    pub paused: bool,
+    /// The transfers the last sync skipped because of their save root's
+    /// [`SyncDirection`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suppressed: Vec<SuppressedSync>,
+    /// The saves the last sync left alone because another process, like the
+    /// emulator playing them, had them open; they sync once closed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub in_use: Vec<PathBuf>,
+}
+
+/// A push or pull that a save root's [`SyncDirection`] didn't allow.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SuppressedSync {
+    /// The local save that would have been transferred.
+    pub path: PathBuf,
+    /// The direction configured for the save's root.
+    pub direction: SyncDirection,
+    /// The transfer that was skipped.
+    pub transfer: Transfer,
+}
+
+/// Which way a save is copied.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Transfer {
+    /// From the device to the remote.
+    Push,
+    /// From the remote to the device.
+    Pull,
+}
+
+/// The outcome of the most recent sync attempt.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum SyncStatus {
+    /// No sync has been attempted since the daemon started.
+    #[default]
+    Idle,
+    /// A sync is currently running.
+    Syncing,
+    /// The last sync ran to completion.
+    Synced,
+    /// The last sync was skipped because there was no network connection or
+    /// the ROMM server could not be reached.
+    Offline,
+    /// The last sync ran but hit an error.
+    Failed,
+    /// The last sync was stopped early by a [`DaemonCommandBody::CancelSync`]
+    /// or [`DaemonCommandBody::Pause`].
+    Cancelled,
+    /// Syncing is paused; no syncs will run until the daemon is resumed.
+    Paused,
+    /// The last sync was deferred because the device is on battery and below
+    /// `system.power.min_battery`; it runs once the device is charging.
+    LowBattery,
 }
 
 #[derive(Debug, Error)]
@@ -83,3 +240,51 @@ impl FromStr for DaemonCommand {
         s.as_bytes().try_into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn test_query() {
+        let (mut client, mut daemon) = tokio::io::duplex(1024);
+        let cmd = DaemonCommand::new(DaemonCommandBody::GetStatus);
+        let response = DaemonResponse::new(DaemonResponseBody::Status(DaemonStatus::default()));
+        let reply = response.serialize();
+        let expected = cmd.serialize();
+        let daemon = tokio::spawn(async move {
+            let mut received = vec![0; expected.len()];
+            daemon.read_exact(&mut received).await.unwrap();
+            assert_eq!(received, expected.as_bytes());
+            // Replies can arrive in more than one read.
+            let (first, rest) = reply.split_at(reply.len() / 2);
+            daemon.write_all(first.as_bytes()).await.unwrap();
+            daemon.flush().await.unwrap();
+            tokio::task::yield_now().await;
+            daemon.write_all(rest.as_bytes()).await.unwrap();
+            daemon
+        });
+        assert_eq!(cmd.query(&mut client, TIMEOUT).await.unwrap(), response);
+        daemon.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_query_errors() {
+        let cmd = DaemonCommand::new(DaemonCommandBody::GetStatus);
+
+        let (mut client, mut daemon) = tokio::io::duplex(1024);
+        daemon.write_all(b"{\"version\": 1, \"Sta").await.unwrap();
+        daemon.shutdown().await.unwrap();
+        let err = cmd.query(&mut client, TIMEOUT).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let (mut client, _daemon) = tokio::io::duplex(1024);
+        let err = cmd
+            .query(&mut client, Duration::from_millis(10))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,829 @@
+//! A full validation pass over the config files, reporting every problem found
+//! along with the file & key it came from.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use url::Url;
+
+use super::layers::{Layer, Layers};
+use super::loading::FlattenedList;
+use crate::config::{Config, PathPattern, RemoteKind, SaveRoot};
+use crate::path_format_strings::FormatString;
+
+/// The variables the daemon fills in or reads from format strings.
+pub const KNOWN_VARIABLES: &[&str] = &[
+    "$ROM",
+    "$NAME",
+    "$EXT",
+    "$EMULATOR",
+    "$PLATFORM",
+    "$TIMESTAMP",
+    "$CREATED",
+    "$UPDATED",
+];
+
+/// Finite poll intervals longer than this will never elapse in practice; the
+/// device will have been restarted long before.
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(60 * 60 * 24 * 365);
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Severity {
+    /// Probably a mistake, but the daemon can still run.
+    Warning,
+    /// The daemon will refuse to start, or will never sync some saves.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// Where a config value came from.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Origin {
+    /// A config file.
+    File(PathBuf),
+    /// An environment variable, such as `ROMM_URL`.
+    Env(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::File(path) => write!(f, "{}", path.display()),
+            Origin::Env(var) => write!(f, "${var}"),
+        }
+    }
+}
+
+/// A single problem found by [`Config::check`].
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Where the offending value was set, or [`None`] for problems with the
+    /// config as a whole, such as a missing required field.
+    pub origin: Option<Origin>,
+    /// The key the problem is with, like `system.saves[1]`, or empty if the
+    /// file couldn't be read at all.
+    pub key: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(origin: Option<&Origin>, key: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            origin: origin.cloned(),
+            key: key.into(),
+            message: message.into(),
+        }
+    }
+
+    fn warning(
+        origin: Option<&Origin>,
+        key: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(origin, key, message)
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.severity)?;
+        if let Some(origin) = &self.origin {
+            write!(f, "{origin}: ")?;
+        }
+        if !self.key.is_empty() {
+            write!(f, "{}: ", self.key)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// A `system.allow` or `system.deny` entry, along with where it was set.
+struct ListEntry<'a> {
+    origin: &'a Origin,
+    key: String,
+    pattern: &'a PathPattern,
+}
+
+impl Config {
+    /// Checks the config files for the current platform; see [`Config::check`].
+    pub async fn check_current_platform() -> Vec<Diagnostic> {
+        match Self::current_platform_files().await {
+            Ok(files) => Self::check(files.into_iter()).await,
+            Err(e) => vec![Diagnostic::error(None, "", format!("{e:#}"))],
+        }
+    }
+
+    /// Runs every check we have against the given config files, as they'd be
+    /// loaded by [`Config::load`].
+    ///
+    /// Unlike [`Config::load`] this doesn't stop at the first problem, and
+    /// also reports things that are valid but probably not what the user
+    /// meant, like a save root that doesn't exist.
+    pub async fn check(files: impl Iterator<Item = impl AsRef<Path>>) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let layers = Layers::read(files).await;
+        for (origin, e) in &layers.errors {
+            let key = if origin.is_some() { "" } else { "romm" };
+            diagnostics.push(Diagnostic::error(origin.as_ref(), key, format!("{e:#}")));
+        }
+        let (merged, failed) = layers.merged();
+        for (env_override, e) in &failed {
+            let origin = Origin::Env(env_override.var.clone());
+            let message = format!("{:#}", e.root_cause());
+            diagnostics.push(Diagnostic::error(
+                Some(&origin),
+                env_override.key(),
+                message,
+            ));
+        }
+
+        if layers.errors.is_empty() {
+            check_required(&merged, &mut diagnostics);
+        }
+        check_formats(&layers.layers, &mut diagnostics);
+        check_save_roots(&layers.layers, &mut diagnostics).await;
+        check_allow_deny(&merged, &layers, &mut diagnostics);
+        check_poll_interval(&merged, &layers, &mut diagnostics);
+        check_schedule(&merged, &layers, &mut diagnostics);
+        check_power(&merged, &layers, &mut diagnostics);
+        check_urls(&merged, &layers, &mut diagnostics);
+        diagnostics
+    }
+}
+
+/// Each entry in `system.saves`, along with its key.
+fn save_keys(saves: &FlattenedList<SaveRoot>) -> impl Iterator<Item = (String, &SaveRoot)> {
+    let single = matches!(saves, FlattenedList::Single(_));
+    saves.as_slice().iter().enumerate().map(move |(idx, root)| {
+        let key = if single {
+            "system.saves".to_owned()
+        } else {
+            format!("system.saves[{idx}]")
+        };
+        (key, root)
+    })
+}
+
+/// The directory saves are searched for under, unless the format starts with a
+/// variable or wildcard rather than a directory.
+fn fixed_root(root: &SaveRoot) -> Option<PathBuf> {
+    let prefix = root.format.prefix();
+    if prefix == "/" && !root.format.as_str().starts_with('/') {
+        return None;
+    }
+    Some(PathBuf::from(prefix))
+}
+
+fn check_required(config: &Config, diagnostics: &mut Vec<Diagnostic>) {
+    let system = &config.system;
+    let mut required = vec![
+        ("system.saves", !system.saves.is_empty()),
+        ("system.database", system.database.is_some()),
+    ];
+    let mut missing = Vec::new();
+    match system.remote() {
+        RemoteKind::Romm => {
+            for name in config.used_profiles() {
+                let Some(profile) = config.profile(name) else {
+                    let name = name.unwrap_or_default();
+                    diagnostics.push(Diagnostic::error(
+                        None,
+                        format!("profiles.{name}"),
+                        "Saves are bound to this profile, but it isn't configured",
+                    ));
+                    continue;
+                };
+                let section = match name {
+                    None => "romm".to_owned(),
+                    Some(name) => format!("profiles.{name}"),
+                };
+                if profile.url.is_none() {
+                    missing.push(format!("{section}.url"));
+                }
+                if profile.api_key.is_none() {
+                    missing.push(format!("{section}.api_key"));
+                }
+            }
+        }
+        RemoteKind::Directory => {
+            required.push(("directory.path", config.directory.path.is_some()));
+        }
+        RemoteKind::WebDav => {
+            let webdav = &config.webdav;
+            required.push(("webdav.url", webdav.url.is_some()));
+            if webdav.password.is_some() {
+                required.push(("webdav.username", webdav.username.is_some()));
+            }
+        }
+    }
+    let required = required
+        .into_iter()
+        .filter(|(_, present)| !present)
+        .map(|(key, _)| key.to_owned());
+    for key in required.chain(missing) {
+        diagnostics.push(Diagnostic::error(None, key, "Missing required field"));
+    }
+}
+
+/// Checks a format string for syntax errors & variables we'll never fill in.
+fn check_format(
+    origin: &Origin,
+    key: &str,
+    format: &FormatString,
+    unknown_severity: Severity,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if let Err(e) = format.validate() {
+        diagnostics.push(Diagnostic::error(Some(origin), key, e.to_string()));
+        return;
+    }
+    let mut unknown = format
+        .variables()
+        .into_iter()
+        .filter(|var| !KNOWN_VARIABLES.contains(&var.as_str()))
+        .collect::<Vec<_>>();
+    unknown.sort();
+    for var in unknown {
+        diagnostics.push(Diagnostic {
+            severity: unknown_severity,
+            ..Diagnostic::error(
+                Some(origin),
+                key,
+                format!(
+                    "Unknown variable {var}; expected one of {}",
+                    KNOWN_VARIABLES.join(", ")
+                ),
+            )
+        });
+    }
+}
+
+fn check_formats(layers: &[Layer], diagnostics: &mut Vec<Diagnostic>) {
+    for layer in layers {
+        let config = &layer.config;
+        for (key, root) in save_keys(&config.system.saves) {
+            // Unknown variables in a save root only capture text that's
+            // thrown away, so they're probably a typo but harmless.
+            check_format(
+                &layer.origin,
+                &key,
+                &root.format,
+                Severity::Warning,
+                diagnostics,
+            );
+        }
+        // Unknown variables in a remote's format end up in the remote's file
+        // names as-is.
+        let remote_formats = [
+            ("romm.format", config.romm.format.as_ref()),
+            ("directory.format", config.directory.format.as_ref()),
+            ("webdav.format", config.webdav.format.as_ref()),
+        ];
+        for (key, format) in remote_formats {
+            if let Some(format) = format {
+                check_format(&layer.origin, key, format, Severity::Error, diagnostics);
+            }
+        }
+        for (name, profile) in &config.profiles {
+            if let Some(format) = &profile.format {
+                let key = format!("profiles.{name}.format");
+                check_format(&layer.origin, &key, format, Severity::Error, diagnostics);
+            }
+        }
+    }
+}
+
+async fn check_save_roots(layers: &[Layer], diagnostics: &mut Vec<Diagnostic>) {
+    for layer in layers {
+        for (key, root) in save_keys(&layer.config.system.saves) {
+            if root.format.validate().is_err() {
+                continue;
+            }
+            let origin = Some(&layer.origin);
+            let Some(prefix) = fixed_root(root) else {
+                diagnostics.push(Diagnostic::error(
+                    origin,
+                    key,
+                    "Save path must start with a fixed directory, not a variable or wildcard",
+                ));
+                continue;
+            };
+            match tokio::fs::metadata(&prefix).await.map(|meta| meta.is_dir()) {
+                Ok(true) => {}
+                Ok(false) => diagnostics.push(Diagnostic::error(
+                    origin,
+                    key,
+                    format!("{} is not a directory", prefix.display()),
+                )),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    diagnostics.push(Diagnostic::warning(
+                        origin,
+                        key,
+                        format!("{} does not exist; it will be skipped", prefix.display()),
+                    ))
+                }
+                Err(e) => diagnostics.push(Diagnostic::error(
+                    origin,
+                    key,
+                    format!("Error reading {}: {e}", prefix.display()),
+                )),
+            }
+        }
+    }
+}
+
+fn check_allow_deny(config: &Config, layers: &Layers, diagnostics: &mut Vec<Diagnostic>) {
+    let mut allow = Vec::new();
+    let mut deny = Vec::new();
+    for layer in &layers.layers {
+        let system = &layer.config.system;
+        let lists = [
+            (
+                "system.allow",
+                system.allow.as_deref().unwrap_or_default(),
+                &mut allow,
+            ),
+            ("system.deny", system.deny.as_slice(), &mut deny),
+        ];
+        for (field, patterns, entries) in lists {
+            entries.extend(patterns.iter().enumerate().map(|(idx, pattern)| ListEntry {
+                origin: &layer.origin,
+                key: format!("{field}[{idx}]"),
+                pattern,
+            }));
+        }
+    }
+
+    if config.system.allow.as_ref().is_some_and(Vec::is_empty) {
+        let origin = layers.origin_of("system.allow", |cfg| cfg.system.allow.is_some());
+        diagnostics.push(Diagnostic::warning(
+            origin.as_ref(),
+            "system.allow",
+            "The allowlist is empty, so no saves will be synced",
+        ));
+    }
+
+    for list in [&allow, &deny] {
+        for (idx, entry) in list.iter().enumerate() {
+            let earlier = &list[..idx];
+            if let Some(other) = earlier.iter().find(|other| other.pattern == entry.pattern) {
+                diagnostics.push(Diagnostic::warning(
+                    Some(entry.origin),
+                    &entry.key,
+                    format!("Duplicate of {} in {}", other.key, other.origin),
+                ));
+                continue;
+            }
+            let Some(path) = entry.pattern.as_path() else {
+                continue;
+            };
+            let covering = list
+                .iter()
+                .find(|other| other.pattern != entry.pattern && other.pattern.matches(path));
+            if let Some(other) = covering {
+                diagnostics.push(Diagnostic::warning(
+                    Some(entry.origin),
+                    &entry.key,
+                    format!(
+                        "Already covered by {:?} ({} in {})",
+                        other.pattern.to_string(),
+                        other.key,
+                        other.origin
+                    ),
+                ));
+            }
+        }
+    }
+
+    for entry in &allow {
+        if let Some(denied) = deny.iter().find(|denied| denied.pattern == entry.pattern) {
+            diagnostics.push(Diagnostic::error(
+                Some(entry.origin),
+                &entry.key,
+                format!(
+                    "Both allowed and denied ({} in {}); it will never be synced",
+                    denied.key, denied.origin
+                ),
+            ));
+            continue;
+        }
+        let Some(path) = entry.pattern.as_path() else {
+            continue;
+        };
+        if let Some(denied) = deny.iter().find(|denied| denied.pattern.matches(path)) {
+            diagnostics.push(Diagnostic::warning(
+                Some(entry.origin),
+                &entry.key,
+                format!(
+                    "Has no effect, since it's denied by {:?} ({} in {})",
+                    denied.pattern.to_string(),
+                    denied.key,
+                    denied.origin
+                ),
+            ));
+            continue;
+        }
+        let under_a_root = config
+            .system
+            .saves
+            .as_slice()
+            .iter()
+            .filter_map(fixed_root)
+            .any(|root| entry.pattern.could_match_under(&root));
+        if !under_a_root {
+            diagnostics.push(Diagnostic::warning(
+                Some(entry.origin),
+                &entry.key,
+                "Isn't inside any of the directories in system.saves",
+            ));
+        }
+    }
+}
+
+fn check_poll_interval(config: &Config, layers: &Layers, diagnostics: &mut Vec<Diagnostic>) {
+    // Every config file has to set a poll interval, so it's from the last one
+    // unless it's overridden.
+    let origin = layers
+        .origin_of("system.poll_interval", |_| false)
+        .or_else(|| {
+            let mut files = layers.layers.iter().rev();
+            let last_file = files.find(|layer| matches!(layer.origin, Origin::File(_)))?;
+            Some(last_file.origin.clone())
+        });
+    let Some(origin) = &origin else {
+        return;
+    };
+    let key = "system.poll_interval";
+    let interval = *config.system.poll_interval;
+    if interval.is_zero() {
+        diagnostics.push(Diagnostic::error(
+            Some(origin),
+            key,
+            "A poll interval of 0 would sync continuously; use \"inf\" to disable polling",
+        ));
+    } else if interval == Duration::MAX {
+        if !config.system.sync_on_file_change {
+            diagnostics.push(Diagnostic::warning(
+                Some(origin),
+                key,
+                "Polling is disabled and sync_on_file_change is off, so saves will only sync \
+                 when a sync is requested",
+            ));
+        }
+    } else if interval > MAX_POLL_INTERVAL {
+        diagnostics.push(Diagnostic::warning(
+            Some(origin),
+            key,
+            format!(
+                "A poll interval of {} will never be reached; use \"inf\" to disable polling",
+                config.system.poll_interval
+            ),
+        ));
+    }
+}
+
+fn check_schedule(config: &Config, layers: &Layers, diagnostics: &mut Vec<Diagnostic>) {
+    let schedule = &config.system.schedule;
+    let intervals = [
+        (
+            "system.schedule.plugged_in",
+            schedule.plugged_in,
+            layers.origin_of("system.schedule.plugged_in", |cfg| {
+                cfg.system.schedule.plugged_in.is_some()
+            }),
+        ),
+        (
+            "system.schedule.on_battery",
+            schedule.on_battery,
+            layers.origin_of("system.schedule.on_battery", |cfg| {
+                cfg.system.schedule.on_battery.is_some()
+            }),
+        ),
+    ];
+    for (key, interval, origin) in intervals {
+        let Some(interval) = interval else {
+            continue;
+        };
+        if interval.is_zero() {
+            diagnostics.push(Diagnostic::error(
+                origin.as_ref(),
+                key,
+                "An interval of 0 would sync continuously; use \"inf\" to disable polling",
+            ));
+        } else if *interval != Duration::MAX && *interval > MAX_POLL_INTERVAL {
+            diagnostics.push(Diagnostic::warning(
+                origin.as_ref(),
+                key,
+                format!(
+                    "An interval of {interval} will never be reached; use \"inf\" to disable \
+                     polling"
+                ),
+            ));
+        }
+    }
+    for layer in &layers.layers {
+        let quiet_hours = &layer.config.system.schedule.quiet_hours;
+        for (idx, window) in quiet_hours.iter().enumerate() {
+            if window.start == window.end {
+                diagnostics.push(Diagnostic::warning(
+                    Some(&layer.origin),
+                    format!("system.schedule.quiet_hours[{idx}]"),
+                    "Starts & ends at the same time, so it never applies",
+                ));
+            }
+        }
+    }
+}
+
+fn check_power(config: &Config, layers: &Layers, diagnostics: &mut Vec<Diagnostic>) {
+    let key = "system.power.min_battery";
+    let min_battery = config.system.power.min_battery();
+    if min_battery > 100 {
+        let origin = layers.origin_of(key, |cfg| cfg.system.power.min_battery.is_some());
+        diagnostics.push(Diagnostic::warning(
+            origin.as_ref(),
+            key,
+            format!(
+                "The battery never gets above {min_battery}%, so syncs will only run while \
+                 charging"
+            ),
+        ));
+    }
+}
+
+/// Checks that `url` is something we can make HTTP requests against.
+fn check_url(origin: Option<&Origin>, key: &str, url: &Url, diagnostics: &mut Vec<Diagnostic>) {
+    if !matches!(url.scheme(), "http" | "https") {
+        diagnostics.push(Diagnostic::error(
+            origin,
+            key,
+            format!(
+                "Unsupported URL scheme {:?}; expected http or https",
+                url.scheme()
+            ),
+        ));
+    } else if url.host().is_none() {
+        diagnostics.push(Diagnostic::error(origin, key, "URL has no host"));
+    } else if url.query().is_some() || url.fragment().is_some() {
+        diagnostics.push(Diagnostic::warning(
+            origin,
+            key,
+            "The query & fragment of the URL are ignored",
+        ));
+    }
+}
+
+fn check_urls(config: &Config, layers: &Layers, diagnostics: &mut Vec<Diagnostic>) {
+    if let Some(url) = &config.romm.url {
+        let origin = layers.origin_of("romm.url", |cfg| cfg.romm.url.is_some());
+        check_url(origin.as_ref(), "romm.url", url, diagnostics);
+    }
+    for (name, profile) in &config.profiles {
+        let Some(url) = &profile.url else {
+            continue;
+        };
+        let key = format!("profiles.{name}.url");
+        let origin = layers.origin_of(&key, |cfg| {
+            cfg.profiles
+                .get(name)
+                .is_some_and(|profile| profile.url.is_some())
+        });
+        check_url(origin.as_ref(), &key, url, diagnostics);
+    }
+    if let Some(url) = &config.webdav.url {
+        let origin = layers.origin_of("webdav.url", |cfg| cfg.webdav.url.is_some());
+        check_url(origin.as_ref(), "webdav.url", url, diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syncer_test_support::TempDir;
+
+    /// A config layer with nothing wrong with it, to put underneath the layer
+    /// a test is checking.
+    const VALID_BASE: &str = r#"
+[romm]
+url = "http://romm.local"
+api_key = "key"
+
+[system]
+saves = ["{saves}/$EMULATOR/$NAME.$EXT"]
+database = "db.sqlite"
+poll_interval = "30m"
+"#;
+
+    /// Config files written to a scratch directory, with `{saves}` in them
+    /// replaced by an existing save directory.
+    struct Files {
+        _dir: TempDir,
+        paths: Vec<PathBuf>,
+    }
+
+    impl Files {
+        async fn new(contents: &[&str]) -> Self {
+            let dir = TempDir::new("config-check").unwrap();
+            let saves = dir.path().join("saves");
+            tokio::fs::create_dir_all(&saves).await.unwrap();
+            let mut paths = Vec::new();
+            for (idx, raw) in contents.iter().enumerate() {
+                let path = dir.path().join(format!("{idx}.toml"));
+                let raw = raw.replace("{saves}", &saves.display().to_string());
+                tokio::fs::write(&path, raw).await.unwrap();
+                paths.push(path);
+            }
+            Self { _dir: dir, paths }
+        }
+
+        async fn check(&self) -> Vec<Diagnostic> {
+            Config::check(self.paths.iter()).await
+        }
+    }
+
+    fn found(
+        diagnostics: &[Diagnostic],
+        severity: Severity,
+        file: &Path,
+        key: &str,
+        needle: &str,
+    ) -> bool {
+        diagnostics.iter().any(|d| {
+            d.severity == severity
+                && d.origin == Some(Origin::File(file.to_path_buf()))
+                && d.key == key
+                && d.message.contains(needle)
+        })
+    }
+
+    #[tokio::test]
+    async fn test_check_valid() {
+        let files = Files::new(&[VALID_BASE]).await;
+        assert_eq!(files.check().await, []);
+    }
+
+    #[tokio::test]
+    async fn test_check_paths() {
+        let files = Files::new(&[
+            r#"
+[romm]
+url = "ftp://romm.local"
+api_key = "key"
+format = "$NAME-$VERSION.$EXT"
+
+[system]
+saves = ["{saves}/$EMULATOR/$NAME.$EXT", "{saves}/missing/$NAME.$EXT"]
+database = "db.sqlite"
+poll_interval = "0s"
+deny = ["{saves}/GB"]
+"#,
+            r#"
+[system]
+poll_interval = "30m"
+saves = "$EMULATOR/$NAME.$EXT"
+allow = ["{saves}/GB/Tetris.sav", "{saves}/GB", "/elsewhere"]
+deny = ["{saves}/GB"]
+"#,
+        ])
+        .await;
+        let (base, user) = (&files.paths[0], &files.paths[1]);
+        let diagnostics = files.check().await;
+        let found = |severity, file, key, needle| found(&diagnostics, severity, file, key, needle);
+        assert!(found(Severity::Error, base, "romm.url", "scheme"));
+        assert!(found(Severity::Error, base, "romm.format", "$VERSION"));
+        assert!(found(
+            Severity::Warning,
+            base,
+            "system.saves[1]",
+            "does not exist"
+        ));
+        assert!(found(
+            Severity::Error,
+            user,
+            "system.saves",
+            "fixed directory"
+        ));
+        assert!(found(
+            Severity::Error,
+            user,
+            "system.allow[1]",
+            "Both allowed and denied"
+        ));
+        assert!(found(
+            Severity::Warning,
+            user,
+            "system.allow[0]",
+            "Already covered"
+        ));
+        assert!(found(
+            Severity::Warning,
+            user,
+            "system.allow[2]",
+            "Isn't inside"
+        ));
+        assert!(found(
+            Severity::Warning,
+            user,
+            "system.deny[0]",
+            "Duplicate"
+        ));
+        // The user file's poll interval overrides the base file's.
+        assert!(!diagnostics.iter().any(|d| d.key == "system.poll_interval"));
+        assert!(!diagnostics.iter().any(|d| d.message.contains("Missing")));
+    }
+
+    #[tokio::test]
+    async fn test_check_unreadable() {
+        let files = Files::new(&[VALID_BASE, "[system]\npoll_interval = \"0s\"\nsaves = 3"]).await;
+        let diagnostics = files.check().await;
+        let unreadable = Diagnostic::error(Some(&Origin::File(files.paths[1].clone())), "", "");
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.origin == unreadable.origin && d.key.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_check_profiles() {
+        // Profiles that saves are bound to have to exist & be complete.
+        let files = Files::new(&[
+            VALID_BASE,
+            "[profiles.friend]\nurl = \"gopher://friend.local\"\n\
+             [system]\npoll_interval = \"30m\"\n\
+             [[rules]]\nrom = \"Tetris\"\nprofile = \"friend\"\n\
+             [[rules]]\nrom = \"Zelda\"\nprofile = \"nope\"\n",
+        ])
+        .await;
+        let diagnostics = files.check().await;
+        let has = |key: &str, needle: &str| {
+            diagnostics.iter().any(|d| {
+                d.severity == Severity::Error && d.key == key && d.message.contains(needle)
+            })
+        };
+        assert!(has("profiles.friend.api_key", "Missing"));
+        assert!(has("profiles.friend.url", "scheme"));
+        assert!(has("profiles.nope", "isn't configured"));
+        assert!(!has("romm.api_key", "Missing"));
+    }
+
+    #[tokio::test]
+    async fn test_check_schedule() {
+        let files = Files::new(&[
+            VALID_BASE,
+            "[system]\npoll_interval = \"30m\"\n\
+             [system.schedule]\non_battery = \"0s\"\nquiet_hours = [\"23:00-07:00\", \"12:00-12:00\"]\n",
+        ])
+        .await;
+        let user = &files.paths[1];
+        let diagnostics = files.check().await;
+        assert!(found(
+            &diagnostics,
+            Severity::Error,
+            user,
+            "system.schedule.on_battery",
+            "sync continuously"
+        ));
+        assert!(found(
+            &diagnostics,
+            Severity::Warning,
+            user,
+            "system.schedule.quiet_hours[1]",
+            "never applies"
+        ));
+        assert!(!diagnostics
+            .iter()
+            .any(|d| d.key == "system.schedule.quiet_hours[0]"));
+    }
+
+    #[tokio::test]
+    async fn test_check_power() {
+        let files = Files::new(&[
+            VALID_BASE,
+            "[system]\npoll_interval = \"30m\"\n[system.power]\nmin_battery = 120\n",
+        ])
+        .await;
+        let diagnostics = files.check().await;
+        assert!(found(
+            &diagnostics,
+            Severity::Warning,
+            &files.paths[1],
+            "system.power.min_battery",
+            "only run while charging"
+        ));
+    }
+}
@@ -0,0 +1,348 @@
+//! Writing changes to a config file in place, keeping its comments & layout.
+
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Serialize};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, TableLike};
+use tracing::warn;
+
+use crate::config::Config;
+use crate::platforms::Platform;
+use crate::utils::write_atomically;
+
+impl Config {
+    /// Writes the changes made to this config since `original` to the default
+    /// location for this platform; see [`Config::save_changes`].
+    pub async fn save_changes_current_platform(
+        &self,
+        original: &Self,
+    ) -> Result<(), anyhow::Error> {
+        self.save_changes(original, Platform::get().config_save_path())
+            .await
+    }
+
+    /// Writes the changes made to this config since `original` to the TOML
+    /// file at `path`, leaving everything else in the file as it was.
+    ///
+    /// Both configs are expected to be the result of joining every config
+    /// layer, and `path` to be one of those layers. Only the keys that differ
+    /// are written, so values from other files & the environment don't leak
+    /// into `path`. Entries added to or removed from lists are added to or
+    /// removed from the list in `path`; entries that came from another file
+    /// can't be removed this way, and are logged & skipped.
+    ///
+    /// The file is replaced atomically, so a crash part way through never
+    /// leaves it half-written.
+    pub async fn save_changes(
+        &self,
+        original: &Self,
+        path: impl AsRef<Path>,
+    ) -> Result<(), anyhow::Error> {
+        let path = path.as_ref();
+        let ext = path
+            .extension()
+            .map(|raw| raw.to_string_lossy())
+            .unwrap_or_default();
+        if !matches!(&*ext, "toml" | "") {
+            return Err(anyhow::anyhow!(
+                "Can only edit TOML config files in place, not {}",
+                path.display()
+            ));
+        }
+        let raw = match tokio::fs::read_to_string(path).await {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Error reading {}", path.display()));
+            }
+        };
+        let edited = edit_document(&raw, original, self)
+            .with_context(|| format!("Error editing {}", path.display()))?;
+        write_atomically(path, edited.as_bytes())
+            .await
+            .with_context(|| format!("Error writing {}", path.display()))
+    }
+}
+
+/// Applies the changes between `before` & `after` to the TOML document `raw`.
+fn edit_document(raw: &str, before: &Config, after: &Config) -> Result<String, anyhow::Error> {
+    let mut doc: DocumentMut = raw.parse()?;
+    apply_changes(doc.as_table_mut(), &to_table(before)?, &to_table(after)?);
+    Ok(doc.to_string())
+}
+
+pub(super) fn to_table(config: &Config) -> Result<toml::Table, anyhow::Error> {
+    match toml::Value::try_from(config)? {
+        toml::Value::Table(table) => Ok(table),
+        other => Err(anyhow::anyhow!(
+            "Config serialized as a {}",
+            other.type_str()
+        )),
+    }
+}
+
+/// The key `key` is written as in `table`, which may be the hyphenated alias
+/// of the field name.
+fn existing_key(table: &dyn TableLike, key: &str) -> String {
+    let hyphenated = key.replace('_', "-");
+    if !table.contains_key(key) && table.contains_key(&hyphenated) {
+        hyphenated
+    } else {
+        key.to_owned()
+    }
+}
+
+fn apply_changes(target: &mut dyn TableLike, before: &toml::Table, after: &toml::Table) {
+    let removed = before.keys().filter(|key| !after.contains_key(*key));
+    for key in after.keys().chain(removed) {
+        let (old, new) = (before.get(key), after.get(key));
+        if old == new {
+            continue;
+        }
+        // Tables & lists that were empty aren't serialized at all.
+        let empty = match new {
+            Some(toml::Value::Table(_)) => Some(toml::Value::Table(toml::Table::new())),
+            Some(toml::Value::Array(_)) => Some(toml::Value::Array(Vec::new())),
+            _ => None,
+        };
+        let old = old.or(empty.as_ref());
+        let file_key = existing_key(target, key);
+        match (old, new) {
+            (_, None) => {
+                target.remove(&file_key);
+            }
+            (Some(toml::Value::Table(old)), Some(toml::Value::Table(new))) => {
+                if !target.get(&file_key).is_some_and(Item::is_table_like) {
+                    target.insert(&file_key, Item::Table(toml_edit::Table::new()));
+                }
+                let table = target
+                    .get_mut(&file_key)
+                    .and_then(Item::as_table_like_mut)
+                    .expect("Just made sure this is a table");
+                apply_changes(table, old, new);
+            }
+            (Some(toml::Value::Array(old)), Some(toml::Value::Array(new)))
+                if target.get(&file_key).is_none_or(is_array) =>
+            {
+                let item = target.get_mut(&file_key);
+                if let Some(item) = apply_array_changes(item, &file_key, old, new) {
+                    target.insert(&file_key, item);
+                }
+            }
+            (_, Some(new)) => {
+                let mut value = to_edit_value(new);
+                match target.get_mut(&file_key) {
+                    Some(Item::Value(existing)) => {
+                        *value.decor_mut() = existing.decor().clone();
+                        *existing = value;
+                    }
+                    _ => {
+                        target.insert(&file_key, Item::Value(value));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn is_array(item: &Item) -> bool {
+    item.is_array() || item.is_array_of_tables()
+}
+
+/// Applies the entries added & removed between `before` & `after` to the array
+/// `item` from the file, returning a new item if there wasn't one yet.
+fn apply_array_changes(
+    item: Option<&mut Item>,
+    key: &str,
+    before: &[toml::Value],
+    after: &[toml::Value],
+) -> Option<Item> {
+    let mut removed = before
+        .iter()
+        .filter(|value| !after.contains(value))
+        .collect::<Vec<_>>();
+    let mut added = after
+        .iter()
+        .filter(|value| !before.contains(value))
+        .cloned()
+        .collect::<VecDeque<_>>();
+
+    let Some(item) = item else {
+        for value in removed {
+            warn!("Can't remove {value} from {key}, since it was set in another config file.");
+        }
+        let added = added.into_iter().collect::<Vec<_>>();
+        return Some(new_array_item(&added));
+    };
+    let existing = match &*item {
+        Item::ArrayOfTables(tables) => tables
+            .iter()
+            .map(|table| Item::Table(table.clone()).into_value().ok())
+            .collect(),
+        item => item
+            .as_array()
+            .map(|array| array.iter().cloned().map(Some).collect())
+            .unwrap_or_else(Vec::new),
+    };
+
+    // Removed entries are replaced in place where possible, so that editing an
+    // entry in an ordered list like `rules` doesn't move it to the end.
+    let mut edits = Vec::new();
+    for (idx, value) in existing.into_iter().enumerate() {
+        let Some(value) = value.and_then(from_edit_value) else {
+            continue;
+        };
+        if let Some(pos) = removed.iter().position(|old| **old == value) {
+            removed.swap_remove(pos);
+            edits.push((idx, added.pop_front()));
+        }
+    }
+    for value in removed {
+        warn!("Can't remove {value} from {key}, since it was set in another config file.");
+    }
+
+    match item {
+        Item::ArrayOfTables(tables) => {
+            for (idx, replacement) in edits.into_iter().rev() {
+                match replacement.as_ref().and_then(to_edit_table) {
+                    Some(table) => {
+                        if let Some(slot) = tables.get_mut(idx) {
+                            *slot = table;
+                        }
+                    }
+                    None => tables.remove(idx),
+                }
+            }
+            for value in added {
+                if let Some(table) = to_edit_table(&value) {
+                    tables.push(table);
+                }
+            }
+        }
+        item => {
+            let array = item.as_array_mut()?;
+            for (idx, replacement) in edits.into_iter().rev() {
+                match replacement {
+                    Some(value) => {
+                        let decor = array.get(idx).map(|old| old.decor().clone());
+                        let mut value = to_edit_value(&value);
+                        if let Some(decor) = decor {
+                            *value.decor_mut() = decor;
+                        }
+                        array.replace(idx, value);
+                    }
+                    None => {
+                        array.remove(idx);
+                    }
+                }
+            }
+            // Lay new entries out like the last existing one, so they end up
+            // on their own line in a multi-line list.
+            let decor = array.iter().last().map(|last| last.decor().clone());
+            for value in added {
+                let mut value = to_edit_value(&value);
+                if let Some(decor) = &decor {
+                    *value.decor_mut() = decor.clone();
+                }
+                array.push_formatted(value);
+            }
+        }
+    }
+    None
+}
+
+/// A new array for the file; a `[[key]]` list if every entry is a table.
+fn new_array_item(values: &[toml::Value]) -> Item {
+    let tables = values.iter().map(to_edit_table).collect::<Option<Vec<_>>>();
+    match tables {
+        Some(tables) if !tables.is_empty() => {
+            let mut array = ArrayOfTables::new();
+            for table in tables {
+                array.push(table);
+            }
+            Item::ArrayOfTables(array)
+        }
+        _ => Item::Value(toml_edit::Value::Array(
+            values.iter().map(to_edit_value).collect(),
+        )),
+    }
+}
+
+pub(super) fn to_edit_value(value: &toml::Value) -> toml_edit::Value {
+    value
+        .serialize(toml_edit::ser::ValueSerializer::new())
+        .expect("TOML values are always valid TOML")
+}
+
+fn to_edit_table(value: &toml::Value) -> Option<toml_edit::Table> {
+    match to_edit_value(value) {
+        toml_edit::Value::InlineTable(table) => Some(table.into_table()),
+        _ => None,
+    }
+}
+
+fn from_edit_value(value: toml_edit::Value) -> Option<toml::Value> {
+    toml::Value::deserialize(value.into_deserializer()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::config::{PolicyRule, SyncDirection};
+
+    const MIYOO_CONFIG: &str = include_str!("../../../../assets/miyoo-mini/config.toml");
+
+    #[test]
+    fn test_edit_document() {
+        let parse = |raw: &str| toml::from_str::<Config>(raw).unwrap();
+        // The shipped URL is a placeholder the user has to fill in.
+        let shipped = MIYOO_CONFIG.replace("$ROMM_URL", "https://romm.local");
+        let before = parse(&shipped);
+        let mut after = before.clone();
+        after.system.poll_interval = Duration::from_secs(60 * 60).into();
+        after.system.sync_on_file_change = false;
+        after
+            .system
+            .deny
+            .push("/mnt/SDCARD/Saves/x".parse().unwrap());
+        after.rules.push(PolicyRule {
+            direction: Some(SyncDirection::PushOnly),
+            ..PolicyRule::for_rom("Tetris")
+        });
+        let edited = edit_document(&shipped, &before, &after).unwrap();
+        assert_eq!(parse(&edited), after);
+        // Comments are kept, and the changed key stays where it was.
+        assert!(edited.contains("# How often the daemon should check for any necessary resyncs\npoll_interval = \"1h\" \n"));
+        assert!(edited.contains("# Currently used variables:"));
+        assert!(edited.contains("[[rules]]\nrom = \"Tetris\""));
+
+        // Entries are only removed from the lists they're in, and edited rules
+        // stay in place.
+        let before = after;
+        let mut after = before.clone();
+        after.system.deny.clear();
+        after.system.sync_on_file_change = true;
+        after.rules[0].direction = Some(SyncDirection::PullOnly);
+        let edited = edit_document(&edited, &before, &after).unwrap();
+        assert_eq!(parse(&edited), after);
+        assert!(!edited.contains("sync_on_file_change = false"));
+
+        // Values from other layers aren't copied into the file.
+        let layer = "[system]\nsaves = \"/saves/$NAME.$EXT\"\npoll-interval = \"5m\"\n";
+        let mut before = parse(layer);
+        before.romm.url = Some("https://romm.local".parse().unwrap());
+        let mut after = before.clone();
+        after.system.poll_interval = Duration::from_secs(60).into();
+        let edited = edit_document(layer, &before, &after).unwrap();
+        assert_eq!(
+            edited,
+            "[system]\nsaves = \"/saves/$NAME.$EXT\"\npoll-interval = \"1m\"\n"
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Showing the effective value of every setting, and where each was set.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::editing::{to_edit_value, to_table};
+use super::layers::Layers;
+use crate::config::{Config, Origin};
+
+/// Settings whose values are masked when explained.
+const SECRET_KEYS: &[&str] = &["romm.api_key", "webdav.password"];
+
+/// The effective value of a single setting.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Explanation {
+    /// The setting's key, like `system.poll_interval`.
+    pub key: String,
+    /// The value, written as TOML, with secrets masked.
+    pub value: String,
+    /// Where the value was set, or empty if it's the default. Lists are
+    /// joined across files, so can have more than one origin.
+    pub origins: Vec<Origin>,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}  # ", self.key, self.value)?;
+        if self.origins.is_empty() {
+            return f.write_str("default");
+        }
+        for (idx, origin) in self.origins.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{origin}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Explains the config for the current platform; see [`Config::explain`].
+    pub async fn explain_current_platform() -> Result<Vec<Explanation>, anyhow::Error> {
+        Self::explain(Self::current_platform_files().await?.into_iter()).await
+    }
+
+    /// Lists the effective value of every setting the given config files &
+    /// the environment add up to, and which of them set it.
+    pub async fn explain(
+        files: impl Iterator<Item = impl AsRef<Path>>,
+    ) -> Result<Vec<Explanation>, anyhow::Error> {
+        let mut layers = Layers::read(files).await;
+        if let Some((_, e)) = layers.errors.drain(..).next() {
+            return Err(e);
+        }
+        let (merged, failed) = layers.merged();
+        if let Some((_, e)) = failed.into_iter().next() {
+            return Err(e);
+        }
+
+        let mut effective = to_table(&merged)?;
+        let system = &merged.system;
+        let concurrency = &system.concurrency;
+        insert_default(&mut effective, "system.skip_hidden", system.skip_hidden)?;
+        insert_default(
+            &mut effective,
+            "system.sync_on_file_change",
+            system.sync_on_file_change,
+        )?;
+        insert_default(&mut effective, "system.remote", system.remote())?;
+        insert_default(&mut effective, "system.backups", system.backup_dir())?;
+        insert_default(
+            &mut effective,
+            "system.concurrency.hashing",
+            concurrency.hashing(),
+        )?;
+        insert_default(
+            &mut effective,
+            "system.concurrency.metadata",
+            concurrency.metadata(),
+        )?;
+        insert_default(
+            &mut effective,
+            "system.concurrency.transfers",
+            concurrency.transfers(),
+        )?;
+
+        let mut sources = Vec::new();
+        for layer in &layers.layers {
+            let mut table = to_table(&layer.config)?;
+            // The `$ROMM_URL` & `$ROMM_API_KEY` layers only set `romm`; the
+            // rest of them are placeholders.
+            if let Origin::Env(_) = layer.origin {
+                table.retain(|key, _| key == "romm");
+            }
+            sources.push((&layer.origin, table));
+        }
+
+        let mut retvl = Vec::new();
+        for (path, value) in leaves(&effective) {
+            let key = path.join(".");
+            let overridden = layers.overrides.iter().rev().find(|o| o.key() == key);
+            let mut origins = match overridden {
+                Some(env_override) => vec![Origin::Env(env_override.var.clone())],
+                None => sources
+                    .iter()
+                    .filter(|(_, table)| lookup(table, &path).is_some())
+                    .map(|(origin, _)| (*origin).clone())
+                    .collect(),
+            };
+            if !value.is_array() && origins.len() > 1 {
+                origins.drain(..origins.len() - 1);
+            }
+            let value = match value {
+                toml::Value::String(secret) if is_secret(&key) => {
+                    format!("{:?}", "*".repeat(secret.len()))
+                }
+                value => to_edit_value(value).to_string().trim().to_owned(),
+            };
+            retvl.push(Explanation {
+                key,
+                value,
+                origins,
+            });
+        }
+        Ok(retvl)
+    }
+}
+
+/// Whether the value of `key` should be masked, including the API keys of
+/// named profiles.
+fn is_secret(key: &str) -> bool {
+    SECRET_KEYS.contains(&key)
+        || key
+            .strip_prefix("profiles.")
+            .is_some_and(|rest| rest.ends_with(".api_key"))
+}
+
+/// Sets the dotted `key` in `table` to `value` if it isn't set already.
+fn insert_default(
+    table: &mut toml::Table,
+    key: &str,
+    value: impl Serialize,
+) -> Result<(), anyhow::Error> {
+    let Ok(value) = toml::Value::try_from(value) else {
+        // Values like `None` have no TOML representation; leave them unset.
+        return Ok(());
+    };
+    let mut parents = key.split('.').collect::<Vec<_>>();
+    let leaf = parents.pop().expect("Keys are never empty");
+    let mut table = table;
+    for part in parents {
+        table = match table
+            .entry(part)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+        {
+            toml::Value::Table(inner) => inner,
+            _ => return Err(anyhow::anyhow!("{part} in {key} isn't a table")),
+        };
+    }
+    table.entry(leaf).or_insert(value);
+    Ok(())
+}
+
+/// Every non-table value in `table`, along with its path.
+fn leaves(table: &toml::Table) -> Vec<(Vec<&str>, &toml::Value)> {
+    let mut retvl = Vec::new();
+    for (key, value) in table {
+        match value {
+            toml::Value::Table(inner) => {
+                for (mut path, value) in leaves(inner) {
+                    path.insert(0, key.as_str());
+                    retvl.push((path, value));
+                }
+            }
+            value => retvl.push((vec![key.as_str()], value)),
+        }
+    }
+    retvl
+}
+
+fn lookup<'a>(table: &'a toml::Table, path: &[&str]) -> Option<&'a toml::Value> {
+    let (first, rest) = path.split_first()?;
+    let value = table.get(*first)?;
+    match (rest, value) {
+        ([], value) => Some(value),
+        (rest, toml::Value::Table(inner)) => lookup(inner, rest),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use syncer_test_support::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_explain() {
+        let dir = TempDir::new("config-explain").unwrap();
+        let base = dir.path().join("config.toml");
+        let dropin = dir.path().join("10-deny.toml");
+        tokio::fs::write(
+            &base,
+            "[romm]\nurl = \"https://romm.local\"\napi-key = \"secret\"\n\
+             [system]\npoll_interval = \"30m\"\nsaves = \"/a/$NAME.$EXT\"\ndeny = [\"/a/x\"]\n",
+        )
+        .await
+        .unwrap();
+        tokio::fs::write(
+            &dropin,
+            "[system]\npoll_interval = \"1h\"\ndeny = [\"/a/y\"]\n",
+        )
+        .await
+        .unwrap();
+
+        let explained = Config::explain([&base, &dropin].into_iter()).await.unwrap();
+        let find = |key: &str| explained.iter().find(|e| e.key == key).unwrap();
+        let file = |path: &Path| Origin::File(path.to_path_buf());
+
+        assert_eq!(find("romm.url").origins, [file(&base)]);
+        assert_eq!(find("romm.api_key").value, "\"******\"");
+        assert_eq!(find("system.poll_interval").value, "\"1h\"");
+        assert_eq!(find("system.poll_interval").origins, [file(&dropin)]);
+        assert_eq!(find("system.deny").origins, [file(&base), file(&dropin)]);
+        assert_eq!(find("system.skip_hidden").value, "true");
+        assert!(find("system.concurrency.transfers").origins.is_empty());
+        assert_eq!(
+            find("system.poll_interval").to_string(),
+            format!("system.poll_interval = \"1h\"  # {}", dropin.display())
+        );
+    }
+}
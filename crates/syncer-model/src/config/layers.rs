@@ -0,0 +1,236 @@
+//! The separate sources a config is built from, kept apart so that settings
+//! can be traced back to where they were set.
+
+use std::path::Path;
+
+use crate::config::{Config, EnvOverride, Origin, RommConfig};
+
+/// One source of config values, before being joined with the others.
+pub(super) struct Layer {
+    pub origin: Origin,
+    pub config: Config,
+}
+
+/// Every source of config values, in the order [`Config::load`] applies them.
+pub(super) struct Layers {
+    /// The config files, followed by `$ROMM_URL` & `$ROMM_API_KEY`.
+    pub layers: Vec<Layer>,
+    /// Overrides applied on top of the joined layers.
+    pub overrides: Vec<EnvOverride>,
+    /// Sources that couldn't be read at all.
+    pub errors: Vec<(Option<Origin>, anyhow::Error)>,
+}
+
+impl Layers {
+    /// Reads each of `files`, along with the environment.
+    pub async fn read(files: impl Iterator<Item = impl AsRef<Path>>) -> Self {
+        let mut layers = Vec::new();
+        let mut errors = Vec::new();
+        for file in files {
+            let origin = Origin::File(file.as_ref().to_path_buf());
+            match Config::read_file(file.as_ref()).await {
+                Ok(config) => layers.push(Layer { origin, config }),
+                Err(e) => errors.push((Some(origin), e)),
+            }
+        }
+        match RommConfig::from_env() {
+            Ok(env) => {
+                let env_layers = [
+                    (
+                        "ROMM_URL",
+                        RommConfig {
+                            url: env.url,
+                            ..Default::default()
+                        },
+                    ),
+                    (
+                        "ROMM_API_KEY",
+                        RommConfig {
+                            api_key: env.api_key,
+                            ..Default::default()
+                        },
+                    ),
+                ];
+                for (var, romm) in env_layers {
+                    if romm != RommConfig::default() {
+                        let config = Config {
+                            romm,
+                            ..Default::default()
+                        };
+                        layers.push(Layer {
+                            origin: Origin::Env(var.to_owned()),
+                            config,
+                        });
+                    }
+                }
+            }
+            Err(e) => errors.push((None, e)),
+        }
+        Self {
+            layers,
+            overrides: EnvOverride::from_env(),
+            errors,
+        }
+    }
+
+    /// The config these layers add up to, along with any overrides that
+    /// couldn't be applied.
+    pub fn merged(&self) -> (Config, Vec<(&EnvOverride, anyhow::Error)>) {
+        let mut merged = self.layers.iter().fold(Config::default(), |acc, layer| {
+            acc.join(layer.config.clone())
+        });
+        let mut failed = Vec::new();
+        for env_override in &self.overrides {
+            match env_override.apply(merged.clone()) {
+                Ok(config) => merged = config,
+                Err(e) => failed.push((env_override, e)),
+            }
+        }
+        (merged, failed)
+    }
+
+    /// Where the setting `key` was last set, which is the value that wins;
+    /// `is_set` checks whether a layer sets it.
+    pub fn origin_of(&self, key: &str, is_set: impl Fn(&Config) -> bool) -> Option<Origin> {
+        if let Some(env_override) = self.overrides.iter().rev().find(|o| o.key() == key) {
+            return Some(Origin::Env(env_override.var.clone()));
+        }
+        self.layers
+            .iter()
+            .rev()
+            .find(|layer| is_set(&layer.config))
+            .map(|layer| layer.origin.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use syncer_test_support::TempDir;
+
+    use super::*;
+
+    fn layer(name: &str, toml: &str) -> Layer {
+        Layer {
+            origin: Origin::File(name.into()),
+            config: toml::from_str(toml).unwrap(),
+        }
+    }
+
+    fn layers(layers: Vec<Layer>, overrides: &[(&str, &str)]) -> Layers {
+        Layers {
+            layers,
+            overrides: overrides
+                .iter()
+                .map(|(var, raw)| EnvOverride::parse(var, raw).unwrap())
+                .collect(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn base() -> Layer {
+        layer(
+            "base.toml",
+            "[romm]\nurl = \"https://romm.local\"\n\
+             [system]\npoll_interval = \"30m\"\nsaves = \"/a/$NAME.$EXT\"\ndeny = [\"/a/x\"]\n",
+        )
+    }
+
+    fn dropin() -> Layer {
+        layer(
+            "dropin.toml",
+            "[romm]\napi_key = \"key\"\n\
+             [system]\npoll_interval = \"1h\"\ndeny = [\"/a/y\"]\n",
+        )
+    }
+
+    #[test]
+    fn test_later_layers_win() {
+        let layered = layers(vec![base(), dropin()], &[]);
+        let (merged, failed) = layered.merged();
+        assert!(failed.is_empty());
+        assert_eq!(*merged.system.poll_interval, Duration::from_secs(60 * 60));
+        // Settings only one layer sets are kept.
+        assert_eq!(merged.romm.url.unwrap().as_str(), "https://romm.local/");
+        assert_eq!(merged.romm.api_key.as_deref(), Some("key"));
+
+        let (merged, _) = layers(vec![dropin(), base()], &[]).merged();
+        assert_eq!(*merged.system.poll_interval, Duration::from_secs(30 * 60));
+    }
+
+    #[test]
+    fn test_overrides_win() {
+        let layers = layers(
+            vec![base(), dropin()],
+            &[
+                ("ROM_SYNC_SYSTEM__POLL_INTERVAL", "5m"),
+                ("ROM_SYNC_SYSTEM__CONCURRENCY__HASHING", "lots"),
+            ],
+        );
+        let (merged, failed) = layers.merged();
+        assert_eq!(*merged.system.poll_interval, Duration::from_secs(5 * 60));
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0.var, "ROM_SYNC_SYSTEM__CONCURRENCY__HASHING");
+    }
+
+    #[test]
+    fn test_origin_of() {
+        let file = |name: &str| Some(Origin::File(name.into()));
+        let layers = layers(
+            vec![base(), dropin()],
+            &[("ROM_SYNC_ROMM__API_KEY", "other")],
+        );
+        assert_eq!(
+            layers.origin_of("system.poll_interval", |_| true),
+            file("dropin.toml")
+        );
+        assert_eq!(
+            layers.origin_of("romm.url", |cfg| cfg.romm.url.is_some()),
+            file("base.toml")
+        );
+        assert_eq!(
+            layers.origin_of("romm.api_key", |cfg| cfg.romm.api_key.is_some()),
+            Some(Origin::Env("ROM_SYNC_ROMM__API_KEY".to_owned()))
+        );
+        assert_eq!(
+            layers.origin_of("romm.format", |cfg| cfg.romm.format.is_some()),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read() {
+        let dir = TempDir::new("config-layers").unwrap();
+        let files = ["10-a.toml", "20-broken.toml", "30-b.toml"].map(|name| dir.path().join(name));
+        let contents = [
+            "[system]\npoll_interval = \"30m\"\n",
+            "[system]\npoll_interval = 3\n",
+            "[system]\npoll_interval = \"1h\"\n",
+        ];
+        for (file, contents) in files.iter().zip(contents) {
+            tokio::fs::write(file, contents).await.unwrap();
+        }
+
+        let layers = Layers::read(files.iter()).await;
+        // Files keep the order they're given in, and ones that can't be read
+        // are left out rather than failing the rest.
+        let read = layers
+            .layers
+            .iter()
+            .filter(|layer| matches!(layer.origin, Origin::File(_)))
+            .map(|layer| layer.origin.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            read,
+            [
+                Origin::File(files[0].clone()),
+                Origin::File(files[2].clone())
+            ]
+        );
+        assert!(layers
+            .errors
+            .iter()
+            .any(|(origin, _)| *origin == Some(Origin::File(files[1].clone()))));
+    }
+}
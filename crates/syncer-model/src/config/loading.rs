@@ -112,18 +112,16 @@ const fn nanos_to_unitted(nanos: u128) -> (u64, &'static str) {
         }
         let (cur_stop_point, _) = DURATION_SUFFIXES[idx];
         let cur_stop_point = cur_stop_point as u128;
-        if nanos % cur_stop_point != 0 {
+        if !nanos.is_multiple_of(cur_stop_point) {
             idx -= 1;
             break;
         }
         idx += 1;
     }
     let (coeff, suffix) = DURATION_SUFFIXES[idx];
-    let coeff = coeff as u128;
-    if coeff == 0 {
-        (nanos as _, suffix)
-    } else {
-        ((nanos / coeff) as _, suffix)
+    match nanos.checked_div(coeff as u128) {
+        Some(count) => (count as _, suffix),
+        None => (nanos as _, suffix),
     }
 }
 
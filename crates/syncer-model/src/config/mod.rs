@@ -1,6 +1,8 @@
 //! User-editable configuration for the application.
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::hash::Hash;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::{env, fmt::Debug};
 
@@ -8,16 +10,36 @@ use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::AsyncReadExt;
 use url::Url;
 
+mod diagnostics;
+pub use diagnostics::{Diagnostic, Origin, Severity, KNOWN_VARIABLES};
+mod editing;
+mod explain;
+pub use explain::Explanation;
+mod layers;
 mod loading;
 use loading::FlattenedList;
 pub use loading::ParseableDuration;
+mod overrides;
+pub use overrides::{EnvOverride, ENV_PREFIX};
+mod patterns;
+pub use patterns::{PathPattern, PathPatternError};
+mod rules;
+pub use rules::{pattern_matches, ConflictPolicy, PolicyRule, SavePolicy};
+mod power;
 mod save_finding;
+pub use power::{PowerConfig, PowerSource, PowerState};
+mod schedule;
+pub use schedule::{CronSchedule, CronScheduleError, ScheduleConfig, TimeWindow, TimeWindowError};
 
-use crate::path_format_strings::FormatString;
+use crate::path_format_strings::{FormatString, FormatStringError};
 use crate::platforms::Platform;
+use crate::utils::write_atomically;
+
+/// The name of the directory of drop-in config files next to each config file.
+pub const DROPIN_DIR_NAME: &str = "conf.d";
 
 /// User-editable configuration for the application.
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
@@ -27,7 +49,28 @@ pub struct Config {
     pub system: SystemConfig,
 
     /// Configuration for dealing with the remote ROMM server.
+    ///
+    /// This is the default profile, used by saves that aren't bound to one of
+    /// the named `profiles`.
+    #[serde(default)]
     pub romm: RommConfig,
+
+    /// Other ROMM servers or accounts, by name, that save roots & rules can
+    /// bind saves to.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, RommConfig>,
+
+    /// Configuration for syncing with a plain directory instead of ROMM.
+    #[serde(default, skip_serializing_if = "DirectoryConfig::is_empty")]
+    pub directory: DirectoryConfig,
+
+    /// Configuration for syncing with a WebDAV share instead of ROMM.
+    #[serde(default, skip_serializing_if = "WebDavConfig::is_empty")]
+    pub webdav: WebDavConfig,
+
+    /// Per-ROM, per-emulator & per-platform overrides of how saves are synced.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<PolicyRule>,
 }
 
 impl Config {
@@ -37,13 +80,91 @@ impl Config {
         Self {
             system: self.system.join(other.system),
             romm: self.romm.join(other.romm),
+            profiles: {
+                let mut profiles = self.profiles;
+                for (name, profile) in other.profiles {
+                    let joined = match profiles.remove(&name) {
+                        Some(existing) => existing.join(profile),
+                        None => profile,
+                    };
+                    profiles.insert(name, joined);
+                }
+                profiles
+            },
+            directory: self.directory.join(other.directory),
+            webdav: self.webdav.join(other.webdav),
+            rules: {
+                let mut rules = self.rules;
+                rules.extend(other.rules);
+                rules
+            },
+        }
+    }
+
+    /// The ROMM settings for the profile `name`, where [`None`] is the default
+    /// `[romm]` section.
+    pub fn profile(&self, name: Option<&str>) -> Option<&RommConfig> {
+        match name {
+            None => Some(&self.romm),
+            Some(name) => self.profiles.get(name),
         }
     }
 
+    /// Every profile that saves can end up bound to, by their `system.saves`
+    /// entry or a rule; [`None`] is the default `[romm]` section.
+    ///
+    /// The default profile is only included if some entry in `system.saves`
+    /// isn't bound to a named one.
+    pub fn used_profiles(&self) -> BTreeSet<Option<&str>> {
+        let roots = self
+            .system
+            .saves
+            .as_slice()
+            .iter()
+            .map(|root| root.profile.as_deref());
+        let rules = self
+            .rules
+            .iter()
+            .filter_map(|rule| rule.profile.as_deref())
+            .map(Some);
+        roots.chain(rules).collect()
+    }
+
     /// Reads the config from the default location(s) for this platform.
     pub async fn load_current_platform() -> Result<Self, anyhow::Error> {
-        let platform = Platform::get();
-        Self::load(platform.config_input_paths()).await
+        Self::load(Self::current_platform_files().await?.into_iter()).await
+    }
+
+    /// The config files for this platform, in the order they're loaded.
+    ///
+    /// Each of the platform's config files is followed by the `.toml` &
+    /// `.json` files in the `conf.d` directory next to it, in name order.
+    pub async fn current_platform_files() -> Result<Vec<PathBuf>, anyhow::Error> {
+        let mut retvl = Vec::new();
+        for file in Platform::get().config_input_paths() {
+            retvl.push(file.to_path_buf());
+            let dropin_dir = file.with_file_name(DROPIN_DIR_NAME);
+            let mut rdr = match tokio::fs::read_dir(&dropin_dir).await {
+                Ok(rdr) => rdr,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Error reading {dropin_dir:?}."));
+                }
+            };
+            let mut dropins = Vec::new();
+            while let Some(ent) = rdr.next_entry().await? {
+                let path = ent.path();
+                let is_config = path
+                    .extension()
+                    .is_some_and(|ext| ext == "toml" || ext == "json");
+                if is_config && ent.file_type().await?.is_file() {
+                    dropins.push(path);
+                }
+            }
+            dropins.sort();
+            retvl.extend(dropins);
+        }
+        Ok(retvl)
     }
 
     /// Writes this config to the default location for this platform.
@@ -51,9 +172,11 @@ impl Config {
         self.save(Platform::get().config_save_path()).await
     }
 
-    /// Writes this config to the given path.
+    /// Writes this config to the given path, replacing the whole file.
     ///
-    /// The file format is based on the extention of the path.
+    /// The file format is based on the extention of the path. To change a few
+    /// settings in a file the user might have edited, prefer
+    /// [`Config::save_changes`].
     pub async fn save(&self, path: impl AsRef<Path>) -> Result<(), anyhow::Error> {
         let path = path.as_ref();
         let ext = path
@@ -68,68 +191,224 @@ impl Config {
                 return Err(anyhow::anyhow!("Unsupported config file extention {other}"));
             }
         };
-        let mut fh = File::create(path).await?;
-        fh.write_all(payload.as_bytes()).await?;
+        write_atomically(path, payload.as_bytes()).await?;
         Ok(())
     }
 
     /// Builds a config from the given config file paths.
     ///
-    /// Later configs in the iterator overwrite earlier values.
+    /// Later configs in the iterator overwrite earlier values, and settings
+    /// from the environment overwrite them all; see [`EnvOverride`].
     pub async fn load(
         files: impl Iterator<Item = impl AsRef<Path>>,
     ) -> Result<Self, anyhow::Error> {
         let mut retvl = Self::default();
         for file in files {
-            let file = file.as_ref();
-            let mut fh = File::open(file)
-                .await
-                .with_context(|| format!("Error opening config file {file:?}."))?;
-            let ext = file.extension().map(|s| s.to_string_lossy());
-            let ext = ext.as_ref().map(|s| s.as_ref());
-            let parsed = match ext {
-                Some("toml") => {
-                    let mut data = String::new();
-                    fh.read_to_string(&mut data)
-                        .await
-                        .with_context(|| format!("Error reading data from TOML file {file:?}."))?;
-                    toml::from_str(&data)
-                        .with_context(|| format!("Error parsing TOML file {file:?}."))?
-                }
-                Some("json") => {
-                    let mut data = String::new();
-                    fh.read_to_string(&mut data)
-                        .await
-                        .with_context(|| format!("Error reading data from JSON file {file:?}."))?;
-
-                    serde_json::from_str(&data)
-                        .with_context(|| format!("Error parsing JSON file {file:?}."))?
-                }
-                _ => {
-                    let mut data = String::new();
-                    fh.read_to_string(&mut data)
-                        .await
-                        .with_context(|| format!("Error reading data from TOML file {file:?}."))?;
-                    toml::from_str(&data)
-                        .with_context(|| format!("Error parsing TOML file {file:?}."))?
-                }
-            };
+            let parsed = Self::read_file(file.as_ref()).await?;
             retvl = retvl.join(parsed);
         }
         let romm_env_config = RommConfig::from_env()?;
         retvl.romm = retvl.romm.join(romm_env_config);
+        for env_override in EnvOverride::from_env() {
+            retvl = env_override.apply(retvl)?;
+        }
         retvl.validate()?;
         Ok(retvl)
     }
+    /// Reads a single config file, without joining it with any others or
+    /// validating it.
+    async fn read_file(file: &Path) -> Result<Self, anyhow::Error> {
+        let mut fh = File::open(file)
+            .await
+            .with_context(|| format!("Error opening config file {file:?}."))?;
+        let ext = file.extension().map(|s| s.to_string_lossy());
+        let ext = ext.as_ref().map(|s| s.as_ref());
+        let parsed = match ext {
+            Some("toml") => {
+                let mut data = String::new();
+                fh.read_to_string(&mut data)
+                    .await
+                    .with_context(|| format!("Error reading data from TOML file {file:?}."))?;
+                toml::from_str(&data)
+                    .with_context(|| format!("Error parsing TOML file {file:?}."))?
+            }
+            Some("json") => {
+                let mut data = String::new();
+                fh.read_to_string(&mut data)
+                    .await
+                    .with_context(|| format!("Error reading data from JSON file {file:?}."))?;
+
+                serde_json::from_str(&data)
+                    .with_context(|| format!("Error parsing JSON file {file:?}."))?
+            }
+            _ => {
+                let mut data = String::new();
+                fh.read_to_string(&mut data)
+                    .await
+                    .with_context(|| format!("Error reading data from TOML file {file:?}."))?;
+                toml::from_str(&data)
+                    .with_context(|| format!("Error parsing TOML file {file:?}."))?
+            }
+        };
+        Ok(parsed)
+    }
+
     /// Checks for any consistency errors in this config, such as missing
     /// required fields or a field having a value of the wrong format.
     pub fn validate(&self) -> Result<(), ConfigError> {
-        self.romm.validate()?;
+        match self.system.remote() {
+            RemoteKind::Romm => {
+                for name in self.used_profiles() {
+                    match (name, self.profile(name)) {
+                        (None, _) => self.romm.validate()?,
+                        (Some(name), Some(profile)) => profile.validate_profile(name)?,
+                        (Some(name), None) => {
+                            return Err(ConfigError::UnknownProfile(name.to_owned()))
+                        }
+                    }
+                }
+            }
+            RemoteKind::Directory => self.directory.validate()?,
+            RemoteKind::WebDav => self.webdav.validate()?,
+        }
         self.system.validate()?;
         Ok(())
     }
 }
 
+/// Which kind of remote saves are synced with.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteKind {
+    /// A ROMM server, configured in the `romm` section.
+    #[default]
+    Romm,
+    /// A plain directory, such as a NAS mount or USB drive, configured in the
+    /// `directory` section.
+    Directory,
+    /// A WebDAV share, such as a Nextcloud folder, configured in the `webdav`
+    /// section.
+    #[serde(rename = "webdav")]
+    WebDav,
+}
+
+/// Configuration for syncing saves with a plain directory.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirectoryConfig {
+    /// The directory to keep saves in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// How saves are laid out within `path`; defaults to
+    /// [`DirectoryConfig::DEFAULT_FORMAT`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<FormatString>,
+}
+
+impl DirectoryConfig {
+    pub const DEFAULT_FORMAT: &str = "$ROM/$NAME.$EXT";
+
+    /// The layout of saves within the directory.
+    pub fn format(&self) -> FormatString {
+        self.format
+            .clone()
+            .unwrap_or_else(|| Self::DEFAULT_FORMAT.into())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.path.is_none() && self.format.is_none()
+    }
+
+    /// Combines this config with another, prioritizing options set in `other`
+    /// over `self` if an option is set in both.
+    pub fn join(self, other: Self) -> Self {
+        Self {
+            path: other.path.or(self.path),
+            format: other.format.or(self.format),
+        }
+    }
+
+    /// Checks for any consistency errors in this config, such as missing
+    /// required fields or a field having a value of the wrong format.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.path
+            .as_ref()
+            .ok_or(ConfigError::MissingField("directory.path"))?;
+        validate_format("directory.format", self.format.as_ref())?;
+        Ok(())
+    }
+}
+
+/// Configuration for syncing saves with a WebDAV share.
+#[derive(Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebDavConfig {
+    /// The URL of the collection to keep saves in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<Url>,
+    /// The user to log in as, if the share needs one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// The password (or app password) for `username`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// How saves are laid out within the share; defaults to
+    /// [`DirectoryConfig::DEFAULT_FORMAT`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<FormatString>,
+}
+
+impl Debug for WebDavConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebDavConfig")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field(
+                "password",
+                &self.password.as_deref().map(|s| "*".repeat(s.len())),
+            )
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl WebDavConfig {
+    /// The layout of saves within the share.
+    pub fn format(&self) -> FormatString {
+        self.format
+            .clone()
+            .unwrap_or_else(|| DirectoryConfig::DEFAULT_FORMAT.into())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Combines this config with another, prioritizing options set in `other`
+    /// over `self` if an option is set in both.
+    pub fn join(self, other: Self) -> Self {
+        Self {
+            url: other.url.or(self.url),
+            username: other.username.or(self.username),
+            password: other.password.or(self.password),
+            format: other.format.or(self.format),
+        }
+    }
+
+    /// Checks for any consistency errors in this config, such as missing
+    /// required fields or a field having a value of the wrong format.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.url
+            .as_ref()
+            .ok_or(ConfigError::MissingField("webdav.url"))?;
+        if self.password.is_some() && self.username.is_none() {
+            return Err(ConfigError::MissingField("webdav.username"));
+        }
+        validate_format("webdav.format", self.format.as_ref())?;
+        Ok(())
+    }
+}
+
 /// Configuration for dealing with the remote ROMM server.
 #[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 #[serde(deny_unknown_fields)]
@@ -204,6 +483,19 @@ impl RommConfig {
         self.api_key
             .as_ref()
             .ok_or(ConfigError::MissingField("romm.api_key"))?;
+        validate_format("romm.format", self.format.as_ref())?;
+        Ok(())
+    }
+
+    /// Like [`RommConfig::validate`], for the named profile `name`.
+    pub fn validate_profile(&self, name: &str) -> Result<(), ConfigError> {
+        let missing = |field| ConfigError::MissingProfileField {
+            profile: name.to_owned(),
+            field,
+        };
+        self.url.as_ref().ok_or_else(|| missing("url"))?;
+        self.api_key.as_ref().ok_or_else(|| missing("api_key"))?;
+        validate_format(&format!("profiles.{name}.format"), self.format.as_ref())?;
         Ok(())
     }
 }
@@ -225,10 +517,11 @@ impl RommConfig {
 #[serde(deny_unknown_fields)]
 pub struct SystemConfig {
     /// The list of formatted strings denoting where in the filesystem to look
-    /// for save files.
+    /// for save files, each optionally limited to syncing in one direction.
     #[serde(default, skip_serializing_if = "FlattenedList::is_empty")]
-    pub saves: FlattenedList<FormatString>,
-    /// Allowlist of specific files/directories to be kept in sync.
+    pub saves: FlattenedList<SaveRoot>,
+    /// Allowlist of files/directories to be kept in sync, as plain paths,
+    /// globs or regexes; see [`PathPattern`].
     ///
     /// If [`None`] then no allowlist will be applied; any file matching an
     /// entry in `saves` and no entry in the deny list will be matched. If
@@ -237,13 +530,14 @@ pub struct SystemConfig {
     /// This field is mainly interacted with via the UI; we don't expect it to
     /// be manipulated directly.
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub allow: Option<Vec<PathBuf>>,
-    /// Denylist of specific files/directories to NOT be kept in sync.
+    pub allow: Option<Vec<PathPattern>>,
+    /// Denylist of files/directories to NOT be kept in sync, as plain paths,
+    /// globs or regexes; see [`PathPattern`].
     ///
     /// This field is mainly interacted with via the UI; we don't expect it to
     /// be manipulated directly.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub deny: Vec<PathBuf>,
+    pub deny: Vec<PathPattern>,
     /// Whether or not we should ignore hidden files; defaults to `true`.
     #[serde(
         default = "default_true",
@@ -251,6 +545,14 @@ pub struct SystemConfig {
         skip_serializing_if = "is_true"
     )]
     pub skip_hidden: bool,
+    /// A gitignore-style file of patterns to skip in every save root, on top
+    /// of any `.rommsyncignore` files inside them.
+    #[serde(
+        default,
+        alias = "ignore-file",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ignore_file: Option<PathBuf>,
     /// Where to put the local sync database, used for checking for modification
     /// conflicts and keep a record of updates.
     pub database: Option<PathBuf>,
@@ -259,6 +561,14 @@ pub struct SystemConfig {
     #[serde(alias = "poll-interval")]
     pub poll_interval: ParseableDuration,
 
+    /// When scheduled syncs run, beyond every `poll_interval`.
+    #[serde(default, skip_serializing_if = "ScheduleConfig::is_empty")]
+    pub schedule: ScheduleConfig,
+
+    /// How syncing is held back or brought forward around the battery.
+    #[serde(default, skip_serializing_if = "PowerConfig::is_empty")]
+    pub power: PowerConfig,
+
     /// If true, we use a filesystem notification library to sync whenever a
     /// save file changes locally on disk.
     #[serde(
@@ -267,6 +577,127 @@ pub struct SystemConfig {
         skip_serializing_if = "is_true"
     )]
     pub sync_on_file_change: bool,
+
+    /// If true, on platforms whose frontend reports the game it's launching
+    /// (like Onion OS), we pull that game's saves right as it starts and push
+    /// them as soon as it exits.
+    #[serde(
+        default = "default_true",
+        alias = "game-hooks",
+        skip_serializing_if = "is_true"
+    )]
+    pub game_hooks: bool,
+
+    /// How much work the daemon is allowed to do in parallel during a sync.
+    #[serde(default, skip_serializing_if = "ConcurrencyConfig::is_empty")]
+    pub concurrency: ConcurrencyConfig,
+
+    /// Which kind of remote to sync saves with; defaults to ROMM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<RemoteKind>,
+
+    /// Where to keep copies of local saves replaced by a pull, for saves whose
+    /// rules ask for backups; defaults to a `backups` directory next to
+    /// `database`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backups: Option<PathBuf>,
+}
+
+/// An entry in `system.saves`.
+///
+/// Written either as a bare format string, which syncs in both directions, or
+/// as a table like `{ path = "...", direction = "push-only" }`.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(from = "RawSaveRoot", into = "RawSaveRoot")]
+pub struct SaveRoot {
+    /// Where the saves are & how to parse their names.
+    pub format: FormatString,
+    /// Which way saves under this root are allowed to sync.
+    pub direction: SyncDirection,
+    /// The named profile saves under this root sync with, rather than the
+    /// default `[romm]` section.
+    pub profile: Option<String>,
+}
+
+impl From<FormatString> for SaveRoot {
+    fn from(format: FormatString) -> Self {
+        Self {
+            format,
+            direction: SyncDirection::default(),
+            profile: None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawSaveRoot {
+    Format(FormatString),
+    Table(SaveRootTable),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SaveRootTable {
+    path: FormatString,
+    #[serde(default)]
+    direction: SyncDirection,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
+}
+
+impl From<RawSaveRoot> for SaveRoot {
+    fn from(raw: RawSaveRoot) -> Self {
+        match raw {
+            RawSaveRoot::Format(format) => format.into(),
+            RawSaveRoot::Table(table) => Self {
+                format: table.path,
+                direction: table.direction,
+                profile: table.profile,
+            },
+        }
+    }
+}
+
+impl From<SaveRoot> for RawSaveRoot {
+    fn from(root: SaveRoot) -> Self {
+        match (root.direction, root.profile) {
+            (SyncDirection::Bidirectional, None) => RawSaveRoot::Format(root.format),
+            (direction, profile) => RawSaveRoot::Table(SaveRootTable {
+                path: root.format,
+                direction,
+                profile,
+            }),
+        }
+    }
+}
+
+/// Which way saves under a [`SaveRoot`] are allowed to sync.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncDirection {
+    /// Push local changes & pull remote ones.
+    #[default]
+    Bidirectional,
+    /// Never pull; the device is only a backup source.
+    PushOnly,
+    /// Never push; local changes stay on the device.
+    PullOnly,
+    /// Never push, and replace any local save that differs from the remote,
+    /// even if it was changed locally since the last sync.
+    MirrorFromRemote,
+}
+
+impl SyncDirection {
+    /// Whether saves may be pushed to the remote.
+    pub const fn allows_push(self) -> bool {
+        matches!(self, Self::Bidirectional | Self::PushOnly)
+    }
+
+    /// Whether saves may be pulled to the device.
+    pub const fn allows_pull(self) -> bool {
+        !matches!(self, Self::PushOnly)
+    }
 }
 
 impl SystemConfig {
@@ -287,13 +718,33 @@ impl SystemConfig {
             saves: self.saves.join(other.saves),
             skip_hidden: self.skip_hidden || other.skip_hidden,
             database: other.database.or(self.database),
+            ignore_file: other.ignore_file.or(self.ignore_file),
             deny,
             allow,
             poll_interval: other.poll_interval,
+            schedule: self.schedule.join(other.schedule),
+            power: self.power.join(other.power),
             sync_on_file_change: other.sync_on_file_change,
+            game_hooks: other.game_hooks,
+            concurrency: self.concurrency.join(other.concurrency),
+            remote: other.remote.or(self.remote),
+            backups: other.backups.or(self.backups),
         }
     }
 
+    /// Which kind of remote to sync saves with.
+    pub fn remote(&self) -> RemoteKind {
+        self.remote.unwrap_or_default()
+    }
+
+    /// Where to keep copies of local saves replaced by a pull.
+    pub fn backup_dir(&self) -> Option<PathBuf> {
+        self.backups.clone().or_else(|| {
+            let database = self.database.as_deref()?;
+            Some(database.with_file_name("backups"))
+        })
+    }
+
     /// Checks for any consistency errors in this config, such as missing
     /// required fields or a field having a value of the wrong format.
     pub fn validate(&self) -> Result<(), ConfigError> {
@@ -303,12 +754,97 @@ impl SystemConfig {
         if self.database.is_none() {
             return Err(ConfigError::MissingField("system.database"));
         }
+        for root in self.saves.as_slice() {
+            validate_format("system.saves", Some(&root.format))?;
+        }
         Ok(())
     }
 }
 
+/// Limits on how much work the daemon does in parallel during a sync.
+///
+/// Each save moves through hashing, metadata lookups against the server, and
+/// finally an upload or download; each stage has its own limit so that one
+/// slow transfer doesn't hold up every other save. The defaults are kept small
+/// for devices like the Miyoo Mini.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    /// How many local save files can be hashed at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hashing: Option<usize>,
+    /// How many metadata lookups against the ROMM server can be in flight at
+    /// once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<usize>,
+    /// How many save uploads & downloads can run at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfers: Option<usize>,
+}
+
+impl ConcurrencyConfig {
+    pub const DEFAULT_HASHING: usize = 1;
+    pub const DEFAULT_METADATA: usize = 2;
+    pub const DEFAULT_TRANSFERS: usize = 1;
+
+    /// The effective hashing limit; always at least 1.
+    pub fn hashing(&self) -> usize {
+        self.hashing.unwrap_or(Self::DEFAULT_HASHING).max(1)
+    }
+
+    /// The effective metadata lookup limit; always at least 1.
+    pub fn metadata(&self) -> usize {
+        self.metadata.unwrap_or(Self::DEFAULT_METADATA).max(1)
+    }
+
+    /// The effective transfer limit; always at least 1.
+    pub fn transfers(&self) -> usize {
+        self.transfers.unwrap_or(Self::DEFAULT_TRANSFERS).max(1)
+    }
+
+    /// Whether none of the limits have been set explicitly.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Combines this config with another, prioritizing options set in `other`
+    /// over `self` if an option is set in both.
+    pub fn join(self, other: Self) -> Self {
+        Self {
+            hashing: other.hashing.or(self.hashing),
+            metadata: other.metadata.or(self.metadata),
+            transfers: other.transfers.or(self.transfers),
+        }
+    }
+}
+
+/// Checks the optional format string in `field` for syntax errors.
+fn validate_format(field: &str, format: Option<&FormatString>) -> Result<(), ConfigError> {
+    let Some(format) = format else {
+        return Ok(());
+    };
+    format
+        .validate()
+        .map_err(|source| ConfigError::InvalidFormat {
+            field: field.to_owned(),
+            source,
+        })
+}
+
 #[derive(Debug, Error)]
 pub enum ConfigError {
     #[error("Missing required field {0}")]
     MissingField(&'static str),
+    #[error("Missing required field profiles.{profile}.{field}")]
+    MissingProfileField {
+        profile: String,
+        field: &'static str,
+    },
+    #[error("Unknown profile {0}; expected a [profiles.{0}] section")]
+    UnknownProfile(String),
+    #[error("Invalid format string in {field}: {source}")]
+    InvalidFormat {
+        field: String,
+        source: FormatStringError,
+    },
 }
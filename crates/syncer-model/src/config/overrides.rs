@@ -0,0 +1,193 @@
+//! Overriding any config setting from the environment.
+
+use std::env;
+
+use anyhow::Context;
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+
+use crate::config::Config;
+
+/// The prefix for environment variables overriding config settings.
+pub const ENV_PREFIX: &str = "ROM_SYNC_";
+
+/// A config setting overridden by an environment variable.
+///
+/// Variables are named after the setting's key, uppercased, with `__` between
+/// each part, eg `ROM_SYNC_SYSTEM__POLL_INTERVAL` for `system.poll_interval`.
+/// Values are read as TOML, like `false` or `["a", "b"]`, falling back to a
+/// plain string if they aren't valid TOML or the setting is a string.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct EnvOverride {
+    /// The name of the environment variable.
+    pub var: String,
+    /// The path to the overridden setting, like `["system", "poll_interval"]`.
+    pub path: Vec<String>,
+    /// The value of the variable, as written.
+    pub raw: String,
+}
+
+impl EnvOverride {
+    /// Parses the environment variable `var`, if it overrides a setting.
+    ///
+    /// Variables without a `__`, like `ROM_SYNC_LOG`, configure other things
+    /// and are skipped.
+    pub fn parse(var: &str, raw: &str) -> Option<Self> {
+        let key = var.strip_prefix(ENV_PREFIX)?;
+        if !key.contains("__") {
+            return None;
+        }
+        let path = key
+            .split("__")
+            .map(str::to_ascii_lowercase)
+            .collect::<Vec<_>>();
+        if path.iter().any(String::is_empty) {
+            return None;
+        }
+        Some(Self {
+            var: var.to_owned(),
+            path,
+            raw: raw.to_owned(),
+        })
+    }
+
+    /// Every override set in the current environment, in name order.
+    pub fn from_env() -> Vec<Self> {
+        let mut retvl = env::vars_os()
+            .filter_map(|(var, raw)| Self::parse(var.to_str()?, raw.to_str()?))
+            .collect::<Vec<_>>();
+        retvl.sort_by(|a, b| a.var.cmp(&b.var));
+        retvl
+    }
+
+    /// The dotted key of the overridden setting, like `system.poll_interval`.
+    pub fn key(&self) -> String {
+        self.path.join(".")
+    }
+
+    /// Sets the overridden setting in `config`.
+    pub fn apply(&self, config: Config) -> Result<Config, anyhow::Error> {
+        let mut root = toml::Value::try_from(&config)?;
+        let leaf = self.path.last().expect("Paths are never empty");
+        let is_string = matches!(
+            self.parent_table(&mut root)?.get(leaf),
+            Some(toml::Value::String(_))
+        );
+        // Strings stay strings even if they look like another type. A setting
+        // that isn't set gives no hint of its type, so a value like `12345`
+        // that doesn't fit as a number is tried as a string too.
+        let typed = if is_string {
+            None
+        } else {
+            parse_value(&self.raw)
+        };
+        if let Some(typed) = typed {
+            let mut attempt = root.clone();
+            self.parent_table(&mut attempt)?.insert(leaf.clone(), typed);
+            if let Ok(config) = attempt.try_into() {
+                return Ok(config);
+            }
+        }
+        self.parent_table(&mut root)?
+            .insert(leaf.clone(), toml::Value::String(self.raw.clone()));
+        root.try_into()
+            .with_context(|| format!("Invalid value for ${}", self.var))
+    }
+
+    /// The table in `root` holding the overridden setting, created if it's
+    /// missing.
+    fn parent_table<'a>(
+        &self,
+        root: &'a mut toml::Value,
+    ) -> Result<&'a mut toml::Table, anyhow::Error> {
+        let (_, parents) = self.path.split_last().expect("Paths are never empty");
+        let mut table = root;
+        for part in parents {
+            table = table
+                .as_table_mut()
+                .with_context(|| format!("${} doesn't name a setting", self.var))?
+                .entry(part.as_str())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        }
+        table
+            .as_table_mut()
+            .with_context(|| format!("${} doesn't name a setting", self.var))
+    }
+}
+
+/// Parses a single TOML value, like `3` or `["a", "b"]`.
+fn parse_value(raw: &str) -> Option<toml::Value> {
+    let value = raw.parse::<toml_edit::Value>().ok()?;
+    toml::Value::deserialize(value.into_deserializer()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::config::RemoteKind;
+
+    #[test]
+    fn test_env_overrides() {
+        assert_eq!(EnvOverride::parse("ROM_SYNC_LOG", "debug"), None);
+        assert_eq!(EnvOverride::parse("ROMM_URL", "http://x"), None);
+        let parse = |var: &str, raw: &str| EnvOverride::parse(var, raw).unwrap();
+        let interval = parse("ROM_SYNC_SYSTEM__POLL_INTERVAL", "1h");
+        assert_eq!(interval.key(), "system.poll_interval");
+
+        let config: Config = toml::from_str(
+            "[romm]\napi_key = \"abc\"\n[system]\npoll_interval = \"30m\"\nsaves = \"/a/$NAME.$EXT\"",
+        )
+        .unwrap();
+        let config = interval.apply(config).unwrap();
+        assert_eq!(*config.system.poll_interval, Duration::from_secs(60 * 60));
+        let config = parse("ROM_SYNC_SYSTEM__SKIP_HIDDEN", "false")
+            .apply(config)
+            .unwrap();
+        assert!(!config.system.skip_hidden);
+        let config = parse("ROM_SYNC_SYSTEM__REMOTE", "directory")
+            .apply(config)
+            .unwrap();
+        assert_eq!(config.system.remote(), RemoteKind::Directory);
+        // Strings stay strings even if they look like another type.
+        let config = parse("ROM_SYNC_ROMM__API_KEY", "12345")
+            .apply(config)
+            .unwrap();
+        assert_eq!(config.romm.api_key.as_deref(), Some("12345"));
+        let config = parse("ROM_SYNC_SYSTEM__CONCURRENCY__HASHING", "3")
+            .apply(config)
+            .unwrap();
+        assert_eq!(config.system.concurrency.hashing(), 3);
+        let config = parse("ROM_SYNC_SYSTEM__DENY", r#"["**/*.rtc", "/a/b"]"#)
+            .apply(config)
+            .unwrap();
+        assert_eq!(config.system.deny.len(), 2);
+
+        // Settings that aren't set yet have no type to go by.
+        let unset: Config =
+            toml::from_str("[system]\npoll_interval = \"30m\"\nsaves = \"/a/$NAME.$EXT\"").unwrap();
+        let unset = parse("ROM_SYNC_ROMM__API_KEY", "12345")
+            .apply(unset)
+            .unwrap();
+        assert_eq!(unset.romm.api_key.as_deref(), Some("12345"));
+        let unset = parse("ROM_SYNC_WEBDAV__PASSWORD", "123456")
+            .apply(unset)
+            .unwrap();
+        assert_eq!(unset.webdav.password.as_deref(), Some("123456"));
+        let unset = parse("ROM_SYNC_SYSTEM__POWER__MIN_BATTERY", "20")
+            .apply(unset)
+            .unwrap();
+        assert_eq!(unset.system.power.min_battery(), 20);
+        assert!(parse("ROM_SYNC_SYSTEM__SKIP_HIDDEN", "3")
+            .apply(unset)
+            .is_err());
+
+        assert!(parse("ROM_SYNC_SYSTEM__NOPE", "1")
+            .apply(config.clone())
+            .is_err());
+        assert!(parse("ROM_SYNC_SYSTEM__POLL_INTERVAL__X", "1")
+            .apply(config)
+            .is_err());
+    }
+}
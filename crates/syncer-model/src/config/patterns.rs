@@ -0,0 +1,210 @@
+//! Path patterns used by the `system.allow` and `system.deny` lists.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use glob::MatchOptions;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// An entry in the `system.allow` or `system.deny` lists.
+///
+/// Written as a string, which is one of:
+/// * A plain path, matching that file or directory & everything under it.
+/// * `glob:PATTERN`, or any path containing a `*`, matching every file whose
+///   path (or whose parent directory's path) matches the glob. `*` and `?`
+///   don't match across `/`, while `**` matches any number of directories.
+/// * `regex:PATTERN`, matching every file whose path contains a match for the
+///   regular expression.
+///
+/// # Examples
+/// ```
+/// # use std::path::Path;
+/// # use syncer_model::config::PathPattern;
+/// let rtc: PathPattern = "**/*.rtc".parse().unwrap();
+/// assert!(rtc.matches(Path::new("/mnt/SDCARD/Saves/Pokemon Gold.rtc")));
+/// assert!(!rtc.matches(Path::new("/mnt/SDCARD/Saves/Pokemon Gold.srm")));
+///
+/// let dir: PathPattern = "/mnt/SDCARD/Saves/CurrentProfile".parse().unwrap();
+/// assert!(dir.matches(Path::new("/mnt/SDCARD/Saves/CurrentProfile/saves/a.srm")));
+///
+/// let re: PathPattern = r"regex:/PPSSPP/.*\.(ini|bin)$".parse().unwrap();
+/// assert!(re.matches(Path::new("/mnt/SDCARD/PPSSPP/SAVEDATA/ICON0.bin")));
+/// ```
+#[derive(Clone)]
+pub struct PathPattern {
+    source: String,
+    kind: PatternKind,
+}
+
+#[derive(Clone)]
+enum PatternKind {
+    Prefix(PathBuf),
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+/// Errors from parsing a [`PathPattern`].
+#[derive(Debug, Error)]
+pub enum PathPatternError {
+    #[error("Invalid glob {0:?}: {1}")]
+    Glob(String, #[source] glob::PatternError),
+    #[error("Invalid regex {0:?}: {1}")]
+    Regex(String, #[source] regex::Error),
+}
+
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+impl PathPattern {
+    /// The path this pattern matches everything under, if it's a plain path
+    /// rather than a glob or regex.
+    pub fn as_path(&self) -> Option<&Path> {
+        match &self.kind {
+            PatternKind::Prefix(prefix) => Some(prefix),
+            PatternKind::Glob(_) | PatternKind::Regex(_) => None,
+        }
+    }
+
+    /// Whether `path` is matched by this pattern.
+    pub fn matches(&self, path: &Path) -> bool {
+        match &self.kind {
+            PatternKind::Prefix(prefix) => path.starts_with(prefix),
+            PatternKind::Glob(glob) => path
+                .ancestors()
+                .any(|pt| glob.matches_path_with(pt, GLOB_OPTIONS)),
+            PatternKind::Regex(regex) => regex.is_match(&path.to_string_lossy()),
+        }
+    }
+
+    /// Whether this pattern could match anything under the directory `dir`.
+    ///
+    /// Only plain paths can be ruled out up front; globs & regexes are assumed
+    /// to possibly match somewhere under any directory.
+    pub fn could_match_under(&self, dir: &Path) -> bool {
+        match &self.kind {
+            PatternKind::Prefix(prefix) => prefix.starts_with(dir) || dir.starts_with(prefix),
+            PatternKind::Glob(_) | PatternKind::Regex(_) => true,
+        }
+    }
+}
+
+impl From<PathBuf> for PathPattern {
+    fn from(value: PathBuf) -> Self {
+        Self {
+            source: value.to_string_lossy().into_owned(),
+            kind: PatternKind::Prefix(value),
+        }
+    }
+}
+
+impl std::str::FromStr for PathPattern {
+    type Err = PathPatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let kind = if let Some(raw) = s.strip_prefix("regex:") {
+            let regex = Regex::new(raw).map_err(|e| PathPatternError::Regex(raw.to_owned(), e))?;
+            PatternKind::Regex(regex)
+        } else if let Some(raw) = s.strip_prefix("glob:").or(s.contains('*').then_some(s)) {
+            let glob =
+                glob::Pattern::new(raw).map_err(|e| PathPatternError::Glob(raw.to_owned(), e))?;
+            PatternKind::Glob(glob)
+        } else {
+            PatternKind::Prefix(PathBuf::from(s))
+        };
+        Ok(Self {
+            source: s.to_owned(),
+            kind,
+        })
+    }
+}
+
+impl TryFrom<String> for PathPattern {
+    type Error = PathPatternError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<PathPattern> for String {
+    fn from(value: PathPattern) -> Self {
+        value.source
+    }
+}
+
+impl Serialize for PathPattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for PathPattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Debug for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.source, f)
+    }
+}
+
+impl fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl PartialEq for PathPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for PathPattern {}
+
+impl Hash for PathPattern {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.source.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_patterns() {
+        let parse = |raw: &str| raw.parse::<PathPattern>().unwrap();
+        let path = Path::new("/mnt/SDCARD/Saves/CurrentProfile/saves/gpSP/Pokemon [!].sav");
+
+        // Plain paths keep matching by prefix, even with glob characters in.
+        assert!(parse("/mnt/SDCARD/Saves").matches(path));
+        assert!(parse(path.to_str().unwrap()).matches(path));
+        assert!(!parse("/mnt/SDCARD/Save").matches(path));
+        assert!(parse(path.to_str().unwrap()).as_path().is_some());
+
+        assert!(parse("**/gpSP").matches(path));
+        assert!(parse("**/*.sav").matches(path));
+        assert!(!parse("/mnt/SDCARD/*.sav").matches(path));
+        assert!(parse("glob:/mnt/SDCARD/Saves/?urrentProfile").matches(path));
+        assert!(parse("regex:(?i)pokemon").matches(path));
+        assert!(!parse("regex:^Pokemon").matches(path));
+
+        assert!(parse("**/*.rtc").could_match_under(Path::new("/mnt")));
+        assert!(parse("/mnt/SDCARD").could_match_under(Path::new("/mnt/SDCARD/Saves")));
+        assert!(parse("/mnt/SDCARD/Saves/x").could_match_under(Path::new("/mnt/SDCARD")));
+        assert!(!parse("/mnt/SDCARD/Roms").could_match_under(Path::new("/mnt/SDCARD/Saves")));
+
+        assert!("regex:(".parse::<PathPattern>().is_err());
+        assert!("glob:[".parse::<PathPattern>().is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! How the daemon treats the device's battery.
+
+use serde::{Deserialize, Serialize};
+
+/// Where the device is currently getting its power from.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PowerSource {
+    /// Plugged in, or a device without a battery at all.
+    External,
+    /// Running off its battery.
+    Battery,
+}
+
+/// The device's power supply, as last read by the daemon.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PowerState {
+    pub source: PowerSource,
+    /// How full the battery is, in percent, if the device reports it.
+    pub battery: Option<u8>,
+}
+
+impl PowerState {
+    /// A device that's plugged in, for when the power supply can't be read.
+    pub const EXTERNAL: Self = Self {
+        source: PowerSource::External,
+        battery: None,
+    };
+}
+
+/// The `system.power` table.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PowerConfig {
+    /// The battery percentage below which syncs are deferred while on battery,
+    /// since running out mid-transfer can leave a save half written; defaults
+    /// to 10. `0` never defers.
+    #[serde(
+        default,
+        alias = "min-battery",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub min_battery: Option<u8>,
+    /// Whether to push any changed saves one last time before the daemon is
+    /// stopped or the device suspends; defaults to `true`.
+    #[serde(default, alias = "final-push", skip_serializing_if = "Option::is_none")]
+    pub final_push: Option<bool>,
+}
+
+impl PowerConfig {
+    pub const DEFAULT_MIN_BATTERY: u8 = 10;
+
+    /// The effective battery threshold, in percent.
+    pub fn min_battery(&self) -> u8 {
+        self.min_battery.unwrap_or(Self::DEFAULT_MIN_BATTERY)
+    }
+
+    /// Whether a final push should run before the daemon stops or the device
+    /// suspends.
+    pub fn final_push(&self) -> bool {
+        self.final_push.unwrap_or(true)
+    }
+
+    /// Whether none of the options have been set explicitly.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Combines this config with another, prioritizing options set in `other`
+    /// over `self` if an option is set in both.
+    pub fn join(self, other: Self) -> Self {
+        Self {
+            min_battery: other.min_battery.or(self.min_battery),
+            final_push: other.final_push.or(self.final_push),
+        }
+    }
+
+    /// The battery level, if a sync should be deferred on `power` because the
+    /// battery is too low.
+    ///
+    /// A device that's plugged in, or doesn't report its battery level, never
+    /// defers.
+    pub fn low_battery(&self, power: PowerState) -> Option<u8> {
+        if power.source != PowerSource::Battery {
+            return None;
+        }
+        power.battery.filter(|&level| level < self.min_battery())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_low_battery() {
+        let on_battery = |battery| PowerState {
+            source: PowerSource::Battery,
+            battery,
+        };
+        let defaults = PowerConfig::default();
+        assert_eq!(defaults.low_battery(on_battery(Some(5))), Some(5));
+        assert_eq!(defaults.low_battery(on_battery(Some(10))), None);
+        assert_eq!(defaults.low_battery(on_battery(None)), None);
+        let charging = PowerState {
+            source: PowerSource::External,
+            battery: Some(5),
+        };
+        assert_eq!(defaults.low_battery(charging), None);
+
+        let never = PowerConfig {
+            min_battery: Some(0),
+            final_push: None,
+        };
+        assert_eq!(never.low_battery(on_battery(Some(0))), None);
+        let joined = defaults.join(toml::from_str("min_battery = 30").unwrap());
+        assert_eq!(joined.low_battery(on_battery(Some(25))), Some(25));
+        assert!(joined.final_push());
+    }
+}
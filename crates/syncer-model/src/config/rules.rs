@@ -0,0 +1,333 @@
+//! Per-ROM, per-emulator & per-platform overrides of how saves are synced.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, SyncDirection};
+
+/// An entry in the `rules` table.
+///
+/// A rule applies to every save matching all of the selectors it sets; a rule
+/// with no selectors applies to every save. Each rule only overrides the
+/// settings it sets, and later rules take priority over earlier ones.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRule {
+    /// Pattern matched against the save's ROM name; see [`pattern_matches`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rom: Option<String>,
+    /// Pattern matched against the save's `$EMULATOR`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emulator: Option<String>,
+    /// Pattern matched against the save's `$PLATFORM`, if its path format
+    /// captures one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+
+    /// Overrides the direction set on the save's `system.saves` entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<SyncDirection>,
+    /// How to resolve a save that changed on both sides since the last sync.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conflict: Option<ConflictPolicy>,
+    /// How many old copies of a local save to keep when a pull replaces it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backups: Option<usize>,
+    /// Whether matching saves are synced at all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// The named profile matching saves sync with, overriding the one set on
+    /// the save's `system.saves` entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+impl PolicyRule {
+    /// A rule applying to exactly the ROM `rom`, without setting anything yet.
+    pub fn for_rom(rom: &str) -> Self {
+        Self {
+            rom: Some(rom.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// Whether this rule applies to a save with the given ROM, emulator &
+    /// platform.
+    pub fn matches(&self, rom: &str, emulator: Option<&str>, platform: Option<&str>) -> bool {
+        let selector_matches = |pattern: &Option<String>, value: Option<&str>| match pattern {
+            None => true,
+            Some(pattern) => value.is_some_and(|value| pattern_matches(pattern, value)),
+        };
+        selector_matches(&self.rom, Some(rom))
+            && selector_matches(&self.emulator, emulator)
+            && selector_matches(&self.platform, platform)
+    }
+
+    /// Whether this rule selects exactly the ROM `rom` and nothing else.
+    fn is_for_rom(&self, rom: &str) -> bool {
+        self.rom.as_deref() == Some(rom) && self.emulator.is_none() && self.platform.is_none()
+    }
+}
+
+/// How to resolve a save that changed on both the device & the remote since
+/// the last sync.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// Skip the save & report the conflict.
+    #[default]
+    Error,
+    /// Push the device's copy.
+    PreferDevice,
+    /// Pull the remote's copy.
+    PreferRemote,
+    /// Keep whichever copy was modified most recently.
+    PreferNewest,
+}
+
+/// The settings that apply to a particular save once every matching rule has
+/// been applied.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SavePolicy {
+    pub direction: SyncDirection,
+    pub conflict: ConflictPolicy,
+    pub backups: usize,
+    pub enabled: bool,
+    /// The named profile the save syncs with, or [`None`] for the default
+    /// `[romm]` section.
+    pub profile: Option<String>,
+}
+
+impl Default for SavePolicy {
+    fn default() -> Self {
+        Self {
+            direction: SyncDirection::default(),
+            conflict: ConflictPolicy::default(),
+            backups: 0,
+            enabled: true,
+            profile: None,
+        }
+    }
+}
+
+impl SavePolicy {
+    /// Overrides whatever `rule` sets.
+    pub fn apply(&mut self, rule: &PolicyRule) {
+        self.direction = rule.direction.unwrap_or(self.direction);
+        self.conflict = rule.conflict.unwrap_or(self.conflict);
+        self.backups = rule.backups.unwrap_or(self.backups);
+        self.enabled = rule.enabled.unwrap_or(self.enabled);
+        if let Some(profile) = &rule.profile {
+            self.profile = Some(profile.clone());
+        }
+    }
+}
+
+impl Config {
+    /// Works out the policy for a save with the given ROM, emulator & platform,
+    /// found under a `system.saves` entry syncing in `direction` with
+    /// `profile`.
+    pub fn policy_for(
+        &self,
+        direction: SyncDirection,
+        profile: Option<&str>,
+        rom: &str,
+        emulator: Option<&str>,
+        platform: Option<&str>,
+    ) -> SavePolicy {
+        let mut policy = SavePolicy {
+            direction,
+            profile: profile.map(str::to_owned),
+            ..Default::default()
+        };
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(rom, emulator, platform))
+            .for_each(|rule| policy.apply(rule));
+        policy
+    }
+
+    /// The last rule applying to exactly the ROM `rom`, adding an empty one
+    /// if there isn't one yet.
+    ///
+    /// Mainly used by the UI, which edits rules one ROM at a time.
+    pub fn rom_rule_mut(&mut self, rom: &str) -> &mut PolicyRule {
+        match self.rules.iter().rposition(|rule| rule.is_for_rom(rom)) {
+            Some(idx) => &mut self.rules[idx],
+            None => {
+                self.rules.push(PolicyRule::for_rom(rom));
+                self.rules.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+/// Matches `value` against a case-insensitive wildcard `pattern`, where `*`
+/// matches any run of characters and `?` matches any single character.
+///
+/// # Examples
+/// ```
+/// # use syncer_model::config::pattern_matches;
+/// assert!(pattern_matches("pokemon*", "Pokemon Emerald"));
+/// assert!(pattern_matches("Mario Kart ?", "Mario Kart 8"));
+/// assert!(!pattern_matches("Zelda", "Zelda II"));
+/// ```
+pub fn pattern_matches(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    let value = value.to_lowercase().chars().collect::<Vec<_>>();
+    // Classic greedy wildcard matching, backtracking to the most recent `*`.
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    v = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_rules(rules: &str) -> Config {
+        toml::from_str(&format!(
+            "[system]\npoll_interval = \"30m\"\nsaves = \"/a/$NAME.$EXT\"\n{rules}"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_policy_for() {
+        let config = config_with_rules(
+            r#"
+[[rules]]
+backups = 3
+conflict = "prefer-newest"
+
+[[rules]]
+emulator = "gpSP"
+direction = "push-only"
+
+[[rules]]
+rom = "Pokemon*"
+conflict = "prefer-device"
+profile = "friend"
+
+[[rules]]
+rom = "pokemon emerald"
+platform = "gba"
+enabled = false
+"#,
+        );
+        let policy = |rom, emulator, platform| {
+            config.policy_for(SyncDirection::Bidirectional, None, rom, emulator, platform)
+        };
+
+        // Only the catch-all rule applies.
+        let tetris = policy("Tetris", Some("mGBA"), None);
+        assert_eq!(tetris.direction, SyncDirection::Bidirectional);
+        assert_eq!(tetris.conflict, ConflictPolicy::PreferNewest);
+        assert_eq!(tetris.backups, 3);
+        assert!(tetris.enabled);
+        assert_eq!(tetris.profile, None);
+
+        // Later rules override only what they set.
+        let ruby = policy("Pokemon Ruby", Some("gpSP"), None);
+        assert_eq!(ruby.direction, SyncDirection::PushOnly);
+        assert_eq!(ruby.conflict, ConflictPolicy::PreferDevice);
+        assert_eq!(ruby.backups, 3);
+        assert_eq!(ruby.profile.as_deref(), Some("friend"));
+
+        // A selector on a variable the save doesn't have never matches.
+        assert!(policy("Pokemon Emerald", Some("gpSP"), None).enabled);
+        assert!(!policy("Pokemon Emerald", Some("gpSP"), Some("GBA")).enabled);
+
+        // Without matching rules, the save's own entry decides.
+        let config = config_with_rules("");
+        let policy = config.policy_for(SyncDirection::PullOnly, Some("home"), "Tetris", None, None);
+        assert_eq!(
+            policy,
+            SavePolicy {
+                direction: SyncDirection::PullOnly,
+                profile: Some("home".to_owned()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_rom_rule_mut() {
+        let mut config = config_with_rules(
+            r#"
+[[rules]]
+rom = "Tetris"
+emulator = "gambatte"
+backups = 1
+
+[[rules]]
+rom = "Tetris"
+backups = 2
+
+[[rules]]
+rom = "Tetris"
+backups = 3
+"#,
+        );
+        // Edits the last rule for exactly that ROM, not ones with more
+        // selectors.
+        config.rom_rule_mut("Tetris").enabled = Some(false);
+        assert_eq!(config.rules.len(), 3);
+        assert_eq!(config.rules[2].enabled, Some(false));
+        assert_eq!(config.rules[2].backups, Some(3));
+        assert_eq!(config.rules[0].enabled, None);
+
+        // Adds a new rule at the end, so it takes priority.
+        config.rom_rule_mut("Zelda").direction = Some(SyncDirection::PullOnly);
+        assert_eq!(config.rules.len(), 4);
+        assert_eq!(
+            config.rules[3],
+            PolicyRule {
+                direction: Some(SyncDirection::PullOnly),
+                ..PolicyRule::for_rom("Zelda")
+            }
+        );
+        // Wildcard rules aren't exact matches for a ROM.
+        config.rules.push(PolicyRule::for_rom("Mario*"));
+        config.rom_rule_mut("Mario Kart").backups = Some(1);
+        assert_eq!(config.rules.len(), 6);
+        assert_eq!(config.rules[4].backups, None);
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        assert!(pattern_matches("", ""));
+        assert!(!pattern_matches("", "a"));
+        assert!(pattern_matches("*", ""));
+        assert!(pattern_matches("**", "anything"));
+        assert!(!pattern_matches("?", ""));
+        assert!(pattern_matches("a*b*c", "aXbYbZc"));
+        assert!(!pattern_matches("a*b*c", "aXbYbZ"));
+        // Backtracks past an earlier partial match.
+        assert!(pattern_matches("*ab", "aab"));
+        assert!(pattern_matches("*issi*", "Mississippi"));
+        assert!(pattern_matches("MARIO*", "mario kart"));
+        // Characters outside ASCII compare as whole characters.
+        assert!(pattern_matches("Pok?mon", "Pokémon"));
+        assert!(pattern_matches("pokÉmon", "PokéMON"));
+    }
+}
@@ -5,8 +5,9 @@ use futures::future::ready;
 use futures::{stream, FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
 use tracing::{debug, error, trace, warn};
 
-use crate::config::Config;
-use crate::path_format_strings::FormatString;
+use crate::config::{Config, SaveRoot};
+use crate::ignore::{IgnoreRules, IgnoreStack};
+use crate::path_format_strings::MetadataResolveError;
 use crate::utils::async_walkdir;
 
 impl Config {
@@ -14,16 +15,23 @@ impl Config {
     ///
     /// Returns:
     /// * The path on the local filesystem to the save file
-    /// * The format string within `config.system.saves` that the path matched
+    /// * The entry within `config.system.saves` that the path matched
     /// * The variables pulled from the path string based on that format string
     pub fn possible_saves(
         &self,
-    ) -> impl Stream<Item = Result<(PathBuf, &FormatString, HashMap<String, String>), io::Error>> + '_
+    ) -> impl Stream<Item = Result<(PathBuf, &SaveRoot, HashMap<String, String>), io::Error>> + '_
     {
         let skip_hidden = self.system.skip_hidden;
-        let full_tree = stream::iter(self.save_roots())
-            .map(io::Result::Ok)
-            .map_ok(|root| async_walkdir(&root))
+        let global_ignores = stream::once(self.global_ignores());
+        let full_tree = global_ignores
+            .map_ok(move |global| {
+                stream::iter(self.save_roots())
+                    .map(move |root| {
+                        let ignores = IgnoreStack::root(&root, global.clone());
+                        io::Result::Ok(async_walkdir(&root, ignores))
+                    })
+                    .try_flatten()
+            })
             .try_flatten();
 
         let matches_allowdeny = full_tree
@@ -31,11 +39,11 @@ impl Config {
                 let Some(allow) = self.system.allow.as_deref() else {
                     return ready(true);
                 };
-                let res = allow.iter().any(|prefix| pt.starts_with(prefix));
+                let res = allow.iter().any(|pattern| pattern.matches(pt));
                 ready(res)
             })
             .try_filter(move |pt| {
-                let res = !self.system.deny.iter().any(|prefix| pt.starts_with(prefix));
+                let res = !self.system.deny.iter().any(|pattern| pattern.matches(pt));
                 ready(res)
             });
 
@@ -58,8 +66,13 @@ impl Config {
             let mut fmt = None;
             for saves in self.system.saves.as_slice().iter() {
                 trace!("Trying fmt: {saves:?}");
-                let Ok(cur) = saves.resolve(&path) else {
-                    continue;
+                let cur = match saves.format.resolve(&path) {
+                    Ok(cur) => cur,
+                    Err(e @ MetadataResolveError::Ambiguous { .. }) => {
+                        warn!("Skipping format for {}: {e}", path.display());
+                        continue;
+                    }
+                    Err(_) => continue,
                 };
                 if cur.len() > variables.len() {
                     fmt = Some(saves);
@@ -77,6 +90,24 @@ impl Config {
         })
     }
 
+    /// Loads the rules from `system.ignore_file`, if set.
+    ///
+    /// A missing file is logged & otherwise treated as empty, so a typo in the
+    /// config doesn't stop every save from syncing.
+    async fn global_ignores(&self) -> io::Result<Option<IgnoreRules>> {
+        let Some(path) = self.system.ignore_file.as_deref() else {
+            return Ok(None);
+        };
+        match IgnoreRules::load(path).await {
+            Ok(rules) => Ok(Some(rules)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                warn!("Ignore file {} was not found; skipping.", path.display());
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     /// Finds the list of static directories that could possibly contain saves
     /// we need to sync.
     ///
@@ -85,15 +116,17 @@ impl Config {
     /// `$VARIABLE`.
     pub fn save_roots(&self) -> impl Iterator<Item = PathBuf> + '_ {
         let all_fmts = self.system.saves.as_slice().iter();
-        let possible = all_fmts.map(|s| s.prefix()).map(PathBuf::from);
+        let possible = all_fmts.map(|s| s.format.prefix()).map(PathBuf::from);
         possible
             .filter(|pt| {
                 let Some(allowlist) = self.system.allow.as_deref() else {
                     return true;
                 };
-                allowlist.iter().any(|prefix| pt.starts_with(prefix))
+                allowlist
+                    .iter()
+                    .any(|pattern| pattern.could_match_under(pt))
             })
-            .filter(|pt| !self.system.deny.iter().any(|prefix| pt.starts_with(prefix)))
+            .filter(|pt| !self.system.deny.iter().any(|pattern| pattern.matches(pt)))
             .filter(
                 |pt| match std::fs::symlink_metadata(pt).map(|meta| meta.is_dir()) {
                     Ok(true) => true,
@@ -0,0 +1,310 @@
+//! When scheduled syncs are allowed to run, on top of `system.poll_interval`.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveTime, TimeZone};
+use croner::Cron;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::{ParseableDuration, PowerSource, SystemConfig};
+
+/// The `system.schedule` table.
+///
+/// `quiet_hours` & `only_while_charging` hold back every sync the daemon
+/// starts by itself, including those triggered by a save changing on disk or
+/// the network coming back. Syncs requested from a UI, and those around
+/// launching a game or suspending the device, always run.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// How often to sync while plugged in; defaults to `system.poll_interval`.
+    #[serde(default, alias = "plugged-in", skip_serializing_if = "Option::is_none")]
+    pub plugged_in: Option<ParseableDuration>,
+    /// How often to sync while on battery; defaults to
+    /// `system.poll_interval`.
+    #[serde(default, alias = "on-battery", skip_serializing_if = "Option::is_none")]
+    pub on_battery: Option<ParseableDuration>,
+    /// Cron-style times to sync at, on top of the interval, like
+    /// `"0 7 * * *"` for every morning at 7.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub at: Vec<CronSchedule>,
+    /// Times of day during which automatic syncs are skipped, like
+    /// `"23:00-07:00"`.
+    #[serde(default, alias = "quiet-hours", skip_serializing_if = "Vec::is_empty")]
+    pub quiet_hours: Vec<TimeWindow>,
+    /// Whether automatic syncs only run while plugged in; defaults to `false`.
+    #[serde(
+        default,
+        alias = "only-while-charging",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub only_while_charging: Option<bool>,
+}
+
+impl ScheduleConfig {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Combines this config with another, prioritizing options set in `other`
+    /// over `self` if an option is set in both.
+    pub fn join(self, other: Self) -> Self {
+        let mut at = self.at;
+        at.extend(other.at);
+        let mut quiet_hours = self.quiet_hours;
+        quiet_hours.extend(other.quiet_hours);
+        Self {
+            plugged_in: other.plugged_in.or(self.plugged_in),
+            on_battery: other.on_battery.or(self.on_battery),
+            at,
+            quiet_hours,
+            only_while_charging: other.only_while_charging.or(self.only_while_charging),
+        }
+    }
+
+    /// The first of the `at` times strictly after `now`, if any.
+    pub fn next_at<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        self.at.iter().filter_map(|cron| cron.next_after(now)).min()
+    }
+
+    /// Why an automatic sync shouldn't run at `now` on `power`, if it
+    /// shouldn't.
+    pub fn blocked_by<Tz: TimeZone>(
+        &self,
+        now: &DateTime<Tz>,
+        power: PowerSource,
+    ) -> Option<String> {
+        if self.only_while_charging == Some(true) && power == PowerSource::Battery {
+            return Some("only_while_charging is set and the device is on battery".to_owned());
+        }
+        let time = now.time();
+        self.quiet_hours
+            .iter()
+            .find(|window| window.contains(time))
+            .map(|window| format!("it's within the quiet hours {window}"))
+    }
+}
+
+impl SystemConfig {
+    /// How often to sync on `power`.
+    pub fn poll_interval_on(&self, power: PowerSource) -> Duration {
+        let interval = match power {
+            PowerSource::External => self.schedule.plugged_in,
+            PowerSource::Battery => self.schedule.on_battery,
+        };
+        *interval.unwrap_or(self.poll_interval)
+    }
+}
+
+/// A cron-style time, with five fields for the minute, hour, day of the month,
+/// month & day of the week.
+///
+/// # Examples
+/// ```
+/// # use chrono::{TimeZone, Utc};
+/// # use syncer_model::config::CronSchedule;
+/// let mornings: CronSchedule = "30 7 * * MON-FRI".parse().unwrap();
+/// let friday = Utc.with_ymd_and_hms(2025, 1, 3, 8, 0, 0).unwrap();
+/// let monday = Utc.with_ymd_and_hms(2025, 1, 6, 7, 30, 0).unwrap();
+/// assert_eq!(mornings.next_after(&friday), Some(monday));
+/// ```
+#[derive(Clone)]
+pub struct CronSchedule {
+    source: String,
+    cron: Cron,
+}
+
+/// Errors from parsing a [`CronSchedule`].
+#[derive(Debug, Error)]
+#[error("Invalid cron time {0:?}: {1}")]
+pub struct CronScheduleError(String, #[source] croner::errors::CronError);
+
+impl CronSchedule {
+    /// The first time strictly after `now` matching this schedule.
+    pub fn next_after<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        self.cron.find_next_occurrence(now, false).ok()
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = CronScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cron = Cron::new(s)
+            .parse()
+            .map_err(|e| CronScheduleError(s.to_owned(), e))?;
+        Ok(Self {
+            source: s.to_owned(),
+            cron,
+        })
+    }
+}
+
+impl Serialize for CronSchedule {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for CronSchedule {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Debug for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.source, f)
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl PartialEq for CronSchedule {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for CronSchedule {}
+
+impl Hash for CronSchedule {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.source.hash(state);
+    }
+}
+
+/// A range of times of day, like `22:30-07:00`, which wraps around midnight
+/// if it ends before it starts.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+/// Errors from parsing a [`TimeWindow`].
+#[derive(Debug, Error)]
+#[error("Invalid time range {0:?}; expected something like \"23:00-07:00\"")]
+pub struct TimeWindowError(String);
+
+impl TimeWindow {
+    /// Whether `time` falls within this window; the start is included, the end
+    /// isn't.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = TimeWindowError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || TimeWindowError(s.to_owned());
+        let (start, end) = s.split_once('-').ok_or_else(err)?;
+        let parse = |raw: &str| NaiveTime::parse_from_str(raw.trim(), "%H:%M").map_err(|_| err());
+        Ok(Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+impl Serialize for TimeWindow {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeWindow {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn test_schedule() {
+        let schedule: ScheduleConfig = toml::from_str(
+            r#"
+on_battery = "2h"
+at = ["0 7 * * *", "0 19 * * *"]
+quiet_hours = ["23:00-06:30"]
+only_while_charging = true
+"#,
+        )
+        .unwrap();
+        let at = |h, m| Utc.with_ymd_and_hms(2025, 3, 1, h, m, 0).unwrap();
+
+        assert_eq!(schedule.next_at(&at(6, 0)), Some(at(7, 0)));
+        assert_eq!(schedule.next_at(&at(7, 0)), Some(at(19, 0)));
+        assert_eq!(
+            schedule.next_at(&at(20, 0)),
+            Some(Utc.with_ymd_and_hms(2025, 3, 2, 7, 0, 0).unwrap())
+        );
+
+        assert!(schedule
+            .blocked_by(&at(12, 0), PowerSource::External)
+            .is_none());
+        assert!(schedule
+            .blocked_by(&at(12, 0), PowerSource::Battery)
+            .is_some());
+        assert!(schedule
+            .blocked_by(&at(23, 30), PowerSource::External)
+            .is_some());
+        assert!(schedule
+            .blocked_by(&at(6, 15), PowerSource::External)
+            .is_some());
+        assert!(schedule
+            .blocked_by(&at(6, 30), PowerSource::External)
+            .is_none());
+
+        let system: SystemConfig =
+            toml::from_str("poll_interval = \"15m\"\n[schedule]\non_battery = \"2h\"\n").unwrap();
+        assert_eq!(
+            system.poll_interval_on(PowerSource::External),
+            Duration::from_secs(15 * 60)
+        );
+        assert_eq!(
+            system.poll_interval_on(PowerSource::Battery),
+            Duration::from_secs(2 * 60 * 60)
+        );
+
+        assert!("25:00-07:00".parse::<TimeWindow>().is_err());
+        assert!("* * *".parse::<CronSchedule>().is_err());
+        let written = toml::to_string(&schedule).unwrap();
+        assert_eq!(
+            toml::from_str::<ScheduleConfig>(&written).unwrap(),
+            schedule
+        );
+    }
+}
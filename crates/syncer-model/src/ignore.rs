@@ -0,0 +1,186 @@
+//! Gitignore-style ignore files for save directories.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use glob::{MatchOptions, Pattern};
+use tracing::warn;
+
+/// The name of the per-directory ignore files honoured while looking for saves.
+pub const IGNORE_FILE_NAME: &str = ".rommsyncignore";
+
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// A single line of an ignore file.
+#[derive(Clone, Debug)]
+struct IgnoreRule {
+    pattern: Pattern,
+    /// Whether this is a `!pattern` rule, re-including what it matches.
+    negated: bool,
+    /// Whether this rule only matches directories, ie it ended in a `/`.
+    dir_only: bool,
+}
+
+/// The parsed contents of an ignore file.
+#[derive(Clone, Debug, Default)]
+pub struct IgnoreRules(Vec<IgnoreRule>);
+
+impl IgnoreRules {
+    /// Parses the contents of an ignore file, following the same syntax as
+    /// `.gitignore`:
+    ///
+    /// * Blank lines & lines starting with `#` are skipped.
+    /// * A leading `!` re-includes anything a previous rule ignored.
+    /// * A trailing `/` only matches directories.
+    /// * Patterns containing a `/` anywhere else are relative to the directory
+    ///   the rules apply to; other patterns match names at any depth.
+    ///
+    /// Lines that aren't valid globs are logged & skipped.
+    pub fn parse(raw: &str) -> Self {
+        let rules = raw
+            .lines()
+            .filter_map(|line| {
+                let line = line.trim_end();
+                if line.is_empty() || line.starts_with('#') {
+                    return None;
+                }
+                let (negated, line) = match line.strip_prefix('!') {
+                    Some(rest) => (true, rest),
+                    None => (false, line.strip_prefix('\\').unwrap_or(line)),
+                };
+                let (dir_only, line) = match line.strip_suffix('/') {
+                    Some(rest) => (true, rest),
+                    None => (false, line),
+                };
+                let glob = match line.strip_prefix('/') {
+                    Some(anchored) => anchored.to_owned(),
+                    None if line.contains('/') => line.to_owned(),
+                    None => format!("**/{line}"),
+                };
+                match Pattern::new(&glob) {
+                    Ok(pattern) => Some(IgnoreRule {
+                        pattern,
+                        negated,
+                        dir_only,
+                    }),
+                    Err(e) => {
+                        warn!("Skipping invalid ignore rule {line:?}: {e}");
+                        None
+                    }
+                }
+            })
+            .collect();
+        Self(rules)
+    }
+
+    /// Reads & parses the ignore file at `path`.
+    pub async fn load(path: &Path) -> io::Result<Self> {
+        let raw = tokio::fs::read_to_string(path).await?;
+        Ok(Self::parse(&raw))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether the last rule matching `relative` ignores or re-includes it, or
+    /// [`None`] if no rule matches it at all.
+    fn decide(&self, relative: &Path, is_dir: bool) -> Option<bool> {
+        self.0
+            .iter()
+            .rev()
+            .filter(|rule| is_dir || !rule.dir_only)
+            .find(|rule| rule.pattern.matches_path_with(relative, GLOB_OPTIONS))
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// The ignore rules in effect for a particular directory: those from its own
+/// ignore file, then its parent's, and so on up to the save root.
+#[derive(Debug)]
+pub struct IgnoreStack {
+    /// The directory these rules are relative to.
+    base: PathBuf,
+    rules: IgnoreRules,
+    parent: Option<Arc<IgnoreStack>>,
+}
+
+impl IgnoreStack {
+    /// The rules for the save root `root`, starting with the global ignore
+    /// rules from the config, if any.
+    pub fn root(root: &Path, global: Option<IgnoreRules>) -> Arc<Self> {
+        Arc::new(Self {
+            base: root.to_path_buf(),
+            rules: global.unwrap_or_default(),
+            parent: None,
+        })
+    }
+
+    /// Adds the rules from the ignore file in `dir`, if it has rules of its
+    /// own.
+    pub fn push(self: &Arc<Self>, dir: &Path, rules: IgnoreRules) -> Arc<Self> {
+        if rules.is_empty() {
+            return Arc::clone(self);
+        }
+        Arc::new(Self {
+            base: dir.to_path_buf(),
+            rules,
+            parent: Some(Arc::clone(self)),
+        })
+    }
+
+    /// Whether `path` should be skipped.
+    ///
+    /// Rules from deeper ignore files take priority, and within a file later
+    /// rules take priority over earlier ones.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut cur = Some(self);
+        while let Some(layer) = cur {
+            let decision = path
+                .strip_prefix(&layer.base)
+                .ok()
+                .and_then(|relative| layer.rules.decide(relative, is_dir));
+            if let Some(ignored) = decision {
+                return ignored;
+            }
+            cur = layer.parent.as_deref();
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_rules() {
+        let root = Path::new("/saves");
+        let global = IgnoreRules::parse("# Junk\n*.bak\n\ncheats/\n/config.ini\n");
+        let stack = IgnoreStack::root(root, Some(global));
+
+        assert!(stack.is_ignored(Path::new("/saves/gpSP/Pokemon.sav.bak"), false));
+        assert!(stack.is_ignored(Path::new("/saves/gpSP/cheats"), true));
+        assert!(!stack.is_ignored(Path::new("/saves/gpSP/cheats"), false));
+        assert!(stack.is_ignored(Path::new("/saves/config.ini"), false));
+        assert!(!stack.is_ignored(Path::new("/saves/gpSP/config.ini"), false));
+        assert!(!stack.is_ignored(Path::new("/saves/gpSP/Pokemon.sav"), false));
+
+        let nested = stack.push(
+            Path::new("/saves/gpSP"),
+            IgnoreRules::parse("!keep.bak\ntmp/*\n\\!odd"),
+        );
+        assert!(!nested.is_ignored(Path::new("/saves/gpSP/keep.bak"), false));
+        assert!(nested.is_ignored(Path::new("/saves/gpSP/other.bak"), false));
+        assert!(nested.is_ignored(Path::new("/saves/gpSP/tmp/a.sav"), false));
+        assert!(!nested.is_ignored(Path::new("/saves/gpSP/x/tmp/a.sav"), false));
+        assert!(nested.is_ignored(Path::new("/saves/gpSP/!odd"), false));
+        // Nested rules don't leak into sibling directories.
+        assert!(stack.is_ignored(Path::new("/saves/mGBA/keep.bak"), false));
+    }
+}
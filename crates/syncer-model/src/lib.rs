@@ -2,6 +2,7 @@
 
 pub mod commands;
 pub mod config;
+mod ignore;
 pub mod path_format_strings;
 pub mod platforms;
 mod utils;
@@ -1,30 +1,66 @@
 //! Template strings for use in building & parsing paths via metadata.
 
 use std::collections::HashSet;
-use std::ffi::OsStr;
 use std::hash::Hash;
 use std::path::Path;
 use std::{borrow::Borrow, collections::HashMap};
 
+use chrono::format::{Item, Parsed, StrftimeItems};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+/// How timestamp variables without a `{...}` format are written: ISO 8601's
+/// basic format in UTC, which unlike RFC 3339 has no `:`s for FAT32 SD cards &
+/// Windows shares to reject.
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.fZ";
+
 /// A template for matching & extracting information from filesystem paths.
 ///
 /// # Syntax
-/// The syntax is very similar to shell variable substitutions. Variables within
-/// the string are given by a `$` followed by an alpha-numeric name, and
-/// constant portions are anything other than that. When resolving the variable
-/// values from a string the algorithm attempts to match as much as possible.
+/// The syntax is very similar to shell variable substitutions:
+/// * `$NAME` is a variable, where the name is any run of alpha-numeric
+///   characters. Variables never span more than one path component.
+/// * `${NAME}` is the same variable, for when it's followed by more
+///   alpha-numeric characters, and `${NAME:-default}` is a variable that
+///   falls back to `default` when it has no value.
+/// * `*` matches any run of characters within a single path component, and
+///   `**/` matches any number of directories, including none. A `**` that
+///   isn't followed by a `/` matches anything at all.
+/// * `$TIMESTAMP`, `$CREATED` and `$UPDATED` can be followed by a strftime-style
+///   format, like `$TIMESTAMP{%Y%m%d-%H%M%S}`. Without one they're written in
+///   [`DEFAULT_TIMESTAMP_FORMAT`], and read in either that or RFC 3339.
+/// * `[...]` is an optional segment, which may or may not be present.
+/// * `$$`, `$[`, `$]` and `$*` are a literal `$`, `[`, `]` and `*`.
+///
+/// Anything else is a constant portion that has to match exactly.
+///
+/// # Resolving
+/// When resolving the variable values from a string, `$TIMESTAMP`, `$CREATED`
+/// and `$UPDATED` only match timestamps in their format and `$EXT` never
+/// contains a `.`, so that eg `$NAME-$TIMESTAMP.$EXT` can pick apart names full of `-`s
+/// and `.`s. (If no interpretation has valid values for them, any values are
+/// accepted instead.) Optional segments are present whenever they can be. If
+/// the string can still be split up in more than one way, such as
+/// `my-rom-save.sav` with `$ROM-$NAME.$EXT`, resolving fails rather than
+/// guessing.
 ///
 /// # Examples
 ///
 /// Given the format string `/root/subroot/$DIRNAME/$FILE-$TIMESTAMP.$EXT`:
 /// * The [`FormatString::prefix`] value is `/root/subroot/`.
-/// * The string `/root/subroot/my-dir/my-rom-file-ts.sav` will produce a
-///   [`FormatString::resolve`] value of `{"$DIRNAME" : "my-dir", "$FILE" :
-///   "my-rom-file", "$TIMESTAMP" : "ts", $"EXT" : "sav"}`.
-///  
+/// * The string `/root/subroot/my-dir/my-rom-file-20240501T102030Z.sav`
+///   will produce a [`FormatString::resolve`] value of `{"$DIRNAME" :
+///   "my-dir", "$FILE" : "my-rom-file", "$TIMESTAMP" :
+///   "2024-05-01T10:20:30+00:00", "$EXT" : "sav"}`.
+///
+/// The format string `/saves/**/[$EMULATOR/]$ROM.$EXT` matches saves nested
+/// any number of directories deep under `/saves`, optionally taking the
+/// emulator from the directory they're in.
+///
+/// Format strings are only checked for syntax errors by
+/// [`FormatString::validate`]; an invalid format string never matches any
+/// path, and is otherwise treated as plain text.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct FormatString(String);
@@ -42,10 +78,25 @@ impl<'a> From<&'a str> for FormatString {
 }
 
 impl FormatString {
+    /// The format string as written.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Checks this format string for syntax errors, such as an unterminated
+    /// `${` or `[`.
+    pub fn validate(&self) -> Result<(), FormatStringError> {
+        parse(&self.0).map(|_| ())
+    }
+
     /// Produces a new output string from the given [`FormatString`] and
     /// variable substitution map.
     ///
-    /// Missing variables aren't substituted and extra variables are ignored.
+    /// Missing variables without a default aren't substituted and extra
+    /// variables are ignored. Optional segments are only included if all of
+    /// their variables have a value, and wildcards are left out entirely.
+    /// Timestamp variables are expected to be RFC 3339, and are written in
+    /// their format.
     ///
     /// # Examples
     /// ```rust
@@ -59,26 +110,25 @@ impl FormatString {
     /// vars.insert("$UNUSED", "d");
     /// let output = fmt.build_with_vars(&vars);
     /// assert_eq!(output.as_str(), "a-postpre-b-prepost-c.$EXT");
+    ///
+    /// let fmt : FormatString = "/saves/**/[$EMULATOR/]${ROM:-unknown}.sav".into();
+    /// assert_eq!(fmt.build_with_vars(&vars), "/saves/unknown.sav");
     /// ```
     pub fn build_with_vars<K, V>(&self, vars: &HashMap<K, V>) -> String
     where
         K: Borrow<str> + Eq + Hash,
         V: AsRef<str>,
     {
+        let Ok(tokens) = parse(&self.0) else {
+            return self.0.clone();
+        };
         let mut retvl = String::new();
-        for portion in split_variable_portions(&self.0) {
-            if portion.starts_with("$") {
-                let nxt = vars.get(portion).map(|s| s.as_ref()).unwrap_or(portion);
-                retvl.push_str(nxt);
-            } else {
-                retvl.push_str(portion);
-            }
-        }
+        build_tokens(&tokens, vars, &mut retvl);
         retvl
     }
 
     /// The string representing the last filesystem folder in this
-    /// [`FormatString`] without any variables.
+    /// [`FormatString`] without any variables, wildcards or optional segments.
     ///
     /// # Example
     ///
@@ -87,33 +137,45 @@ impl FormatString {
     /// let formats : &[FormatString] = &[
     ///     "/my/long/prefix/var-$SUFFIX".into(),
     ///     "/my/long/prefix/$PREFIX-var".into(),
-    ///     "/my/long/prefix/$EMBEDDED/subdir".into()
+    ///     "/my/long/prefix/$EMBEDDED/subdir".into(),
+    ///     "/my/long/prefix/**/$ROM.sav".into(),
+    ///     "/my/long/prefix/[$EMULATOR/]$ROM.sav".into(),
     /// ];
     /// for fmt in formats {
     ///     assert_eq!(fmt.prefix(), "/my/long/prefix/");
     /// }
     /// assert_eq!(FormatString::from("my-file-path.txt").prefix(), "my-file-path.txt");
+    /// assert_eq!(FormatString::from("/cost/$$5/$ROM").prefix(), "/cost/$5/");
     /// ```
-    pub fn prefix(&self) -> &str {
-        let mut itr = split_variable_portions(&self.0);
-        let Some(head) = itr.next() else { return "/" };
-        if itr.next().is_none() {
-            return head;
-        }
-        if head.ends_with('/') {
-            return head;
-        }
-        match head.rsplit_once('/') {
-            Some((ret_without_slash, _)) => &self.0[..ret_without_slash.len() + 1],
-            None => "/",
+    pub fn prefix(&self) -> String {
+        let Ok(tokens) = parse(&self.0) else {
+            return self.0.clone();
+        };
+        let mut head = String::new();
+        for token in &tokens {
+            match token {
+                Token::Literal(lit) => head.push_str(lit),
+                _ => {
+                    return match head.rfind('/') {
+                        Some(idx) => head[..=idx].to_owned(),
+                        None => "/".to_owned(),
+                    };
+                }
+            }
         }
+        head
     }
 
-    /// The list of variables in this format string.
-    pub fn variables(&self) -> HashSet<&str> {
-        split_variable_portions(&self.0)
-            .filter(|s| s.starts_with("$"))
-            .collect()
+    /// The list of variables in this format string, including the leading
+    /// `$`.
+    pub fn variables(&self) -> HashSet<String> {
+        let mut retvl = HashSet::new();
+        if let Ok(tokens) = parse(&self.0) {
+            visit_variables(&tokens, &mut |name, _| {
+                retvl.insert(format!("${name}"));
+            });
+        }
+        retvl
     }
 
     /// Checks whether or not the given [`Path`] matches this [`FormatString`].
@@ -124,7 +186,7 @@ impl FormatString {
     /// # use syncer_model::path_format_strings::FormatString;
     /// # use std::path::Path;
     /// let format : FormatString = "/prefix/$EMULATOR/$ROM-$TIMESTAMP.$EXT".into();
-    /// assert!(format.matches_path(Path::new("/prefix/my-emulator/my-rom-with-ts.sav")));
+    /// assert!(format.matches_path(Path::new("/prefix/my-emulator/my-rom-2024-05-01T10:20:30Z.sav")));
     /// assert!(!format.matches_path(Path::new("/prefix/my-emulator/my-rom-folder/my-rom-file.sav")));
     /// ```
     pub fn matches_path(&self, path: &Path) -> bool {
@@ -138,14 +200,20 @@ impl FormatString {
     /// ```rust
     /// # use syncer_model::path_format_strings::FormatString;
     /// let format : FormatString = "/prefix/$EMULATOR/$ROM-$TIMESTAMP.$EXT".into();
-    /// assert!(format.matches("/prefix/my-emulator/my-rom-with-ts.sav"));
+    /// assert!(format.matches("/prefix/my-emulator/my-rom-2024-05-01T10:20:30Z.sav"));
     /// assert!(!format.matches("/prefix/my-emulator/my-rom-folder/my-rom-file.sav"));
     /// ```
     pub fn matches(&self, s: &str) -> bool {
         self.matches_path(Path::new(s))
     }
+
     /// Extracts the `$`-delimited values from a file path using the given format string.
     ///
+    /// The format string only has to match the end of the path, starting at a
+    /// component boundary. Variables in optional segments that weren't present
+    /// take their default value, if they have one, and are left out otherwise.
+    /// Timestamp variables are converted to RFC 3339, whatever their format.
+    ///
     /// # Examples
     /// ```rust
     /// # use syncer_model::path_format_strings::*;
@@ -162,109 +230,508 @@ impl FormatString {
         &'a self,
         file: &'b Path,
     ) -> Result<HashMap<String, String>, MetadataResolveError<'a, 'b>> {
-        let mut retvl = HashMap::new();
-        let format_components = self.0.rsplit('/').filter(|s| !s.is_empty());
-        let mut file_components = file.components().rev();
-        if self.0.ends_with('/') {
-            file_components.next();
+        let tokens = parse(&self.0).map_err(|source| MetadataResolveError::InvalidFormat {
+            format: &self.0,
+            source,
+        })?;
+        let no_match = || MetadataResolveError::NoMatch {
+            format: &self.0,
+            path: file,
+        };
+
+        // A trailing `/` means "any file directly inside this directory".
+        let text = if self.0.ends_with('/') {
+            let parent = file.parent().ok_or_else(no_match)?;
+            format!("{}/", parent.to_string_lossy())
+        } else {
+            file.to_string_lossy().into_owned()
+        };
+        let starts = text.match_indices('/').map(|(idx, _)| idx);
+        let starts: Vec<usize> = if self.0.starts_with('/') {
+            starts.collect()
+        } else {
+            std::iter::once(0)
+                .chain(starts.map(|idx| idx + 1))
+                .collect()
+        };
+
+        // Typed variables are only given up on if nothing matches with them.
+        for typed in [true, false] {
+            for &start in &starts {
+                let mut state = MatchState {
+                    typed,
+                    optionals: Vec::new(),
+                    bindings: Vec::new(),
+                };
+                let mut candidates = Vec::new();
+                match_tokens(&tokens, &text, start, &mut state, &mut |end, state| {
+                    if end == text.len() {
+                        candidates.push((state.optionals.clone(), state.bindings.clone()));
+                    }
+                    false
+                });
+
+                // Optional segments are taken whenever possible, earliest first.
+                let Some(best) = candidates.iter().map(|(optionals, _)| optionals).max() else {
+                    continue;
+                };
+                let mut found: Vec<HashMap<String, String>> = Vec::new();
+                for (_, bindings) in candidates.iter().filter(|(optionals, _)| optionals == best) {
+                    let variables = collect_variables(&tokens, bindings);
+                    if !found.contains(&variables) {
+                        found.push(variables);
+                    }
+                }
+                if found.len() > 1 {
+                    let mut variables = found[0]
+                        .iter()
+                        .filter(|(name, value)| found.iter().any(|f| f.get(*name) != Some(value)))
+                        .map(|(name, _)| name.clone())
+                        .collect::<Vec<_>>();
+                    variables.sort();
+                    return Err(MetadataResolveError::Ambiguous {
+                        format: &self.0,
+                        path: file,
+                        variables,
+                    });
+                }
+                return found.pop().ok_or_else(no_match);
+            }
         }
-        for (fmt_comp, file_comp) in format_components.zip(file_components) {
-            if !fmt_comp.contains('$') {
-                if file_comp.as_os_str() != fmt_comp {
-                    return Err(MetadataResolveError::ComponentMismatch {
-                        expected: fmt_comp,
-                        found: file_comp.as_os_str(),
+        Err(no_match())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MetadataResolveError<'a, 'b> {
+    #[error("Path {path:?} doesn't match format {format}")]
+    NoMatch { format: &'a str, path: &'b Path },
+    #[error("Invalid format string {format}: {source}")]
+    InvalidFormat {
+        format: &'a str,
+        source: FormatStringError,
+    },
+    #[error("Path {path:?} matches format {format} in more than one way; can't tell where {} start & end", .variables.join(", "))]
+    Ambiguous {
+        format: &'a str,
+        path: &'b Path,
+        /// The variables whose values differ between interpretations.
+        variables: Vec<String>,
+    },
+}
+
+/// Syntax errors in a [`FormatString`], with the byte offset they occur at.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FormatStringError {
+    #[error("`$` at position {0} isn't followed by a variable name; use `$$` for a literal `$`")]
+    DanglingDollar(usize),
+    #[error("Unterminated `${{` at position {0}")]
+    UnclosedBrace(usize),
+    #[error("Invalid variable name {name:?} at position {at}")]
+    InvalidName { at: usize, name: String },
+    #[error("Unterminated `[` at position {0}")]
+    UnclosedBracket(usize),
+    #[error("Unmatched `]` at position {0}; use `$]` for a literal `]`")]
+    UnmatchedBracket(usize),
+    #[error("Invalid timestamp format {format:?} at position {at}")]
+    InvalidTimestampFormat { at: usize, format: String },
+}
+
+/// A parsed piece of a [`FormatString`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token<'a> {
+    /// Text that has to match exactly.
+    Literal(&'a str),
+    /// A `$NAME` or `${NAME:-default}` variable, with a `{...}` timestamp
+    /// format for timestamp variables.
+    Variable {
+        name: &'a str,
+        default: Option<&'a str>,
+        spec: Option<&'a str>,
+        /// The variable as written, for when it's left unsubstituted.
+        source: &'a str,
+    },
+    /// `*`: any run of characters within one path component.
+    Star,
+    /// `**/`: any number of whole directories, including none.
+    AnyDirs,
+    /// `**` not followed by a `/`: anything at all.
+    AnyPath,
+    /// `[...]`: tokens that may or may not be present.
+    Optional(Vec<Token<'a>>),
+}
+
+fn parse(raw: &str) -> Result<Vec<Token<'_>>, FormatStringError> {
+    Parser { raw, pos: 0 }.sequence(None)
+}
+
+struct Parser<'a> {
+    raw: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Parses tokens up to the end of the string, or up to the `]` closing the
+    /// `[` at `opened_at`.
+    fn sequence(&mut self, opened_at: Option<usize>) -> Result<Vec<Token<'a>>, FormatStringError> {
+        let mut tokens = Vec::new();
+        loop {
+            let rest = &self.raw[self.pos..];
+            let Some(next) = rest.chars().next() else {
+                return match opened_at {
+                    Some(at) => Err(FormatStringError::UnclosedBracket(at)),
+                    None => Ok(tokens),
+                };
+            };
+            match next {
+                '$' => tokens.push(self.dollar()?),
+                '[' => {
+                    let at = self.pos;
+                    self.pos += 1;
+                    tokens.push(Token::Optional(self.sequence(Some(at))?));
+                }
+                ']' if opened_at.is_some() => {
+                    self.pos += 1;
+                    return Ok(tokens);
+                }
+                ']' => return Err(FormatStringError::UnmatchedBracket(self.pos)),
+                '*' if rest.starts_with("**/") => {
+                    self.pos += 3;
+                    tokens.push(Token::AnyDirs);
+                }
+                '*' if rest.starts_with("**") => {
+                    self.pos += 2;
+                    tokens.push(Token::AnyPath);
+                }
+                '*' => {
+                    self.pos += 1;
+                    tokens.push(Token::Star);
+                }
+                _ => {
+                    let len = rest.find(['$', '[', ']', '*']).unwrap_or(rest.len());
+                    tokens.push(Token::Literal(&rest[..len]));
+                    self.pos += len;
+                }
+            }
+        }
+    }
+
+    /// Parses a variable or escape starting at a `$`.
+    fn dollar(&mut self) -> Result<Token<'a>, FormatStringError> {
+        let start = self.pos;
+        let rest = &self.raw[start + 1..];
+        match rest.chars().next() {
+            Some('$' | '[' | ']' | '*') => {
+                self.pos += 2;
+                Ok(Token::Literal(&self.raw[start + 1..start + 2]))
+            }
+            Some('{') => {
+                let end = rest
+                    .find('}')
+                    .ok_or(FormatStringError::UnclosedBrace(start))?;
+                let body = &rest[1..end];
+                let (name, default) = match body.split_once(":-") {
+                    Some((name, default)) => (name, Some(default)),
+                    None => (body, None),
+                };
+                if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    return Err(FormatStringError::InvalidName {
+                        at: start,
+                        name: name.to_owned(),
                     });
-                } else {
-                    continue;
                 }
+                self.pos += end + 2;
+                self.variable(start, name, default)
+            }
+            _ => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    return Err(FormatStringError::DanglingDollar(start));
+                }
+                self.pos += len + 1;
+                self.variable(start, &rest[..len], None)
             }
+        }
+    }
+
+    /// Finishes parsing the variable starting at `start`, including the
+    /// `{...}` format after it for timestamp variables.
+    fn variable(
+        &mut self,
+        start: usize,
+        name: &'a str,
+        default: Option<&'a str>,
+    ) -> Result<Token<'a>, FormatStringError> {
+        let rest = &self.raw[self.pos..];
+        let spec = match rest.strip_prefix('{') {
+            Some(spec) if is_timestamp_variable(name) => {
+                let end = spec
+                    .find('}')
+                    .ok_or(FormatStringError::UnclosedBrace(self.pos))?;
+                let spec = &spec[..end];
+                if spec.is_empty() || StrftimeItems::new(spec).any(|item| item == Item::Error) {
+                    return Err(FormatStringError::InvalidTimestampFormat {
+                        at: self.pos,
+                        format: spec.to_owned(),
+                    });
+                }
+                self.pos += end + 2;
+                Some(spec)
+            }
+            _ => None,
+        };
+        Ok(Token::Variable {
+            name,
+            default,
+            spec,
+            source: &self.raw[start..self.pos],
+        })
+    }
+}
+
+/// Calls `f` with the name & default of every variable in `tokens`.
+fn visit_variables<'a>(tokens: &[Token<'a>], f: &mut dyn FnMut(&'a str, Option<&'a str>)) {
+    for token in tokens {
+        match token {
+            Token::Variable { name, default, .. } => f(name, *default),
+            Token::Optional(inner) => visit_variables(inner, f),
+            Token::Literal(_) | Token::Star | Token::AnyDirs | Token::AnyPath => {}
+        }
+    }
+}
 
-            let mapped_component = file_comp.as_os_str().to_string_lossy();
-            let mut cur = &*mapped_component;
-            let mut variable_itr = split_variable_portions(fmt_comp).peekable();
-            while let Some(cur_portion) = variable_itr.next() {
-                if cur_portion.starts_with('$') {
-                    let (next_value, next_cur) =
-                        consume_next_variable(cur, variable_itr.peek().copied()).ok_or(
-                            MetadataResolveError::ComponentFormat {
-                                expected: cur_portion,
-                                found: cur.to_string(),
-                            },
-                        )?;
-                    retvl.insert(cur_portion.to_owned(), next_value.to_owned());
-                    cur = next_cur;
-                } else {
-                    match cur.split_once(cur_portion) {
-                        Some(("", rest)) => {
-                            cur = rest;
-                        }
-                        _ => {
-                            return Err(MetadataResolveError::ComponentFormat {
-                                expected: cur_portion,
-                                found: cur.to_string(),
-                            })
-                        }
+fn lookup_variable<'v, K, V>(
+    vars: &'v HashMap<K, V>,
+    name: &str,
+    default: Option<&'v str>,
+) -> Option<&'v str>
+where
+    K: Borrow<str> + Eq + Hash,
+    V: AsRef<str>,
+{
+    vars.get(format!("${name}").as_str())
+        .map(AsRef::as_ref)
+        .or(default)
+}
+
+fn build_tokens<K, V>(tokens: &[Token<'_>], vars: &HashMap<K, V>, out: &mut String)
+where
+    K: Borrow<str> + Eq + Hash,
+    V: AsRef<str>,
+{
+    for token in tokens {
+        match token {
+            Token::Literal(lit) => out.push_str(lit),
+            Token::Variable {
+                name,
+                default,
+                spec,
+                source,
+            } => match lookup_variable(vars, name, *default) {
+                Some(value) if is_timestamp_variable(name) => {
+                    let formatted = format_timestamp(value, *spec);
+                    out.push_str(formatted.as_deref().unwrap_or(value));
+                }
+                Some(value) => out.push_str(value),
+                None => out.push_str(source),
+            },
+            Token::Star | Token::AnyDirs | Token::AnyPath => {}
+            Token::Optional(inner) => {
+                let all_known = inner.iter().all(|token| match token {
+                    Token::Variable { name, default, .. } => {
+                        lookup_variable(vars, name, *default).is_some()
                     }
+                    _ => true,
+                });
+                if all_known {
+                    build_tokens(inner, vars, out);
                 }
             }
         }
-        Ok(retvl)
     }
 }
 
-#[derive(Debug, Error)]
-pub enum MetadataResolveError<'a, 'b> {
-    #[error("Error matching path: expected component {expected}, found {found:?}")]
-    ComponentMismatch { expected: &'a str, found: &'b OsStr },
-    #[error("Error matching component format: expected format {expected}, found {found:?}")]
-    ComponentFormat { expected: &'a str, found: String },
+/// Variable values bound so far while matching, by name, along with the
+/// variable's timestamp format.
+type Bindings<'a, 't> = Vec<(&'a str, Option<&'a str>, &'t str)>;
+
+/// The choices made so far while matching.
+struct MatchState<'a, 't> {
+    /// Whether variables have to pass [`variable_accepts`].
+    typed: bool,
+    /// For each optional segment reached, in order, whether it was present.
+    optionals: Vec<bool>,
+    bindings: Bindings<'a, 't>,
+}
+
+/// Whether `value` is a plausible value for the variable `name`.
+///
+/// Timestamp variables have to be timestamps in their format (see
+/// [`parse_timestamp`]) and `$EXT` can't contain a `.`; any other variable
+/// accepts anything.
+fn variable_accepts(name: &str, spec: Option<&str>, value: &str) -> bool {
+    match name {
+        "EXT" => !value.contains('.'),
+        _ if is_timestamp_variable(name) => parse_timestamp(value, spec).is_some(),
+        _ => true,
+    }
+}
+
+/// Whether `name` is one of the variables holding a timestamp, which can be
+/// given a format like `$TIMESTAMP{%Y%m%d}`.
+fn is_timestamp_variable(name: &str) -> bool {
+    matches!(name, "TIMESTAMP" | "CREATED" | "UPDATED")
 }
 
-/// Splits a format string containing replacement variables into a stream of
-/// constants to be matched and `$`-prefixed variable names to be extracted.
+/// Parses a timestamp written in the strftime-style format `spec`.
 ///
-/// # Example
-/// ```ignore
-/// let format = "/long/prefix/root/$EMULATOR/$ROM.$EXT";
-/// let split = split_variable_portions(format).collect::<Vec<_>>();
-/// let expected = &["/long/prefix/root/", "$EMULATOR", "/", "$ROM", ".", "$EXT"];
-/// assert_eq!(expected, split.as_slice());
-/// ```
-fn split_variable_portions(raw: &str) -> impl Iterator<Item = &str> + '_ {
-    let mut head = 0;
-    let mut idx = 0;
-    std::iter::from_fn(move || loop {
-        if head >= raw.len() {
-            return None;
+/// Without a format, both [`DEFAULT_TIMESTAMP_FORMAT`] and RFC 3339 are
+/// accepted. Formats without an offset are taken to be in UTC, and anything
+/// else a format leaves out is filled in with its earliest value, so eg `%Y`
+/// is midnight on the 1st of January.
+fn parse_timestamp(value: &str, spec: Option<&str>) -> Option<DateTime<Utc>> {
+    let Some(spec) = spec else {
+        return parse_timestamp(value, Some(DEFAULT_TIMESTAMP_FORMAT)).or_else(|| {
+            DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|ts| ts.to_utc())
+        });
+    };
+    let mut parsed = Parsed::new();
+    chrono::format::parse(&mut parsed, value, StrftimeItems::new(spec)).ok()?;
+    if parsed.timestamp().is_none() {
+        if parsed.month().is_none() && parsed.ordinal().is_none() && parsed.isoweek().is_none() {
+            parsed.set_month(1).ok()?;
         }
-        if idx >= raw.len() {
-            let retvl = &raw[head..];
-            head = idx;
-            return Some(retvl);
+        if parsed.month().is_some() && parsed.day().is_none() {
+            parsed.set_day(1).ok()?;
         }
-        let in_variable = raw.as_bytes()[head] == b'$';
-        let on_variable = raw.as_bytes()[idx] == b'$';
-        if on_variable || (in_variable && !raw.as_bytes()[idx].is_ascii_alphanumeric()) {
-            let retvl = &raw[head..idx];
-            head = idx;
-            idx += 1;
-            return Some(retvl);
+        if parsed.hour_div_12().is_none() {
+            parsed.set_hour(0).ok()?;
         }
-        idx += 1;
-    })
+        if parsed.minute().is_none() {
+            parsed.set_minute(0).ok()?;
+        }
+    }
+    match parsed.to_datetime() {
+        Ok(ts) => Some(ts.to_utc()),
+        Err(_) => Some(parsed.to_naive_datetime_with_offset(0).ok()?.and_utc()),
+    }
 }
 
-fn consume_next_variable<'a>(
-    s: &'a str,
-    next_component: Option<&str>,
-) -> Option<(&'a str, &'a str)> {
-    match next_component {
-        None => Some((s, "")),
-        Some(nxt) => {
-            let (head, _) = s.split_once(nxt)?;
-            let tail = &s[head.len()..];
-            Some((head, tail))
+/// Writes the RFC 3339 timestamp `value` in the strftime-style format `spec`,
+/// or in [`DEFAULT_TIMESTAMP_FORMAT`] if unset.
+fn format_timestamp(value: &str, spec: Option<&str>) -> Option<String> {
+    let ts = DateTime::parse_from_rfc3339(value).ok()?.to_utc();
+    Some(
+        ts.format(spec.unwrap_or(DEFAULT_TIMESTAMP_FORMAT))
+            .to_string(),
+    )
+}
+
+/// The `$`-prefixed variable values from a match, including defaults for
+/// any variables that weren't matched.
+fn collect_variables(tokens: &[Token<'_>], bindings: &Bindings<'_, '_>) -> HashMap<String, String> {
+    let mut retvl = bindings
+        .iter()
+        .map(|&(name, spec, value)| {
+            // Timestamps are always handed out as RFC 3339, whatever their
+            // format in the path.
+            let value = match parse_timestamp(value, spec) {
+                Some(ts) if is_timestamp_variable(name) => ts.to_rfc3339(),
+                _ => value.to_owned(),
+            };
+            (format!("${name}"), value)
+        })
+        .rev()
+        .collect::<HashMap<_, _>>();
+    visit_variables(tokens, &mut |name, default| {
+        if let Some(default) = default {
+            retvl
+                .entry(format!("${name}"))
+                .or_insert_with(|| default.to_owned());
+        }
+    });
+    retvl
+}
+
+/// Matches `tokens` against `text` starting at `pos`, backtracking through
+/// every possible interpretation until `done` accepts the position matching
+/// finished at.
+///
+/// Variables are tried shortest first and optional segments present first,
+/// and a variable appearing more than once has to match the same text each
+/// time. The exception is a timestamp variable repeated with a different
+/// format, which only has to be a valid timestamp each time; its value comes
+/// from the first occurrence.
+fn match_tokens<'a, 't>(
+    tokens: &[Token<'a>],
+    text: &'t str,
+    pos: usize,
+    state: &mut MatchState<'a, 't>,
+    done: &mut dyn FnMut(usize, &mut MatchState<'a, 't>) -> bool,
+) -> bool {
+    let Some((first, rest)) = tokens.split_first() else {
+        return done(pos, state);
+    };
+    let remaining = &text[pos..];
+    let component_end = pos + remaining.find('/').unwrap_or(remaining.len());
+    let boundaries =
+        |range: std::ops::RangeInclusive<usize>| range.filter(|&idx| text.is_char_boundary(idx));
+    match first {
+        Token::Literal(lit) => {
+            remaining.starts_with(lit) && match_tokens(rest, text, pos + lit.len(), state, done)
+        }
+        Token::Variable { name, spec, .. } => {
+            let bound = state.bindings.iter().find(|(bound, _, _)| bound == name);
+            if let Some(&(_, _, bound)) = bound.filter(|(_, bound_spec, _)| bound_spec == spec) {
+                return text[pos..component_end].starts_with(bound)
+                    && match_tokens(rest, text, pos + bound.len(), state, done);
+            }
+            for end in boundaries(pos + 1..=component_end) {
+                let value = &text[pos..end];
+                if state.typed && !variable_accepts(name, *spec, value) {
+                    continue;
+                }
+                state.bindings.push((name, *spec, value));
+                let matched = match_tokens(rest, text, end, state, done);
+                state.bindings.pop();
+                if matched {
+                    return true;
+                }
+            }
+            false
+        }
+        Token::Star => {
+            boundaries(pos..=component_end).any(|end| match_tokens(rest, text, end, state, done))
+        }
+        Token::AnyDirs => {
+            let dir_ends = remaining.match_indices('/').map(|(idx, _)| pos + idx + 1);
+            std::iter::once(pos)
+                .chain(dir_ends)
+                .any(|end| match_tokens(rest, text, end, state, done))
+        }
+        Token::AnyPath => {
+            boundaries(pos..=text.len()).any(|end| match_tokens(rest, text, end, state, done))
+        }
+        Token::Optional(inner) => {
+            state.optionals.push(true);
+            let present = {
+                let mut then_rest = |end: usize, state: &mut MatchState<'a, 't>| {
+                    match_tokens(rest, text, end, state, done)
+                };
+                match_tokens(inner, text, pos, state, &mut then_rest)
+            };
+            state.optionals.pop();
+            if present {
+                return true;
+            }
+            state.optionals.push(false);
+            let absent = match_tokens(rest, text, pos, state, done);
+            state.optionals.pop();
+            absent
         }
     }
 }
@@ -273,6 +740,17 @@ fn consume_next_variable<'a>(
 mod tests {
     use super::*;
 
+    fn resolve(format: &str, path: &str) -> Option<HashMap<String, String>> {
+        FormatString::from(format).resolve(Path::new(path)).ok()
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect()
+    }
+
     #[test]
     fn test_resolve() {
         let format: FormatString = "/long/prefix/$EMULATOR/$ROM.$EXT".into();
@@ -285,20 +763,289 @@ mod tests {
     }
 
     #[test]
-    fn test_split_variables() {
-        let format = "/long/prefix/root/$EMULATOR/$ROM.$EXT";
-        let split = split_variable_portions(format).collect::<Vec<_>>();
-        let expected = &["/long/prefix/root/", "$EMULATOR", "/", "$ROM", ".", "$EXT"];
-        assert_eq!(expected, split.as_slice());
+    fn test_parse() {
+        let tokens = parse("/long/prefix/root/$EMULATOR/${ROM:-x}.$EXT").unwrap();
+        let var = |name, default, source| Token::Variable {
+            name,
+            default,
+            spec: None,
+            source,
+        };
+        let expected = &[
+            Token::Literal("/long/prefix/root/"),
+            var("EMULATOR", None, "$EMULATOR"),
+            Token::Literal("/"),
+            var("ROM", Some("x"), "${ROM:-x}"),
+            Token::Literal("."),
+            var("EXT", None, "$EXT"),
+        ];
+        assert_eq!(expected, tokens.as_slice());
+
+        let tokens = parse("**/[$A/]*$$**").unwrap();
+        let expected = &[
+            Token::AnyDirs,
+            Token::Optional(vec![var("A", None, "$A"), Token::Literal("/")]),
+            Token::Star,
+            Token::Literal("$"),
+            Token::AnyPath,
+        ];
+        assert_eq!(expected, tokens.as_slice());
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let err = |raw: &str| parse(raw).unwrap_err();
+        assert_eq!(err("/a/$/b"), FormatStringError::DanglingDollar(3));
+        assert_eq!(err("/a/${ROM"), FormatStringError::UnclosedBrace(3));
+        assert!(matches!(
+            err("${RO-M}"),
+            FormatStringError::InvalidName { at: 0, .. }
+        ));
+        assert!(matches!(err("${}"), FormatStringError::InvalidName { .. }));
+        assert_eq!(err("/a/[$ROM"), FormatStringError::UnclosedBracket(3));
+        assert_eq!(err("/a/$ROM]"), FormatStringError::UnmatchedBracket(7));
+
+        let invalid = FormatString::from("/saves/[$ROM");
+        assert!(invalid.validate().is_err());
+        assert!(!invalid.matches("/saves/[$ROM"));
+        assert_eq!(invalid.prefix(), "/saves/[$ROM");
+        assert_eq!(
+            invalid.build_with_vars(&vars(&[("$ROM", "x")])),
+            "/saves/[$ROM"
+        );
+    }
+
+    #[test]
+    fn test_variables() {
+        // Variables don't cross directories.
+        assert!(resolve("/x/$A", "/x/a/b").is_none());
+        // Nor can they be empty.
+        assert!(resolve("/$ROM.$EXT", "/.sav").is_none());
+        // A repeated variable has to match the same text.
+        assert!(resolve("/$ROM/$ROM.sav", "/zelda/zelda.sav").is_some());
+        assert!(resolve("/$ROM/$ROM.sav", "/zelda/mario.sav").is_none());
+        assert_eq!(
+            resolve("/${ROM}s/$NAME", "/zeldas/x").unwrap(),
+            vars(&[("$ROM", "zelda"), ("$NAME", "x")])
+        );
+    }
+
+    #[test]
+    fn test_typed_variables() {
+        let format = "$NAME-$TIMESTAMP.$EXT";
+        assert_eq!(
+            resolve(
+                format,
+                "Pokemon - Red (v1.1)-2024-05-01T10:20:30.5+02:00.sav"
+            )
+            .unwrap(),
+            vars(&[
+                ("$NAME", "Pokemon - Red (v1.1)"),
+                ("$TIMESTAMP", "2024-05-01T08:20:30.500+00:00"),
+                ("$EXT", "sav"),
+            ])
+        );
+        assert_eq!(
+            resolve("$EMULATOR/$NAME.$EXT", "mgba/Pokemon v1.1.sav").unwrap(),
+            vars(&[
+                ("$EMULATOR", "mgba"),
+                ("$NAME", "Pokemon v1.1"),
+                ("$EXT", "sav")
+            ])
+        );
+        // Without a valid timestamp, untyped matching is used as a fallback.
+        assert_eq!(
+            resolve(format, "rom-ts.sav").unwrap(),
+            vars(&[("$NAME", "rom"), ("$TIMESTAMP", "ts"), ("$EXT", "sav")])
+        );
+    }
+
+    #[test]
+    fn test_ambiguity() {
+        let format = FormatString::from("$ROM-$NAME.$EXT");
+        let path = Path::new("my-rom-save.sav");
+        match format.resolve(path) {
+            Err(MetadataResolveError::Ambiguous { variables, .. }) => {
+                assert_eq!(variables, ["$NAME", "$ROM"]);
+            }
+            res => panic!("Expected an ambiguity error, got {res:?}"),
+        }
+        assert!(!format.matches_path(path));
+        assert!(format.matches("rom-save.sav"));
+        assert!(resolve("$A.$B", "a.b.c").is_none());
+        assert!(resolve("/saves/$ROM*.sav", "/saves/zelda (USA).sav").is_none());
+        // Optional segments being present doesn't count as ambiguity.
+        assert_eq!(
+            resolve("$NAME[-$SLOT].$EXT", "zelda-2.sav").unwrap(),
+            vars(&[("$NAME", "zelda"), ("$SLOT", "2"), ("$EXT", "sav")])
+        );
+        assert!(resolve("$NAME[-$SLOT].$EXT", "a-b-c.sav").is_none());
     }
 
     #[test]
-    fn test_consume() {
-        let data = "a.b.c.d";
-        let next_component = Some(".");
+    fn test_wildcards() {
+        let deep = "/saves/**/$ROM.$EXT";
+        assert!(resolve(deep, "/saves/a.sav").is_some());
+        assert!(resolve(deep, "/saves/x/y/a.sav").is_some());
+        assert!(resolve(deep, "/other/a.sav").is_none());
+
+        let star = "/saves/*/$ROM.$EXT";
+        assert!(resolve(star, "/saves/mgba/a.sav").is_some());
+        assert!(resolve(star, "/saves/x/y/a.sav").is_none());
         assert_eq!(
-            ("a", ".b.c.d"),
-            consume_next_variable(data, next_component).unwrap()
+            resolve("/saves/$ROM (*).sav", "/saves/zelda (USA).sav").unwrap(),
+            vars(&[("$ROM", "zelda")])
         );
+
+        assert!(resolve("/saves/**.sav", "/saves/x/y/a.sav").is_some());
+        assert!(resolve("/saves/$$*.sav", "/saves/$5.sav").is_some());
+        assert!(resolve("/saves/$*.sav", "/saves/a.sav").is_none());
+        assert!(resolve("/saves/$*.sav", "/saves/*.sav").is_some());
+    }
+
+    #[test]
+    fn test_optional_segments() {
+        let format = "/saves/[$EMULATOR/]$ROM.$EXT";
+        assert_eq!(
+            resolve(format, "/saves/mgba/zelda.sav").unwrap(),
+            vars(&[("$EMULATOR", "mgba"), ("$ROM", "zelda"), ("$EXT", "sav")])
+        );
+        assert_eq!(
+            resolve(format, "/saves/zelda.sav").unwrap(),
+            vars(&[("$ROM", "zelda"), ("$EXT", "sav")])
+        );
+
+        let with_default = "/saves/[${EMULATOR:-gpsp}/]$ROM.$EXT";
+        assert_eq!(
+            resolve(with_default, "/saves/zelda.sav").unwrap(),
+            vars(&[("$EMULATOR", "gpsp"), ("$ROM", "zelda"), ("$EXT", "sav")])
+        );
+        assert_eq!(
+            resolve(with_default, "/saves/mgba/zelda.sav").unwrap()["$EMULATOR"],
+            "mgba"
+        );
+
+        let nested = "/saves/$ROM[-$SLOT[.$EXT]]";
+        assert_eq!(
+            resolve(nested, "/saves/zelda").unwrap(),
+            vars(&[("$ROM", "zelda")])
+        );
+        assert_eq!(
+            resolve(nested, "/saves/zelda-1.sav").unwrap(),
+            vars(&[("$ROM", "zelda"), ("$SLOT", "1"), ("$EXT", "sav")])
+        );
+    }
+
+    #[test]
+    fn test_build() {
+        let format = FormatString::from("/saves/**/[$EMULATOR/]${ROM:-unknown}[-$SLOT].$EXT");
+        assert_eq!(
+            format.build_with_vars(&vars(&[("$EMULATOR", "mgba"), ("$EXT", "sav")])),
+            "/saves/mgba/unknown.sav"
+        );
+        assert_eq!(
+            format.build_with_vars(&vars(&[("$ROM", "zelda"), ("$SLOT", "2")])),
+            "/saves/zelda-2.$EXT"
+        );
+        assert_eq!(
+            FormatString::from("/a/*$ROM.sav").build_with_vars(&vars(&[("$ROM", "x")])),
+            "/a/x.sav"
+        );
+        assert_eq!(
+            FormatString::from("/a/$$$ROM$[1$]").build_with_vars(&vars(&[("$ROM", "x")])),
+            "/a/$x[1]"
+        );
+        // Whatever's built resolves back to the same variables.
+        let built = format.build_with_vars(&vars(&[
+            ("$EMULATOR", "mgba"),
+            ("$ROM", "zelda"),
+            ("$SLOT", "2"),
+            ("$EXT", "sav"),
+        ]));
+        assert_eq!(
+            format.resolve(Path::new(&built)).unwrap(),
+            vars(&[
+                ("$EMULATOR", "mgba"),
+                ("$ROM", "zelda"),
+                ("$SLOT", "2"),
+                ("$EXT", "sav")
+            ])
+        );
+    }
+
+    #[test]
+    fn test_timestamp_formats() {
+        let ts = "2024-05-01T10:20:30+00:00";
+        let format = FormatString::from("/saves/$NAME-$TIMESTAMP.$EXT");
+        let built = format.build_with_vars(&vars(&[
+            ("$NAME", "a-b"),
+            ("$TIMESTAMP", ts),
+            ("$EXT", "sav"),
+        ]));
+        assert_eq!(built, "/saves/a-b-20240501T102030Z.sav");
+        assert_eq!(format.resolve(Path::new(&built)).unwrap()["$TIMESTAMP"], ts);
+        // Saves written before the default changed still resolve.
+        assert_eq!(
+            format
+                .resolve(Path::new("/saves/a-b-2024-05-01T12:20:30+02:00.sav"))
+                .unwrap()["$TIMESTAMP"],
+            ts
+        );
+
+        let custom =
+            FormatString::from("/saves/$TIMESTAMP{%Y}/$NAME ($TIMESTAMP{%Y%m%d-%H%M%S}).$EXT");
+        let built = custom.build_with_vars(&vars(&[
+            ("$NAME", "zelda"),
+            ("$TIMESTAMP", ts),
+            ("$EXT", "srm"),
+        ]));
+        assert_eq!(built, "/saves/2024/zelda (20240501-102030).srm");
+        let resolved = custom.resolve(Path::new(&built)).unwrap();
+        assert_eq!(
+            resolved,
+            vars(&[
+                ("$NAME", "zelda"),
+                ("$TIMESTAMP", "2024-01-01T00:00:00+00:00"),
+                ("$EXT", "srm")
+            ])
+        );
+
+        let dated = FormatString::from("$NAME.$CREATED{%Y-%m-%d}.$EXT");
+        assert_eq!(
+            dated.resolve(Path::new("a.b.2024-05-01.sav")).unwrap()["$CREATED"],
+            "2024-05-01T00:00:00+00:00"
+        );
+        // Invalid timestamps are passed through as-is by the untyped fallback.
+        assert_eq!(
+            dated.resolve(Path::new("a.b.sav")).unwrap()["$CREATED"],
+            "b"
+        );
+
+        // Only timestamp variables take a format.
+        assert_eq!(
+            FormatString::from("$ROM{x}").build_with_vars(&vars(&[("$ROM", "a")])),
+            "a{x}"
+        );
+        assert!(matches!(
+            parse("$TIMESTAMP{%Q}"),
+            Err(FormatStringError::InvalidTimestampFormat { at: 10, .. })
+        ));
+        assert_eq!(
+            parse("$UPDATED{%Y"),
+            Err(FormatStringError::UnclosedBrace(8))
+        );
+    }
+
+    #[test]
+    fn test_prefix_and_variables() {
+        assert_eq!(FormatString::from("/a/b[/c]/$ROM").prefix(), "/a/");
+        assert_eq!(FormatString::from("$ROM.sav").prefix(), "/");
+        assert_eq!(FormatString::from("/a/*.sav").prefix(), "/a/");
+        let expected = ["$EMULATOR", "$ROM", "$EXT"]
+            .map(str::to_owned)
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let format = FormatString::from("/saves/**/[$EMULATOR/]${ROM:-x}.$EXT");
+        assert_eq!(format.variables(), expected);
     }
 }
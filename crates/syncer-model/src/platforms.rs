@@ -91,6 +91,15 @@ impl Platform {
         }
     }
 
+    /// Where the platform's frontend writes the command for the game it's
+    /// launching, if it does; the file goes away once the game exits.
+    pub fn game_command_path(&self) -> Option<&Path> {
+        match self {
+            Platform::MiyooMiniOnion => Some(Path::new("/tmp/cmd_to_run.sh")),
+            _ => None,
+        }
+    }
+
     /// The place to open the named socket on the platform.
     pub fn socket_path(&self) -> String {
         match *self {
@@ -1,12 +1,22 @@
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use futures::TryStream;
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
 
-pub fn async_walkdir(root: &Path) -> impl TryStream<Ok = PathBuf, Error = io::Error> {
-    futures::stream::unfold(vec![Ok(root.to_path_buf())], |mut queue| async move {
-        let nxt = match queue.pop()? {
+use crate::ignore::{IgnoreRules, IgnoreStack, IGNORE_FILE_NAME};
+
+/// Walks every file & directory under `root`, skipping anything excluded by
+/// `ignores` or by an ignore file found along the way.
+pub fn async_walkdir(
+    root: &Path,
+    ignores: Arc<IgnoreStack>,
+) -> impl TryStream<Ok = PathBuf, Error = io::Error> {
+    let queue = vec![Ok((root.to_path_buf(), ignores))];
+    futures::stream::unfold(queue, |mut queue| async move {
+        let (nxt, ignores) = match queue.pop()? {
             Ok(pt) => pt,
             Err(e) => {
                 return Some((Err(e), queue));
@@ -25,6 +35,15 @@ pub fn async_walkdir(root: &Path) -> impl TryStream<Ok = PathBuf, Error = io::Er
                 return Some((Ok(nxt), queue));
             }
         }
+        let ignore_file = nxt.join(IGNORE_FILE_NAME);
+        let ignores = match IgnoreRules::load(&ignore_file).await {
+            Ok(rules) => ignores.push(&nxt, rules),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => ignores,
+            Err(e) => {
+                queue.push(Err(e));
+                ignores
+            }
+        };
         let mut rdr = match tokio::fs::read_dir(&nxt).await {
             Ok(rdr) => rdr,
             Err(e) => {
@@ -38,7 +57,11 @@ pub fn async_walkdir(root: &Path) -> impl TryStream<Ok = PathBuf, Error = io::Er
                     break;
                 }
                 Ok(Some(ent)) => {
-                    queue.push(Ok(ent.path()));
+                    let path = ent.path();
+                    let is_dir = ent.file_type().await.is_ok_and(|ty| ty.is_dir());
+                    if path != ignore_file && !ignores.is_ignored(&path, is_dir) {
+                        queue.push(Ok((path, Arc::clone(&ignores))));
+                    }
                 }
                 Err(e) => {
                     queue.push(Err(e));
@@ -48,3 +71,22 @@ pub fn async_walkdir(root: &Path) -> impl TryStream<Ok = PathBuf, Error = io::Er
         Some((Ok(nxt), queue))
     })
 }
+
+/// Replaces the contents of the file at `path` with `payload`, by writing to a
+/// temporary file next to it & renaming it over the original.
+pub async fn write_atomically(path: &Path, payload: &[u8]) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let res = async {
+        let mut fh = fs::File::create(&tmp_path).await?;
+        fh.write_all(payload).await?;
+        fh.sync_all().await?;
+        fs::rename(&tmp_path, path).await
+    }
+    .await;
+    if res.is_err() {
+        fs::remove_file(&tmp_path).await.ok();
+    }
+    res
+}
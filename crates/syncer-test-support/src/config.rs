@@ -0,0 +1,97 @@
+use url::Url;
+
+/// Builds the TOML for a syncer config, so tests don't each hand-write one.
+///
+/// The poll interval is set long enough that background polling never gets in
+/// the way of a test.
+#[derive(Clone, Debug, Default)]
+pub struct TestConfig {
+    romm: Option<(Url, String)>,
+    profiles: Vec<(String, Url, String)>,
+    saves: Vec<String>,
+    system: Vec<(String, String)>,
+    extra: Vec<String>,
+}
+
+impl TestConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Points the `[romm]` section at `url`.
+    pub fn romm(mut self, url: Url, api_key: &str) -> Self {
+        self.romm = Some((url, api_key.to_owned()));
+        self
+    }
+
+    /// Adds the named profile, talking to the ROMM server at `url`.
+    pub fn profile(mut self, name: &str, url: Url, api_key: &str) -> Self {
+        self.profiles
+            .push((name.to_owned(), url, api_key.to_owned()));
+        self
+    }
+
+    /// Adds a `system.saves` entry matching `format`, eg
+    /// `/tmp/saves/$EMULATOR/$NAME.$EXT`.
+    pub fn saves(mut self, format: &str) -> Self {
+        self.saves.push(quote(format));
+        self
+    }
+
+    /// Adds a `system.saves` entry matching `format` that is bound to the
+    /// named profile.
+    pub fn profile_saves(mut self, format: &str, profile: &str) -> Self {
+        self.saves.push(format!(
+            "{{ path = {}, profile = {} }}",
+            quote(format),
+            quote(profile)
+        ));
+        self
+    }
+
+    /// Sets `system.<key>` to the string `value`.
+    pub fn system(mut self, key: &str, value: &str) -> Self {
+        self.system.push((key.to_owned(), quote(value)));
+        self
+    }
+
+    /// Appends raw TOML, eg a `[[rules]]` table, after everything else.
+    pub fn raw(mut self, toml: &str) -> Self {
+        self.extra.push(toml.to_owned());
+        self
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut retvl = String::new();
+        if let Some((url, api_key)) = &self.romm {
+            retvl.push_str(&romm_table("romm", url, api_key));
+        }
+        for (name, url, api_key) in &self.profiles {
+            retvl.push_str(&romm_table(&format!("profiles.{name}"), url, api_key));
+        }
+        retvl.push_str("[system]\n");
+        retvl.push_str(&format!("saves = [{}]\n", self.saves.join(", ")));
+        retvl.push_str("poll_interval = \"30m\"\n");
+        for (key, value) in &self.system {
+            retvl.push_str(&format!("{key} = {value}\n"));
+        }
+        for extra in &self.extra {
+            retvl.push('\n');
+            retvl.push_str(extra);
+        }
+        retvl
+    }
+}
+
+fn romm_table(name: &str, url: &Url, api_key: &str) -> String {
+    format!(
+        "[{name}]\nurl = {}\napi_key = {}\n\n",
+        quote(url.as_str()),
+        quote(api_key)
+    )
+}
+
+/// Quotes `value` as a TOML string; JSON's string escapes are valid TOML.
+fn quote(value: &str) -> String {
+    serde_json::to_string(value).unwrap()
+}
@@ -0,0 +1,335 @@
+//! An in-process stand-in for a ROMM server.
+
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use md5::{Digest, Md5};
+use romm_api::{DetailedRomSchema, RomSchema, SaveSchema};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use url::Url;
+
+use crate::http::{self, Request, Response};
+
+/// The ROMM version a [`FakeRommServer`] reports unless told otherwise.
+pub const DEFAULT_VERSION: &str = "3.5.0";
+
+/// A fake ROMM server listening on a random localhost port.
+///
+/// Implements only the endpoints the syncer uses: the heartbeat, rom search
+/// (paginated whenever the client passes a `limit`), rom details including the
+/// rom's saves, save uploads, and save downloads. All state lives in memory
+/// and can be seeded & inspected directly by tests.
+///
+/// A [legacy](Self::start_legacy) server instead answers like older ROMM
+/// releases: rom search ignores pagination, and saves carry no hash.
+///
+/// The server stops when this is dropped.
+pub struct FakeRommServer {
+    url: Url,
+    state: Arc<Mutex<FakeState>>,
+    task: JoinHandle<()>,
+}
+
+/// A save stored on a [`FakeRommServer`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FakeSave {
+    pub id: i64,
+    pub rom_id: i64,
+    pub file_name: String,
+    pub emulator: Option<String>,
+    pub data: Vec<u8>,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+}
+
+struct FakeRom {
+    id: i64,
+    file_name: String,
+}
+
+struct FakeState {
+    version: String,
+    legacy: bool,
+    next_id: i64,
+    roms: Vec<FakeRom>,
+    saves: Vec<FakeSave>,
+}
+
+impl FakeRommServer {
+    /// Starts a server reporting [`DEFAULT_VERSION`].
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub async fn start() -> io::Result<Self> {
+        Self::start_with_version(DEFAULT_VERSION).await
+    }
+
+    /// Starts a server reporting `version` from its heartbeat.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub async fn start_with_version(version: &str) -> io::Result<Self> {
+        Self::start_inner(version, false).await
+    }
+
+    /// Starts a server answering like an older ROMM release, without rom
+    /// search pagination or save hashes.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub async fn start_legacy() -> io::Result<Self> {
+        Self::start_inner(DEFAULT_VERSION, true).await
+    }
+
+    async fn start_inner(version: &str, legacy: bool) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?)).unwrap();
+        let state = Arc::new(Mutex::new(FakeState {
+            version: version.to_owned(),
+            legacy,
+            next_id: 1,
+            roms: Vec::new(),
+            saves: Vec::new(),
+        }));
+        let handler_state = Arc::clone(&state);
+        let task = tokio::spawn(http::serve(listener, move |req| {
+            lock(&handler_state).handle(req)
+        }));
+        Ok(Self { url, state, task })
+    }
+
+    /// The base URL to point a client at.
+    pub fn url(&self) -> Url {
+        self.url.clone()
+    }
+
+    /// Adds a rom with the given file name, eg `Pokemon Emerald.gba`,
+    /// returning its ID.
+    pub fn add_rom(&self, file_name: &str) -> i64 {
+        let mut state = lock(&self.state);
+        let id = state.new_id();
+        state.roms.push(FakeRom {
+            id,
+            file_name: file_name.to_owned(),
+        });
+        id
+    }
+
+    /// Adds a save for the given rom as though it had been uploaded just now,
+    /// returning its ID.
+    pub fn add_save(
+        &self,
+        rom_id: i64,
+        file_name: &str,
+        emulator: Option<&str>,
+        data: impl Into<Vec<u8>>,
+    ) -> i64 {
+        lock(&self.state).store_save(rom_id, file_name, emulator, data.into())
+    }
+
+    /// A snapshot of every save currently on the server.
+    pub fn saves(&self) -> Vec<FakeSave> {
+        lock(&self.state).saves.clone()
+    }
+}
+
+impl Drop for FakeRommServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn lock(state: &Mutex<FakeState>) -> MutexGuard<'_, FakeState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl FakeState {
+    fn new_id(&mut self) -> i64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn handle(&mut self, req: Request) -> Response {
+        let path = req.path.trim_matches('/').to_owned();
+        let segments = path.split('/').collect::<Vec<_>>();
+        match (req.method.as_str(), segments.as_slice()) {
+            ("GET", ["api", "heartbeat"]) => {
+                Response::json(&json!({ "SYSTEM": { "VERSION": self.version } }))
+            }
+            ("GET", ["api", "roms"]) => self.search_roms(&req),
+            ("GET", ["api", "roms", id]) => match id.parse().ok().and_then(|id| self.rom(id)) {
+                Some(rom) => Response::json(&self.detailed_rom_schema(rom)),
+                None => Response::not_found(),
+            },
+            ("POST", ["api", "saves"]) => self.upload_save(&req),
+            ("GET", ["api", "saves", id, "content"]) => {
+                match self.saves.iter().find(|save| id.parse() == Ok(save.id)) {
+                    Some(save) => Response::bytes(save.data.clone()),
+                    None => Response::not_found(),
+                }
+            }
+            _ => Response::not_found(),
+        }
+    }
+
+    fn rom(&self, id: i64) -> Option<&FakeRom> {
+        self.roms.iter().find(|rom| rom.id == id)
+    }
+
+    fn search_roms(&self, req: &Request) -> Response {
+        let term = req.query("search_term").unwrap_or_default().to_lowercase();
+        let found = self
+            .roms
+            .iter()
+            .filter(|rom| rom.file_name.to_lowercase().contains(&term))
+            .map(rom_schema)
+            .collect::<Vec<_>>();
+        let limit = req.query("limit").filter(|_| !self.legacy);
+        let Some(limit) = limit else {
+            return Response::json(&found);
+        };
+        let (Ok(limit), Ok(offset)) = (
+            limit.parse::<usize>(),
+            req.query("offset").unwrap_or("0").parse::<usize>(),
+        ) else {
+            return Response::error(400, "Bad pagination parameters");
+        };
+        let total = found.len();
+        let items = found
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .collect::<Vec<_>>();
+        Response::json(&json!({
+            "items": items,
+            "total": total,
+            "limit": limit,
+            "offset": offset,
+        }))
+    }
+
+    fn detailed_rom_schema(&self, rom: &FakeRom) -> DetailedRomSchema {
+        let base = rom_schema(rom);
+        DetailedRomSchema {
+            id: base.id,
+            file_name: base.file_name,
+            file_name_no_ext: base.file_name_no_ext,
+            file_name_no_tags: base.file_name_no_tags,
+            file_extension: base.file_extension,
+            full_path: base.full_path,
+            user_saves: self
+                .saves
+                .iter()
+                .filter(|save| save.rom_id == rom.id)
+                .map(|save| self.save_schema(save))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn save_schema(&self, save: &FakeSave) -> SaveSchema {
+        let (stem, ext) = split_ext(&save.file_name);
+        let file_path = format!("saves/{}", save.rom_id);
+        SaveSchema {
+            id: save.id,
+            rom_id: save.rom_id,
+            user_id: 1,
+            file_name: save.file_name.clone(),
+            file_name_no_ext: stem.to_owned(),
+            file_name_no_tags: stem.to_owned(),
+            file_extension: ext.to_owned(),
+            full_path: format!("{file_path}/{}", save.file_name),
+            file_path,
+            file_size_bytes: save.data.len() as i64,
+            md5_hash: (!self.legacy).then(|| format!("{:x}", Md5::digest(&save.data))),
+            download_path: format!("/api/saves/{}/content", save.id),
+            emulator: save.emulator.clone(),
+            created_at: save.created,
+            updated_at: save.updated,
+            screenshot: None,
+        }
+    }
+
+    fn upload_save(&mut self, req: &Request) -> Response {
+        let Some(rom_id) = req.query("rom_id").and_then(|id| id.parse().ok()) else {
+            return Response::error(400, "Missing rom_id");
+        };
+        if self.rom(rom_id).is_none() {
+            return Response::not_found();
+        }
+        let emulator = req.query("emulator").map(str::to_owned);
+        let parts = match http::multipart_parts(req) {
+            Ok(parts) => parts,
+            Err(e) => {
+                return Response::error(400, e);
+            }
+        };
+        let mut stored = Vec::new();
+        for part in parts.into_iter().filter(|part| part.name == "saves") {
+            let Some(file_name) = part.file_name else {
+                return Response::error(400, "Save upload is missing a file name");
+            };
+            let id = self.store_save(rom_id, &file_name, emulator.as_deref(), part.data);
+            stored.extend(
+                self.saves
+                    .iter()
+                    .filter(|s| s.id == id)
+                    .map(|s| self.save_schema(s)),
+            );
+        }
+        Response::json(&stored)
+    }
+
+    /// Stores a save, overwriting any existing save with the same name just
+    /// like ROMM does.
+    fn store_save(
+        &mut self,
+        rom_id: i64,
+        file_name: &str,
+        emulator: Option<&str>,
+        data: Vec<u8>,
+    ) -> i64 {
+        let now = Utc::now();
+        let existing = self.saves.iter_mut().find(|save| {
+            save.rom_id == rom_id
+                && save.file_name == file_name
+                && save.emulator.as_deref() == emulator
+        });
+        if let Some(save) = existing {
+            save.data = data;
+            save.updated = now;
+            return save.id;
+        }
+        let id = self.new_id();
+        self.saves.push(FakeSave {
+            id,
+            rom_id,
+            file_name: file_name.to_owned(),
+            emulator: emulator.map(str::to_owned),
+            data,
+            created: now,
+            updated: now,
+        });
+        id
+    }
+}
+
+/// Splits `Name.ext` into `("Name", "ext")`.
+fn split_ext(file_name: &str) -> (&str, &str) {
+    file_name.rsplit_once('.').unwrap_or((file_name, ""))
+}
+
+fn rom_schema(rom: &FakeRom) -> RomSchema {
+    let (stem, ext) = split_ext(&rom.file_name);
+    RomSchema {
+        id: rom.id,
+        file_name: rom.file_name.clone(),
+        file_name_no_ext: stem.to_owned(),
+        file_name_no_tags: stem.to_owned(),
+        file_extension: ext.to_owned(),
+        file_path: "roms".to_owned(),
+        full_path: format!("roms/{}", rom.file_name),
+        ..Default::default()
+    }
+}
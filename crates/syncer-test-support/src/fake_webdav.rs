@@ -0,0 +1,265 @@
+//! An in-process stand-in for a WebDAV share.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use percent_encoding::percent_decode_str;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use url::Url;
+
+use crate::http::{self, Request, Response};
+
+/// The collection every [`FakeWebDavServer`] serves its share from, mimicking
+/// servers like Nextcloud that don't serve from the root of the host.
+const SHARE_ROOT: &str = "dav";
+
+/// A fake WebDAV server listening on a random localhost port.
+///
+/// Implements only what the syncer uses: `PROPFIND` (with a depth of 0 or 1),
+/// `GET`, `PUT` and `MKCOL`. Like most real servers, the ETags it hands out
+/// are opaque rather than content hashes. All state lives in memory and can be
+/// seeded & inspected directly by tests, using paths relative to
+/// [`FakeWebDavServer::url`].
+///
+/// The server stops when this is dropped.
+pub struct FakeWebDavServer {
+    url: Url,
+    state: Arc<Mutex<DavState>>,
+    task: JoinHandle<()>,
+}
+
+struct DavFile {
+    data: Vec<u8>,
+    etag: String,
+    modified: DateTime<Utc>,
+}
+
+struct DavState {
+    /// The `Authorization` header every request must carry, if any.
+    auth: Option<String>,
+    next_etag: u64,
+    /// Every collection, as a path without leading or trailing slashes.
+    collections: BTreeSet<String>,
+    files: BTreeMap<String, DavFile>,
+}
+
+impl FakeWebDavServer {
+    /// Starts a server that accepts every request.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub async fn start() -> io::Result<Self> {
+        Self::start_with_auth(None).await
+    }
+
+    /// Starts a server that rejects any request whose `Authorization` header
+    /// isn't exactly `auth`, if set.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub async fn start_with_auth(auth: Option<&str>) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = Url::parse(&format!("http://{}/{SHARE_ROOT}/", listener.local_addr()?)).unwrap();
+        let state = Arc::new(Mutex::new(DavState {
+            auth: auth.map(str::to_owned),
+            next_etag: 1,
+            collections: BTreeSet::from([String::new(), SHARE_ROOT.to_owned()]),
+            files: BTreeMap::new(),
+        }));
+        let handler_state = Arc::clone(&state);
+        let task = tokio::spawn(http::serve(listener, move |req| {
+            lock(&handler_state).handle(req)
+        }));
+        Ok(Self { url, state, task })
+    }
+
+    /// The URL of the share.
+    pub fn url(&self) -> Url {
+        self.url.clone()
+    }
+
+    /// Stores a file as though another client had just uploaded it, creating
+    /// any missing parent collections.
+    pub fn put_file(&self, path: &str, data: impl Into<Vec<u8>>) {
+        let path = share_path(path);
+        let mut state = lock(&self.state);
+        let mut parent = parent_of(&path);
+        while !parent.is_empty() {
+            state.collections.insert(parent.to_owned());
+            parent = parent_of(parent);
+        }
+        state.store(path, data.into());
+    }
+
+    /// The contents of the file at `path`, if there is one.
+    pub fn file(&self, path: &str) -> Option<Vec<u8>> {
+        lock(&self.state)
+            .files
+            .get(&share_path(path))
+            .map(|file| file.data.clone())
+    }
+
+    /// The paths of every file on the share.
+    pub fn files(&self) -> Vec<String> {
+        let prefix = format!("{SHARE_ROOT}/");
+        lock(&self.state)
+            .files
+            .keys()
+            .filter_map(|path| path.strip_prefix(&prefix).map(str::to_owned))
+            .collect()
+    }
+}
+
+impl Drop for FakeWebDavServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn lock(state: &Mutex<DavState>) -> MutexGuard<'_, DavState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Turns a path relative to the share into a path relative to the server.
+fn share_path(path: &str) -> String {
+    join(SHARE_ROOT, path.trim_matches('/'))
+}
+
+fn join(parent: &str, child: &str) -> String {
+    if parent.is_empty() {
+        child.to_owned()
+    } else {
+        format!("{parent}/{child}")
+    }
+}
+
+fn parent_of(path: &str) -> &str {
+    path.rsplit_once('/')
+        .map(|(parent, _)| parent)
+        .unwrap_or("")
+}
+
+impl DavState {
+    fn handle(&mut self, req: Request) -> Response {
+        if self.auth.is_some() && req.header("authorization") != self.auth.as_deref() {
+            return Response::error(401, "Unauthorized")
+                .with_header("WWW-Authenticate", "Basic realm=\"fake\"");
+        }
+        let path = percent_decode_str(req.path.trim_matches('/'))
+            .decode_utf8_lossy()
+            .into_owned();
+        match req.method.as_str() {
+            "PROPFIND" => self.propfind(&req, &path),
+            "GET" => match self.files.get(&path) {
+                Some(file) => {
+                    Response::bytes(file.data.clone()).with_header("ETag", file.etag.clone())
+                }
+                None => Response::not_found(),
+            },
+            "PUT" => {
+                if self.collections.contains(&path) {
+                    return Response::error(405, "Can't PUT over a collection");
+                }
+                if !self.collections.contains(parent_of(&path)) {
+                    return Response::error(409, "Parent collection doesn't exist");
+                }
+                let existed = self.files.contains_key(&path);
+                let etag = self.store(path, req.body);
+                Response::empty(if existed { 204 } else { 201 }).with_header("ETag", etag)
+            }
+            "MKCOL" => {
+                if self.collections.contains(&path) || self.files.contains_key(&path) {
+                    return Response::error(405, "Already exists");
+                }
+                if !self.collections.contains(parent_of(&path)) {
+                    return Response::error(409, "Parent collection doesn't exist");
+                }
+                self.collections.insert(path);
+                Response::empty(201)
+            }
+            _ => Response::error(405, "Method not supported"),
+        }
+    }
+
+    fn store(&mut self, path: String, data: Vec<u8>) -> String {
+        let etag = format!("\"fake-{}\"", self.next_etag);
+        self.next_etag += 1;
+        self.files.insert(
+            path,
+            DavFile {
+                data,
+                etag: etag.clone(),
+                modified: Utc::now(),
+            },
+        );
+        etag
+    }
+
+    fn propfind(&self, req: &Request, path: &str) -> Response {
+        let depth = req.header("depth").unwrap_or("infinity");
+        let mut responses = String::new();
+        if let Some(file) = self.files.get(path) {
+            responses.push_str(&file_response(path, file));
+        } else if self.collections.contains(path) {
+            responses.push_str(&collection_response(path));
+            if depth == "1" {
+                let prefix = format!("{path}/");
+                let is_child = |child: &String| {
+                    child
+                        .strip_prefix(&prefix)
+                        .is_some_and(|rest| !rest.contains('/'))
+                };
+                for child in self.collections.iter().filter(|c| is_child(c)) {
+                    responses.push_str(&collection_response(child));
+                }
+                for (child, file) in self.files.iter().filter(|(c, _)| is_child(c)) {
+                    responses.push_str(&file_response(child, file));
+                }
+            } else if depth != "0" {
+                return Response::error(403, "Infinite depth is not supported");
+            }
+        } else {
+            return Response::not_found();
+        }
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<d:multistatus xmlns:d=\"DAV:\">{responses}</d:multistatus>"
+        );
+        Response {
+            status: 207,
+            content_type: "application/xml; charset=utf-8",
+            headers: Vec::new(),
+            body: body.into_bytes(),
+        }
+    }
+}
+
+fn href(path: &str) -> String {
+    let mut url = Url::parse("http://fake/").unwrap();
+    url.path_segments_mut().unwrap().extend(path.split('/'));
+    xml_escape(url.path())
+}
+
+fn xml_escape(raw: &str) -> String {
+    raw.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn collection_response(path: &str) -> String {
+    format!(
+        "<d:response><d:href>{}/</d:href><d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+        href(path)
+    )
+}
+
+fn file_response(path: &str, file: &DavFile) -> String {
+    format!(
+        "<d:response><d:href>{}</d:href><d:propstat><d:prop><d:resourcetype/><d:getcontentlength>{}</d:getcontentlength><d:getetag>{}</d:getetag><d:getlastmodified>{}</d:getlastmodified></d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+        href(path),
+        file.data.len(),
+        xml_escape(&file.etag),
+        file.modified.format("%a, %d %b %Y %H:%M:%S GMT"),
+    )
+}
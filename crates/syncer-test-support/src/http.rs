@@ -0,0 +1,312 @@
+//! A deliberately tiny HTTP/1.1 server, capable of just enough to answer the
+//! requests `reqwest` makes of a ROMM server or WebDAV share.
+//!
+//! Every response closes its connection, so we never need to deal with
+//! keep-alive or pipelining.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{trace, warn};
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    query: Vec<(String, String)>,
+    headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// The first value of the query parameter `key`, if present.
+    pub fn query(&self, key: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The value of the header `name`, matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(value: &impl Serialize) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: serde_json::to_vec(value).unwrap(),
+        }
+    }
+
+    pub fn bytes(body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/octet-stream",
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    pub fn error(status: u16, msg: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            headers: Vec::new(),
+            body: msg.into().into_bytes(),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self::error(404, "Not Found")
+    }
+
+    /// An empty response with the given status.
+    pub fn empty(status: u16) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Adds an extra header to the response.
+    pub fn with_header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+}
+
+/// Answers every connection made to `listener` with `handler` until the
+/// returned future is dropped.
+pub async fn serve<H>(listener: TcpListener, handler: H)
+where
+    H: Fn(Request) -> Response + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Error accepting connection: {e:?}");
+                continue;
+            }
+        };
+        let handler = Arc::clone(&handler);
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &*handler).await {
+                warn!("Error handling connection: {e:?}");
+            }
+        });
+    }
+}
+
+async fn handle_connection<H>(stream: TcpStream, handler: &H) -> io::Result<()>
+where
+    H: Fn(Request) -> Response,
+{
+    let mut rdr = BufReader::new(stream);
+    let Some(req) = read_request(&mut rdr).await? else {
+        return Ok(());
+    };
+    trace!("{} {} ({} byte body)", req.method, req.path, req.body.len());
+    let resp = handler(req);
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        resp.status,
+        reason(resp.status),
+        resp.content_type,
+        resp.body.len()
+    );
+    for (name, value) in &resp.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    let stream = rdr.get_mut();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&resp.body).await?;
+    stream.shutdown().await
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        207 => "Multi-Status",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        500 => "Internal Server Error",
+        _ => "Unknown",
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+async fn read_request(rdr: &mut BufReader<TcpStream>) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    if rdr.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(invalid("Malformed request line"));
+    };
+    let method = method.to_owned();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let path = path.to_owned();
+    let query = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        rdr.read_line(&mut line).await?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| invalid("Malformed header"))?;
+        headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_owned());
+    }
+
+    let chunked = headers
+        .get("transfer-encoding")
+        .is_some_and(|enc| enc.eq_ignore_ascii_case("chunked"));
+    let body = if chunked {
+        read_chunked(rdr).await?
+    } else if let Some(len) = headers.get("content-length") {
+        let len = len.parse().map_err(|_| invalid("Bad content-length"))?;
+        let mut body = vec![0; len];
+        rdr.read_exact(&mut body).await?;
+        body
+    } else {
+        Vec::new()
+    };
+    Ok(Some(Request {
+        method,
+        path,
+        query,
+        headers,
+        body,
+    }))
+}
+
+async fn read_chunked(rdr: &mut BufReader<TcpStream>) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        rdr.read_line(&mut line).await?;
+        let size = line.trim_end().split(';').next().unwrap_or_default();
+        let size = usize::from_str_radix(size, 16).map_err(|_| invalid("Bad chunk size"))?;
+        if size == 0 {
+            // Skip any trailers up to the final blank line.
+            loop {
+                line.clear();
+                if rdr.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+                    return Ok(body);
+                }
+            }
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        rdr.read_exact(&mut body[start..]).await?;
+        let mut crlf = [0; 2];
+        rdr.read_exact(&mut crlf).await?;
+    }
+}
+
+/// A single part of a `multipart/form-data` body.
+pub struct FormPart {
+    pub name: String,
+    pub file_name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Splits a `multipart/form-data` request body into its parts.
+pub fn multipart_parts(req: &Request) -> Result<Vec<FormPart>, String> {
+    let content_type = req
+        .header("content-type")
+        .ok_or("Missing content-type header")?;
+    let boundary = content_type
+        .split(';')
+        .filter_map(|param| param.trim().strip_prefix("boundary="))
+        .next()
+        .ok_or("Missing multipart boundary")?;
+    let delimiter = format!("--{}", boundary.trim_matches('"'));
+
+    let mut parts = Vec::new();
+    let mut rest = req.body.as_slice();
+    while let Some(start) = find(rest, delimiter.as_bytes()) {
+        rest = &rest[start + delimiter.len()..];
+        if rest.starts_with(b"--") {
+            break;
+        }
+        let rest_after_crlf = rest.strip_prefix(b"\r\n").unwrap_or(rest);
+        let end = find(rest_after_crlf, delimiter.as_bytes()).ok_or("Unterminated part")?;
+        let raw_part = &rest_after_crlf[..end];
+        let raw_part = raw_part.strip_suffix(b"\r\n").unwrap_or(raw_part);
+        parts.push(parse_part(raw_part)?);
+        rest = &rest_after_crlf[end..];
+    }
+    Ok(parts)
+}
+
+fn parse_part(raw: &[u8]) -> Result<FormPart, String> {
+    let split = find(raw, b"\r\n\r\n").ok_or("Part is missing headers")?;
+    let head = String::from_utf8_lossy(&raw[..split]);
+    let data = raw[split + 4..].to_vec();
+    let disposition = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("content-disposition")
+                .then_some(value)
+        })
+        .ok_or("Part is missing a content-disposition")?;
+    let param = |key: &str| {
+        disposition.split(';').find_map(|param| {
+            let (k, v) = param.trim().split_once('=')?;
+            (k == key).then(|| v.trim_matches('"').to_owned())
+        })
+    };
+    Ok(FormPart {
+        name: param("name").ok_or("Part is missing a name")?,
+        file_name: param("filename"),
+        data,
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
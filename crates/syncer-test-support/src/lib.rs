@@ -0,0 +1,11 @@
+//! Shared helpers for exercising the syncer end-to-end in `cargo test`.
+
+mod config;
+pub use config::TestConfig;
+mod fake_romm;
+pub use fake_romm::{FakeRommServer, FakeSave, DEFAULT_VERSION};
+mod fake_webdav;
+pub use fake_webdav::FakeWebDavServer;
+mod http;
+mod tempdir;
+pub use tempdir::TempDir;
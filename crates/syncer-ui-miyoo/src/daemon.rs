@@ -7,14 +7,28 @@ use tokio::{fs, process::Command};
 const SERVICE_INSTALL_PATH: &str = "/mnt/SDCARD/.tmp_update/startup/start-daemon.sh";
 const DAEMON_EXE_PATH: &str = "./syncer-daemon";
 const SERVICE_PATH: &str = "./start-daemon.sh";
+/// Where Onion looks for scripts to run before suspending & shutting down.
+const FINAL_PUSH_INSTALL_PATHS: [&str; 2] = [
+    "/mnt/SDCARD/.tmp_update/suspend/final-push.sh",
+    "/mnt/SDCARD/.tmp_update/shutdown/final-push.sh",
+];
+const FINAL_PUSH_PATH: &str = "./final-push.sh";
 
 /// Installs the daemon into the Miyoo Mini, including:
 ///
 /// * Moving all files to the correct locations.
 /// * Telling the operating system to start the daemon on boot.
+/// * Telling the operating system to push changed saves before suspending or
+///   shutting down.
 /// * Starting the daemon now.
 pub async fn install_daemon() -> Result<(), DaemonError> {
     fs::copy(SERVICE_PATH, SERVICE_INSTALL_PATH).await?;
+    for path in FINAL_PUSH_INSTALL_PATHS {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        fs::copy(FINAL_PUSH_PATH, path).await?;
+    }
     start_daemon().await?;
     Ok(())
 }
@@ -26,11 +40,17 @@ pub async fn install_daemon() -> Result<(), DaemonError> {
 /// * Removing any stray files in the OS.
 pub async fn uninstall_daemon() -> Result<(), DaemonError> {
     stop_daemon().await?;
-    match fs::remove_file(SERVICE_INSTALL_PATH).await {
-        Ok(()) => Ok(()),
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
-        Err(e) => Err(e.into()),
+    for path in [SERVICE_INSTALL_PATH]
+        .into_iter()
+        .chain(FINAL_PUSH_INSTALL_PATHS)
+    {
+        match fs::remove_file(path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
     }
+    Ok(())
 }
 
 pub async fn restart_daemon() -> Result<(), DaemonError> {
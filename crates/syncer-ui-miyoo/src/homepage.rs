@@ -9,16 +9,16 @@ use buoyant::{
     view::{HStack, LayoutExtensions, RenderExtensions, Spacer, Text, VStack},
 };
 use embedded_graphics::{pixelcolor::Rgb888, prelude::RgbColor};
-use embedded_vintage_fonts::FONT_24X32;
+use embedded_vintage_fonts::{FONT_12X16, FONT_24X32};
 use futures::future;
 use syncer_model::{
-    commands::{DaemonCommand, DaemonCommandBody},
-    config::{Config, ParseableDuration},
+    commands::{DaemonCommand, DaemonCommandBody, DaemonResponseBody, DaemonStatus, SyncStatus},
+    config::{Config, Diagnostic, ParseableDuration, Severity, TimeWindow},
 };
 use tokio::sync::broadcast::{self, error::TryRecvError};
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
-use crate::{ApplicationState, ViewState, utils::BackgroundTask};
+use crate::{ApplicationState, ViewState, socketproto::DaemonSocket, utils::BackgroundTask};
 use crate::{
     components::{button, labeled_checkbox},
     daemon::{daemon_is_running, start_daemon, stop_daemon},
@@ -53,6 +53,43 @@ const fn cur_poll_idx(duration: Duration) -> usize {
     retvl
 }
 
+/// The quiet hours that can be picked from the homepage, besides none at all.
+const QUIET_HOURS_OPTIONS: &[&str] = &["22:00-07:00", "23:00-07:00", "00:00-08:00"];
+
+/// The index into [`QUIET_HOURS_OPTIONS`] plus one of `quiet_hours`, or 0 if
+/// there aren't any or they aren't one of the options.
+fn cur_quiet_hours_idx(quiet_hours: &[TimeWindow]) -> usize {
+    let [window] = quiet_hours else {
+        return 0;
+    };
+    QUIET_HOURS_OPTIONS
+        .iter()
+        .position(|option| window.to_string() == *option)
+        .map_or(0, |idx| idx + 1)
+}
+
+/// The quiet hours for an index from [`cur_quiet_hours_idx`].
+fn quiet_hours_option(idx: usize) -> Vec<TimeWindow> {
+    match idx.checked_sub(1) {
+        Some(idx) => vec![
+            QUIET_HOURS_OPTIONS[idx]
+                .parse()
+                .expect("quiet hours options are valid"),
+        ],
+        None => Vec::new(),
+    }
+}
+
+/// The on-battery interval for an index into [`POLL_TIME_OPTIONS`] plus one,
+/// where 0 means the same as when plugged in.
+fn battery_poll_option(idx: usize) -> Option<ParseableDuration> {
+    idx.checked_sub(1).map(|idx| POLL_TIME_OPTIONS[idx].into())
+}
+
+fn cur_battery_poll_idx(interval: Option<ParseableDuration>) -> usize {
+    interval.map_or(0, |interval| cur_poll_idx(*interval) + 1)
+}
+
 pub struct HomepageState {
     pressed: bool,
     selection: HomePageSelection,
@@ -69,6 +106,9 @@ enum HomePageSelection {
     DaemonInstalledBox,
     DaemonRunningBox,
     PollTimeSelection,
+    BatteryPollSelection,
+    QuietHoursSelection,
+    ChargingOnlyBox,
     FsnotifyBox,
     ReinstallDaemon,
     UninstallDaemon,
@@ -80,7 +120,10 @@ impl HomePageSelection {
         use HomePageSelection::*;
         match *self {
             UninstallDaemon | ReinstallDaemon | ForceSyncButton => FsnotifyBox,
-            FsnotifyBox => PollTimeSelection,
+            FsnotifyBox => ChargingOnlyBox,
+            ChargingOnlyBox => QuietHoursSelection,
+            QuietHoursSelection => BatteryPollSelection,
+            BatteryPollSelection => PollTimeSelection,
             PollTimeSelection => DaemonRunningBox,
             DaemonRunningBox => DaemonInstalledBox,
             DaemonInstalledBox | Nothing => Nothing,
@@ -92,7 +135,10 @@ impl HomePageSelection {
             Nothing => DaemonInstalledBox,
             DaemonInstalledBox => DaemonRunningBox,
             DaemonRunningBox => PollTimeSelection,
-            PollTimeSelection => FsnotifyBox,
+            PollTimeSelection => BatteryPollSelection,
+            BatteryPollSelection => QuietHoursSelection,
+            QuietHoursSelection => ChargingOnlyBox,
+            ChargingOnlyBox => FsnotifyBox,
             FsnotifyBox => ReinstallDaemon,
             ReinstallDaemon | UninstallDaemon | ForceSyncButton => Nothing,
         }
@@ -151,7 +197,11 @@ impl HomepageState {
     async fn reload(&mut self) -> Result<(), anyhow::Error> {
         let needs_redraw = self
             .external_state
-            .modify_with(async |state| state.reload().await)
+            .modify_with(async |state| {
+                let config_changed = state.check_config().await;
+                let state_changed = state.reload().await?;
+                anyhow::Ok(config_changed || state_changed)
+            })
             .await?;
         if needs_redraw {
             self.redraw_trigger.send(()).ok();
@@ -160,6 +210,61 @@ impl HomepageState {
     }
 }
 
+impl HomepageState {
+    /// Moves the selected scrollable option one step forward or back, saving
+    /// the result to the config; returns `false` if no option is selected.
+    async fn step_option(&mut self, forward: bool) -> Result<bool, anyhow::Error> {
+        let step = |idx: usize, len: usize| {
+            if forward {
+                (len - 1).min(idx + 1)
+            } else {
+                idx.saturating_sub(1)
+            }
+        };
+        let system = self.cfg.config().await.system.clone();
+        match self.selection {
+            HomePageSelection::PollTimeSelection => {
+                let idx = step(cur_poll_idx(*system.poll_interval), POLL_TIME_OPTIONS.len());
+                self.cfg
+                    .modify_and_save_cfg(move |cfg: &mut Config| {
+                        cfg.system.poll_interval = POLL_TIME_OPTIONS[idx].into();
+                        future::ready(())
+                    })
+                    .await?;
+            }
+            HomePageSelection::BatteryPollSelection => {
+                let idx = step(
+                    cur_battery_poll_idx(system.schedule.on_battery),
+                    POLL_TIME_OPTIONS.len() + 1,
+                );
+                self.cfg
+                    .modify_and_save_cfg(move |cfg: &mut Config| {
+                        cfg.system.schedule.on_battery = battery_poll_option(idx);
+                        future::ready(())
+                    })
+                    .await?;
+            }
+            HomePageSelection::QuietHoursSelection => {
+                let idx = step(
+                    cur_quiet_hours_idx(&system.schedule.quiet_hours),
+                    QUIET_HOURS_OPTIONS.len() + 1,
+                );
+                self.cfg
+                    .modify_and_save_cfg(move |cfg: &mut Config| {
+                        cfg.system.schedule.quiet_hours = quiet_hours_option(idx);
+                        future::ready(())
+                    })
+                    .await?;
+            }
+            _ => {
+                return Ok(false);
+            }
+        }
+        self.reload().await?;
+        Ok(true)
+    }
+}
+
 impl ViewState for HomepageState {
     async fn up(&mut self) -> Result<(), anyhow::Error> {
         self.selection = self.selection.up();
@@ -170,33 +275,13 @@ impl ViewState for HomepageState {
         Ok(())
     }
     async fn left(&mut self) -> Result<(), anyhow::Error> {
-        if self.selection == HomePageSelection::PollTimeSelection {
-            let cur_poll_idx = cur_poll_idx(*self.cfg.config().await.system.poll_interval);
-            let prev_poll_idx = cur_poll_idx.saturating_sub(1);
-            self.cfg
-                .modify_and_save_cfg(move |cfg: &mut Config| {
-                    cfg.system.poll_interval = POLL_TIME_OPTIONS[prev_poll_idx].into();
-                    future::ready(())
-                })
-                .await?;
-            self.reload().await?;
-        } else {
+        if !self.step_option(false).await? {
             self.selection = self.selection.left();
         }
         Ok(())
     }
     async fn right(&mut self) -> Result<(), anyhow::Error> {
-        if self.selection == HomePageSelection::PollTimeSelection {
-            let cur_poll_idx = cur_poll_idx(*self.cfg.config().await.system.poll_interval);
-            let next_poll_idx = (POLL_TIME_OPTIONS.len() - 1).min(cur_poll_idx + 1);
-            self.cfg
-                .modify_and_save_cfg(move |cfg: &mut Config| {
-                    cfg.system.poll_interval = POLL_TIME_OPTIONS[next_poll_idx].into();
-                    future::ready(())
-                })
-                .await?;
-            self.reload().await?;
-        } else {
+        if !self.step_option(true).await? {
             self.selection = self.selection.right();
         }
         Ok(())
@@ -218,11 +303,12 @@ impl ViewState for HomepageState {
                 self.reload().await?;
             }
             ForceSyncButton => {
-                let res = self
-                    .cfg
-                    .socket
-                    .send(&DaemonCommand::new(DaemonCommandBody::DoSync))
-                    .await;
+                let body = if external_state.is_syncing() {
+                    DaemonCommandBody::CancelSync
+                } else {
+                    DaemonCommandBody::DoSync
+                };
+                let res = self.cfg.socket.send(&DaemonCommand::new(body)).await;
                 match res {
                     Ok(()) => {
                         self.reload().await?;
@@ -260,21 +346,24 @@ impl ViewState for HomepageState {
                     .await?;
                 self.reload().await?;
             }
-            PollTimeSelection | Nothing => {}
+            ChargingOnlyBox => {
+                self.cfg
+                    .modify_and_save_cfg(|cfg: &mut Config| {
+                        cfg.system.schedule.only_while_charging =
+                            Some(!external_state.only_while_charging);
+                        future::ready(())
+                    })
+                    .await?;
+                self.reload().await?;
+            }
+            PollTimeSelection | BatteryPollSelection | QuietHoursSelection | Nothing => {}
         }
         self.pressed = false;
         Ok(())
     }
     fn build_view(&self) -> impl EmbeddedGraphicsView<Rgb888> + Layout + '_ {
         let state = self.external_state.read();
-        build_view(
-            self.selection,
-            self.pressed,
-            state.daemon_installed,
-            state.daemon_running,
-            state.poll_interval,
-            state.fs_notify_enabled,
-        )
+        build_view(self.selection, self.pressed, &state)
     }
     async fn trigger_redraw(&mut self) -> Result<(), anyhow::Error> {
         let mut rcv = self.redraw_trigger.subscribe();
@@ -332,11 +421,18 @@ fn labelled_scrollable_options<'a>(
 fn build_view(
     selection: HomePageSelection,
     pressed: bool,
-    daemon_installed: bool,
-    daemon_running: bool,
-    poll_interval: ParseableDuration,
-    fs_notify_enabled: bool,
-) -> impl EmbeddedGraphicsView<Rgb888> + Layout {
+    state: &ExternalState,
+) -> impl EmbeddedGraphicsView<Rgb888> + Layout + use<> {
+    let ExternalState {
+        daemon_installed,
+        daemon_running,
+        poll_interval,
+        battery_poll_interval,
+        only_while_charging,
+        fs_notify_enabled,
+        ..
+    } = *state;
+    let daemon_status = state.daemon_status.as_ref();
     let installed_box = labeled_checkbox(
         "Daemon installed",
         selection == HomePageSelection::DaemonInstalledBox,
@@ -358,8 +454,10 @@ fn build_view(
         selection == HomePageSelection::ReinstallDaemon && pressed,
     );
 
+    let syncing =
+        daemon_running && daemon_status.is_some_and(|status| status.sync == SyncStatus::Syncing);
     let sync_btn = button(
-        "Force Sync",
+        if syncing { "Cancel Sync" } else { "Force Sync" },
         selection == HomePageSelection::ForceSyncButton,
         selection == HomePageSelection::ForceSyncButton && pressed,
     );
@@ -371,44 +469,186 @@ fn build_view(
         selection == HomePageSelection::PollTimeSelection,
     );
 
+    let battery_poll_time = match battery_poll_interval {
+        Some(interval) => interval.to_string(),
+        None => "Same".to_owned(),
+    };
+    let battery_poll_cfg = labelled_scrollable_options(
+        "On battery",
+        battery_poll_time,
+        selection == HomePageSelection::BatteryPollSelection,
+    );
+
+    let quiet_hours = match &state.quiet_hours[..] {
+        [] => "Off".to_owned(),
+        [window] => window.to_string(),
+        _ => "Custom".to_owned(),
+    };
+    let quiet_hours_cfg = labelled_scrollable_options(
+        "Quiet hours",
+        quiet_hours,
+        selection == HomePageSelection::QuietHoursSelection,
+    );
+
+    let charging_only_box = labeled_checkbox(
+        "Only while charging",
+        selection == HomePageSelection::ChargingOnlyBox,
+        only_while_charging,
+    );
+
     let fs_notify_box = labeled_checkbox(
         "Sync on change",
         selection == HomePageSelection::FsnotifyBox,
         fs_notify_enabled,
     );
 
+    let (status_text, status_color) = status_summary(daemon_running, daemon_status);
+    let status_line = Text::new(status_text, &FONT_12X16).foreground_color(status_color);
+    let (config_text, config_color) = config_summary(&state.config_diagnostics);
+    let config_line = Text::new(config_text, &FONT_12X16).foreground_color(config_color);
+
     let btns = HStack::new((reinstall_btn, uninstall_btn, sync_btn));
     VStack::new((
         installed_box,
         running_box,
         poll_time_cfg,
+        battery_poll_cfg,
+        quiet_hours_cfg,
+        charging_only_box,
         fs_notify_box,
         btns,
+        status_line,
+        config_line,
     ))
     .frame()
 }
 
+/// A short, user-facing summary of what the daemon is doing, and the color to
+/// show it in.
+fn status_summary(daemon_running: bool, status: Option<&DaemonStatus>) -> (String, Rgb888) {
+    const TEXT_COLOR: Rgb888 = Rgb888::BLACK;
+    const ERROR_COLOR: Rgb888 = Rgb888::RED;
+
+    let Some(status) = status.filter(|_| daemon_running) else {
+        return ("Daemon is not running".to_owned(), TEXT_COLOR);
+    };
+    if status.paused && status.sync != SyncStatus::Syncing {
+        return ("Syncing is paused".to_owned(), TEXT_COLOR);
+    }
+    if let Some(error) = status.config_error.as_deref() {
+        return (format!("Config not reloaded: {error}"), ERROR_COLOR);
+    }
+    if let Some(error) = status.error.as_deref() {
+        return (format!("Sync failed: {error}"), ERROR_COLOR);
+    }
+    let label = match status.sync {
+        SyncStatus::Idle => "Waiting for first sync",
+        SyncStatus::Syncing => "Syncing...",
+        SyncStatus::Synced => "Up to date",
+        SyncStatus::Offline => "Offline; will sync when connected",
+        SyncStatus::Failed => "Last sync failed",
+        SyncStatus::Cancelled => "Last sync was cancelled",
+        SyncStatus::Paused => "Syncing is paused",
+        SyncStatus::LowBattery => "Battery low; will sync when charging",
+    };
+    let mut text = match status.server_version.as_deref() {
+        Some(version) => format!("{label} (ROMM {version})"),
+        None => label.to_owned(),
+    };
+    if status.sync == SyncStatus::Synced && !status.suppressed.is_empty() {
+        text.push_str(&format!("; {} held back", status.suppressed.len()));
+    }
+    if status.sync == SyncStatus::Synced && !status.in_use.is_empty() {
+        text.push_str(&format!("; {} in use", status.in_use.len()));
+    }
+    (text, TEXT_COLOR)
+}
+
+/// A short summary of any problems found in the config, and the color to show
+/// it in.
+fn config_summary(diagnostics: &[Diagnostic]) -> (String, Rgb888) {
+    const TEXT_COLOR: Rgb888 = Rgb888::BLACK;
+    const ERROR_COLOR: Rgb888 = Rgb888::RED;
+
+    // Show the first error if there is one, otherwise the first warning.
+    let worst = diagnostics
+        .iter()
+        .find(|d| d.severity == Severity::Error)
+        .or(diagnostics.first());
+    let Some(worst) = worst else {
+        return (String::new(), TEXT_COLOR);
+    };
+    let color = match worst.severity {
+        Severity::Error => ERROR_COLOR,
+        Severity::Warning => TEXT_COLOR,
+    };
+    let more = match diagnostics.len() {
+        1 => String::new(),
+        n => format!(" (+{} more)", n - 1),
+    };
+    (format!("Config {worst}{more}"), color)
+}
+
+async fn query_daemon_status(socket: &DaemonSocket) -> Option<DaemonStatus> {
+    let res = socket
+        .query(&DaemonCommand::new(DaemonCommandBody::GetStatus))
+        .await;
+    match res {
+        Ok(response) => match response.body {
+            DaemonResponseBody::Status(status) => Some(status),
+        },
+        Err(e) => {
+            debug!("Error querying daemon status: {e:?}");
+            None
+        }
+    }
+}
+
 #[derive(Clone)]
 struct ExternalState {
     daemon_installed: bool,
     daemon_running: bool,
+    daemon_status: Option<DaemonStatus>,
     fs_notify_enabled: bool,
     app_state: ApplicationState,
     poll_interval: ParseableDuration,
+    battery_poll_interval: Option<ParseableDuration>,
+    quiet_hours: Vec<TimeWindow>,
+    only_while_charging: bool,
+    /// Problems found in the config files; only refreshed when the homepage
+    /// reloads, since checking touches every save root.
+    config_diagnostics: Vec<Diagnostic>,
 }
 
 impl ExternalState {
+    fn is_syncing(&self) -> bool {
+        self.daemon_status
+            .as_ref()
+            .is_some_and(|status| status.sync == SyncStatus::Syncing)
+    }
     pub async fn new(app_state: ApplicationState) -> Result<Self, anyhow::Error> {
         let mut retvl = Self {
             daemon_installed: false,
             daemon_running: false,
+            daemon_status: None,
             fs_notify_enabled: false,
             app_state,
             poll_interval: ParseableDuration::new(Duration::default()),
+            battery_poll_interval: None,
+            quiet_hours: Vec::new(),
+            only_while_charging: false,
+            config_diagnostics: Vec::new(),
         };
         retvl.reload().await?;
         Ok(retvl)
     }
+    /// Re-runs the config checks, returning whether the results changed.
+    pub async fn check_config(&mut self) -> bool {
+        let diagnostics = Config::check_current_platform().await;
+        let modified = self.config_diagnostics != diagnostics;
+        self.config_diagnostics = diagnostics;
+        modified
+    }
     pub async fn reload(&mut self) -> Result<bool, anyhow::Error> {
         let mut modified = false;
 
@@ -434,8 +674,20 @@ impl ExternalState {
                 .await
                 .context("Error checking daemon run state")?
         );
+        let daemon_status = if self.daemon_running {
+            query_daemon_status(&self.app_state.socket).await
+        } else {
+            None
+        };
+        modify!(self.daemon_status, daemon_status);
         let cfg = self.app_state.config().await;
         modify!(self.poll_interval, cfg.system.poll_interval);
+        modify!(self.battery_poll_interval, cfg.system.schedule.on_battery);
+        modify!(self.quiet_hours, cfg.system.schedule.quiet_hours.clone());
+        modify!(
+            self.only_while_charging,
+            cfg.system.schedule.only_while_charging.unwrap_or(false)
+        );
         modify!(self.fs_notify_enabled, cfg.system.sync_on_file_change);
         Ok(modified)
     }
@@ -120,7 +120,9 @@ async fn async_main() {
         };
         match mapped_evt {
             Ok(Some((btn, evt))) => {
-                view.handle_event(btn, evt).await.unwrap();
+                if let Err(e) = view.handle_event(btn, evt).await {
+                    error!("Error handling {btn:?} {evt:?}: {e:?}");
+                }
                 view.render_view(&mut fb).unwrap();
             }
             Ok(None) => {
@@ -150,6 +152,10 @@ pub trait ViewState {
     fn right(&mut self) -> impl Future<Output = Result<(), anyhow::Error>> + '_ {
         futures::future::ready(Ok(()))
     }
+    /// Handle the `Y` button.
+    fn y(&mut self) -> impl Future<Output = Result<(), anyhow::Error>> + '_ {
+        futures::future::ready(Ok(()))
+    }
     /// Handle the `L` and `Lz` buttons.
     fn l(&mut self) -> impl Future<Output = Result<(), anyhow::Error>> + '_ {
         futures::future::ready(Ok(()))
@@ -218,6 +224,10 @@ pub trait ViewState {
                     self.l().await?;
                     ControlFlow::Continue(())
                 }
+                (MiyooButton::Y, MiyooButtonEvent::Pressed) => {
+                    self.y().await?;
+                    ControlFlow::Continue(())
+                }
                 (MiyooButton::Menu, MiyooButtonEvent::Pressed) => ControlFlow::Break(()),
                 (MiyooButton::B, MiyooButtonEvent::Pressed) => self.back().await?,
                 _ => ControlFlow::Continue(()),
@@ -280,8 +290,9 @@ impl ApplicationState {
         F: for<'a> AsyncFnOnce(&'a mut Config) -> R,
     {
         let mut lock = self.cfg.write().await;
+        let original = lock.clone();
         let res = cb(&mut lock).await;
-        lock.save_current_platform().await?;
+        lock.save_changes_current_platform(&original).await?;
         let socket_res = self
             .socket
             .send(&DaemonCommand::new(DaemonCommandBody::DoSync))
@@ -337,6 +348,13 @@ impl ViewState for FullViewState {
             SavesList(view) => view.right().await,
         }
     }
+    async fn y(&mut self) -> Result<(), anyhow::Error> {
+        use FullViewState::*;
+        match self {
+            Homepage(view) => view.y().await,
+            SavesList(view) => view.y().await,
+        }
+    }
     async fn press(&mut self) -> Result<(), anyhow::Error> {
         use FullViewState::*;
         match self {
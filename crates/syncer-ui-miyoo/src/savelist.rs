@@ -3,6 +3,11 @@
 //! Current UI & navigation is a paged scroll list of checkmarks with
 //! `[default]` at the top controlling what the default is for new saves
 //! following by each of the possible save files, ordered alphabetically.
+//!
+//! Left & right on a save change its ROM's entry in the config's `rules`
+//! table; `Y` picks which of the rule's settings they change: the sync
+//! direction, the conflict policy, how many backups to keep, or whether the
+//! ROM syncs at all.
 
 use std::{
     borrow::Cow,
@@ -13,16 +18,105 @@ use std::{
 use buoyant::{layout::Layout, render::EmbeddedGraphicsView};
 use embedded_graphics::pixelcolor::Rgb888;
 use futures::{StreamExt, TryFutureExt, TryStreamExt, future};
-use syncer_model::config::Config;
+use syncer_model::config::{Config, ConflictPolicy, PathPattern, PolicyRule, SyncDirection};
 use tracing::error;
 
 use crate::components::labeled_checkbox;
 use crate::utils::ForEachDyn;
 use crate::{ApplicationState, ViewState};
 
+/// A single row of the save list.
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct SaveRow {
+    label: String,
+    enabled: bool,
+    /// The ROM the save belongs to; `None` for `[default]`.
+    rom: Option<String>,
+    direction: SyncDirection,
+    conflict: ConflictPolicy,
+    backups: usize,
+    /// Whether a rule has turned syncing off for the save.
+    disabled_by_rule: bool,
+}
+
+impl SaveRow {
+    /// The label to show, including the save's policy if it's not the
+    /// default, and the value of `editing` if the row is selected.
+    fn display_label(&self, editing: Option<RuleField>) -> String {
+        let mut label = self.label.clone();
+        let mut tag = |field: RuleField, shown: bool| {
+            if editing == Some(field) {
+                label.push_str(&format!(" <{}: {}>", field.name(), self.value(field)));
+            } else if shown {
+                label.push_str(&format!(" [{}]", self.value(field)));
+            }
+        };
+        tag(
+            RuleField::Direction,
+            self.direction != SyncDirection::Bidirectional,
+        );
+        tag(RuleField::Conflict, self.conflict != ConflictPolicy::Error);
+        tag(RuleField::Backups, self.backups > 0);
+        tag(RuleField::Enabled, self.disabled_by_rule);
+        label
+    }
+
+    /// A short description of the save's current value for `field`.
+    fn value(&self, field: RuleField) -> Cow<'static, str> {
+        match field {
+            RuleField::Direction => Cow::Borrowed(match self.direction {
+                SyncDirection::Bidirectional => "both",
+                SyncDirection::PushOnly => "push",
+                SyncDirection::PullOnly => "pull",
+                SyncDirection::MirrorFromRemote => "mirror",
+            }),
+            RuleField::Conflict => Cow::Borrowed(match self.conflict {
+                ConflictPolicy::Error => "ask",
+                ConflictPolicy::PreferDevice => "device",
+                ConflictPolicy::PreferRemote => "remote",
+                ConflictPolicy::PreferNewest => "newest",
+            }),
+            RuleField::Backups => Cow::Owned(format!("{} backups", self.backups)),
+            RuleField::Enabled => Cow::Borrowed(if self.disabled_by_rule { "off" } else { "on" }),
+        }
+    }
+}
+
+/// Which setting of a ROM's rule left & right change.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+enum RuleField {
+    #[default]
+    Direction,
+    Conflict,
+    Backups,
+    Enabled,
+}
+
+impl RuleField {
+    fn next(self) -> Self {
+        match self {
+            RuleField::Direction => RuleField::Conflict,
+            RuleField::Conflict => RuleField::Backups,
+            RuleField::Backups => RuleField::Enabled,
+            RuleField::Enabled => RuleField::Direction,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            RuleField::Direction => "direction",
+            RuleField::Conflict => "conflicts",
+            RuleField::Backups => "keep",
+            RuleField::Enabled => "sync",
+        }
+    }
+}
+
 pub struct SavelistState {
-    saves: Vec<(String, bool)>,
+    saves: Vec<SaveRow>,
     selected: usize,
+    /// The setting left & right change on the selected save.
+    editing: RuleField,
     pub cfg: ApplicationState,
 }
 
@@ -31,6 +125,7 @@ impl SavelistState {
         let mut retvl = Self {
             saves: Vec::new(),
             selected: 0,
+            editing: RuleField::default(),
             cfg,
         };
         retvl.reload().await;
@@ -40,32 +135,128 @@ impl SavelistState {
         self.saves = saves_from_cfg(&*self.cfg.config().await).await;
         self.selected = 0;
     }
+
+    /// Moves the selected save's ROM to the next (or previous) value of the
+    /// setting being edited.
+    async fn cycle_rule(&mut self, forward: bool) -> Result<(), anyhow::Error> {
+        let row = &self.saves[self.selected];
+        let Some(rom) = row.rom.clone() else {
+            return Ok(());
+        };
+        let edit: Box<dyn FnOnce(&mut PolicyRule) + Send> = match self.editing {
+            RuleField::Direction => {
+                let direction = next_direction(row.direction, forward);
+                Box::new(move |rule| rule.direction = Some(direction))
+            }
+            RuleField::Conflict => {
+                let conflict = next_conflict(row.conflict, forward);
+                Box::new(move |rule| rule.conflict = Some(conflict))
+            }
+            RuleField::Backups => {
+                let backups = if forward {
+                    (row.backups + 1).min(MAX_BACKUPS)
+                } else {
+                    row.backups.saturating_sub(1)
+                };
+                Box::new(move |rule| rule.backups = Some(backups))
+            }
+            RuleField::Enabled => {
+                let enabled = row.disabled_by_rule;
+                Box::new(move |rule| rule.enabled = Some(enabled))
+            }
+        };
+        self.cfg
+            .modify_and_save_cfg(move |cfg: &mut Config| {
+                edit(cfg.rom_rule_mut(&rom));
+                future::ready(())
+            })
+            .await?;
+        let selected = self.selected;
+        self.reload().await;
+        self.selected = selected.min(self.saves.len() - 1);
+        Ok(())
+    }
+}
+
+const DIRECTIONS: [SyncDirection; 4] = [
+    SyncDirection::Bidirectional,
+    SyncDirection::PushOnly,
+    SyncDirection::PullOnly,
+    SyncDirection::MirrorFromRemote,
+];
+
+fn next_direction(current: SyncDirection, forward: bool) -> SyncDirection {
+    let idx = DIRECTIONS.iter().position(|d| *d == current).unwrap_or(0);
+    let offset = if forward { 1 } else { DIRECTIONS.len() - 1 };
+    DIRECTIONS[(idx + offset) % DIRECTIONS.len()]
 }
 
-async fn saves_from_cfg(cfg: &Config) -> Vec<(String, bool)> {
+const CONFLICTS: [ConflictPolicy; 4] = [
+    ConflictPolicy::Error,
+    ConflictPolicy::PreferNewest,
+    ConflictPolicy::PreferDevice,
+    ConflictPolicy::PreferRemote,
+];
+
+fn next_conflict(current: ConflictPolicy, forward: bool) -> ConflictPolicy {
+    let idx = CONFLICTS.iter().position(|c| *c == current).unwrap_or(0);
+    let offset = if forward { 1 } else { CONFLICTS.len() - 1 };
+    CONFLICTS[(idx + offset) % CONFLICTS.len()]
+}
+
+/// The most backups per ROM the save list will set.
+const MAX_BACKUPS: usize = 10;
+
+async fn saves_from_cfg(cfg: &Config) -> Vec<SaveRow> {
     let mut saves = cfg
         .possible_saves()
         .filter_map(|res| match res {
-            Ok((path, _, _)) => futures::future::ready(Some(path)),
+            Ok(found) => futures::future::ready(Some(found)),
             Err(e) => {
                 error!("Error getting save: {e:?}");
                 futures::future::ready(None)
             }
         })
-        .map(|path| {
-            let flag = is_enabled(cfg, &path);
-            (path, flag)
+        .map(|(path, root, vars)| {
+            let rom = vars
+                .get("$ROM")
+                .or_else(|| vars.get("$NAME"))
+                .cloned()
+                .unwrap_or_else(|| {
+                    let stem = path.file_stem().unwrap_or_default();
+                    stem.to_string_lossy().into_owned()
+                });
+            let policy = cfg.policy_for(
+                root.direction,
+                root.profile.as_deref(),
+                &rom,
+                vars.get("$EMULATOR").map(String::as_str),
+                vars.get("$PLATFORM").map(String::as_str),
+            );
+            SaveRow {
+                label: path.to_string_lossy().into_owned(),
+                enabled: is_enabled(cfg, &path),
+                rom: Some(rom),
+                direction: policy.direction,
+                conflict: policy.conflict,
+                backups: policy.backups,
+                disabled_by_rule: !policy.enabled,
+            }
         })
-        .map(|(path, enabled)| (path.to_string_lossy().into_owned(), enabled))
         .collect::<Vec<_>>()
         .await;
-    saves.sort();
+    saves.sort_by(|a, b| a.label.cmp(&b.label));
     saves.insert(
         0,
-        (
-            "[default]".to_owned(),
-            is_enabled(cfg, Path::new("[default]")),
-        ),
+        SaveRow {
+            label: "[default]".to_owned(),
+            enabled: is_enabled(cfg, Path::new("[default]")),
+            rom: None,
+            direction: SyncDirection::default(),
+            conflict: ConflictPolicy::default(),
+            backups: 0,
+            disabled_by_rule: false,
+        },
     );
     saves
 }
@@ -79,12 +270,23 @@ impl ViewState for SavelistState {
         self.selected = (self.saves.len() - 1).min(self.selected + 1);
         future::ready(Ok(()))
     }
+    async fn left(&mut self) -> Result<(), anyhow::Error> {
+        self.cycle_rule(false).await
+    }
+    async fn right(&mut self) -> Result<(), anyhow::Error> {
+        self.cycle_rule(true).await
+    }
+    fn y(&mut self) -> impl Future<Output = Result<(), anyhow::Error>> + '_ {
+        self.editing = self.editing.next();
+        future::ready(Ok(()))
+    }
     async fn press(&mut self) -> Result<(), anyhow::Error> {
         if self.selected == 0 {
             self.cfg.modify_and_save_cfg(toggle_default).await??;
         } else {
-            let &(ref save, prev_enabled) = &self.saves[self.selected];
-            let save = PathBuf::from(save);
+            let row = &self.saves[self.selected];
+            let prev_enabled = row.enabled;
+            let save = PathBuf::from(&row.label);
             self.cfg
                 .modify_and_save_cfg(move |cfg: &mut Config| {
                     toggle_single(cfg, save, prev_enabled);
@@ -105,10 +307,12 @@ impl ViewState for SavelistState {
             .saves
             .iter()
             .enumerate()
-            .map(|(idx, (label, is_on))| {
+            .map(|(idx, row)| {
+                let editing = (self.selected == idx && row.rom.is_some()).then_some(self.editing);
+                let label = row.display_label(editing);
                 let num_chars = label.chars().count();
                 let label_trunc = if num_chars <= MAX_CHARACTERS_PER_BUTTON {
-                    Cow::Borrowed(label.as_str())
+                    Cow::Owned(label)
                 } else {
                     let to_skip = num_chars - MAX_CHARACTERS_PER_BUTTON;
                     let mapped = label
@@ -120,7 +324,7 @@ impl ViewState for SavelistState {
                         });
                     Cow::Owned(mapped)
                 };
-                labeled_checkbox(label_trunc, self.selected == idx, *is_on)
+                labeled_checkbox(label_trunc, self.selected == idx, row.enabled)
             })
             .skip(skip)
             .take(PER_SCREEN)
@@ -133,19 +337,36 @@ fn is_enabled(cfg: &Config, path: &Path) -> bool {
     if path.to_str() == Some("[default]") {
         return cfg.system.allow.is_none();
     }
-    if let Some(allow) = cfg.system.allow.as_ref() {
-        if !allow.iter().any(|needle| path.ends_with(needle)) {
-            return false;
-        }
+    if let Some(allow) = cfg.system.allow.as_ref()
+        && !allow.iter().any(|needle| pattern_matches(needle, path))
+    {
+        return false;
     }
-    if cfg.system.deny.iter().any(|needle| path.ends_with(needle)) {
+    if cfg
+        .system
+        .deny
+        .iter()
+        .any(|needle| pattern_matches(needle, path))
+    {
         return false;
     }
     cfg.system
         .saves
         .as_slice()
         .iter()
-        .any(|needle| needle.matches_path(path))
+        .any(|needle| needle.format.matches_path(path))
+}
+
+fn pattern_matches(pattern: &PathPattern, path: &Path) -> bool {
+    match pattern.as_path() {
+        Some(needle) => path.ends_with(needle),
+        None => pattern.matches(path),
+    }
+}
+
+/// Whether `pattern` is the plain path `save`, as added by [`toggle_single`].
+fn is_entry_for(pattern: &PathPattern, save: &Path) -> bool {
+    pattern.as_path().is_some_and(|pt| pt.ends_with(save))
 }
 
 // TODO: These are only lifted out of the `pressed` function because of
@@ -158,6 +379,7 @@ async fn toggle_default(cfg: &mut Config) -> Result<(), io::Error> {
         cfg.possible_saves()
             .map_ok(|data| data.0)
             .try_filter(|pt| future::ready(is_enabled(cfg, pt)))
+            .map_ok(PathPattern::from)
             .try_collect::<Vec<_>>()
             .map_ok(|res| (Some(res), Vec::new()))
             .await?
@@ -165,10 +387,18 @@ async fn toggle_default(cfg: &mut Config) -> Result<(), io::Error> {
         cfg.possible_saves()
             .map_ok(|data| data.0)
             .try_filter(|pt| future::ready(!is_enabled(cfg, pt)))
+            .try_filter(|pt| {
+                let mut patterns = cfg.system.deny.iter().filter(|pt| pt.as_path().is_none());
+                future::ready(!patterns.any(|needle| needle.matches(pt)))
+            })
+            .map_ok(PathPattern::from)
             .try_collect::<Vec<_>>()
             .map_ok(|res| (None, res))
             .await?
     };
+    // Hand-written globs & regexes in the denylist are kept either way.
+    let patterns = cfg.system.deny.iter().filter(|pt| pt.as_path().is_none());
+    let new_deny = patterns.cloned().chain(new_deny).collect();
     cfg.system.allow = new_allow;
     cfg.system.deny = new_deny;
     Result::<_, io::Error>::Ok(())
@@ -176,21 +406,26 @@ async fn toggle_default(cfg: &mut Config) -> Result<(), io::Error> {
 
 fn toggle_single(cfg: &mut Config, save: PathBuf, prev_enabled: bool) {
     if prev_enabled {
-        if let Some(allow) = cfg.system.allow.as_mut() {
-            if let Some(prev_idx) = allow.iter().position(|pt| pt.ends_with(&save)) {
-                allow.remove(prev_idx);
-            }
+        if let Some(allow) = cfg.system.allow.as_mut()
+            && let Some(prev_idx) = allow.iter().position(|pt| is_entry_for(pt, &save))
+        {
+            allow.remove(prev_idx);
         }
-        if !cfg.system.deny.iter().any(|pt| pt.ends_with(&save)) {
-            cfg.system.deny.push(save);
+        if !cfg.system.deny.iter().any(|pt| is_entry_for(pt, &save)) {
+            cfg.system.deny.push(save.into());
         }
     } else {
-        if let Some(allow) = cfg.system.allow.as_mut() {
-            if !allow.iter().any(|pt| pt.ends_with(&save)) {
-                allow.push(save.clone());
-            }
+        if let Some(allow) = cfg.system.allow.as_mut()
+            && !allow.iter().any(|pt| is_entry_for(pt, &save))
+        {
+            allow.push(save.clone().into());
         }
-        if let Some(prev_idx) = cfg.system.deny.iter().position(|pt| pt.ends_with(&save)) {
+        if let Some(prev_idx) = cfg
+            .system
+            .deny
+            .iter()
+            .position(|pt| is_entry_for(pt, &save))
+        {
             cfg.system.deny.remove(prev_idx);
         }
     }
@@ -1,13 +1,17 @@
 use std::io;
+use std::time::Duration;
 
 use interprocess::local_socket::tokio::Stream;
 use interprocess::local_socket::traits::tokio::Stream as _;
 use interprocess::local_socket::{GenericFilePath, ToFsName};
 use tokio::io::AsyncWriteExt;
 
-use syncer_model::commands::DaemonCommand;
+use syncer_model::commands::{DaemonCommand, DaemonResponse};
 use syncer_model::platforms::Platform;
 
+/// How long we wait for the daemon to answer a [`DaemonSocket::query`].
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
 #[derive(Clone, Debug)]
 pub struct DaemonSocket {
     _phantom: (),
@@ -18,14 +22,25 @@ impl DaemonSocket {
         Ok(Self { _phantom: () })
     }
     pub async fn send(&self, cmd: &DaemonCommand) -> io::Result<()> {
-        let mut inner = Stream::connect(
+        let mut inner = Self::connect().await?;
+        let payload = cmd.serialize();
+        inner.write_all(payload.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Sends a command that expects a reply and waits for the daemon's
+    /// response.
+    pub async fn query(&self, cmd: &DaemonCommand) -> io::Result<DaemonResponse> {
+        let mut inner = Self::connect().await?;
+        cmd.query(&mut inner, QUERY_TIMEOUT).await
+    }
+
+    async fn connect() -> io::Result<Stream> {
+        Stream::connect(
             Platform::get()
                 .socket_path()
                 .to_fs_name::<GenericFilePath>()?,
         )
-        .await?;
-        let payload = cmd.serialize();
-        inner.write_all(payload.as_bytes()).await?;
-        Ok(())
+        .await
     }
 }